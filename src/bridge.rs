@@ -0,0 +1,220 @@
+//! stdio-to-remote bridge.
+//!
+//! Exposes a remote server (Streamable HTTP, SSE or WebSocket) as a stdio MCP
+//! endpoint on our own stdin/stdout, forwarding JSON-RPC messages both ways.
+//! Auth headers are resolved from the manifest config by the transport.
+//! Tool policy (policy.json) is applied when one targets the server.
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::discovery::get_server;
+use crate::models::Transport;
+use crate::paths::Paths;
+use crate::policy::{Policy, PolicyError, PolicyFilter};
+use crate::protocol::{open_connection, Connection, ProtocolError};
+
+/// Longest a queued client message waits while the server thread is in a read.
+/// Connections only offer bounded reads, so the thread alternates between the two.
+const SEND_LATENCY: Duration = Duration::from_millis(20);
+/// How long to keep waiting for outstanding responses after the client closes stdin.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the relay loop reacts to, from the stdin and server threads.
+enum Event {
+    Client(Value),
+    /// A line from the client that isn't JSON.
+    ClientInvalid(String),
+    ClientClosed,
+    Server(Value),
+    /// A client message could not be sent.
+    SendFailed(Value, ProtocolError),
+    ServerFailed(ProtocolError),
+}
+
+/// Bridge stdin/stdout to an installed remote server until stdin closes or the server goes away.
+pub fn bridge(paths: &Paths, id: &str) -> Result<(), BridgeError> {
+    let (manifest, _) = get_server(paths, id).ok_or(BridgeError::ServerNotFound)?;
+    if let Some(Transport::Stdio { .. }) = manifest.transports.as_deref().unwrap_or_default().first() {
+        return Err(BridgeError::LocalServer);
+    }
+    let policy = Policy::load(paths).map_err(BridgeError::Policy)?;
+    let filter = policy.applies_to(id).then(|| PolicyFilter::new(policy, id));
+    let conn = open_connection(paths, id).map_err(BridgeError::Protocol)?;
+
+    let (events_tx, events) = mpsc::channel::<Event>();
+    let (outbound, outbound_rx) = mpsc::channel::<Value>();
+    spawn_stdin_reader(events_tx.clone());
+    let server = spawn_server_thread(conn, outbound_rx, events_tx);
+    let result = relay(filter, &events, outbound);
+    // `outbound` is gone, so the server thread stops and drops the connection (closing the session)
+    let _ = server.join();
+    result
+}
+
+/// Pass messages between client and server until stdin closes and the server has
+/// answered, or either side fails.
+fn relay(mut filter: Option<PolicyFilter>, events: &Receiver<Event>, outbound: Sender<Value>) -> Result<(), BridgeError> {
+    let mut stdout = std::io::stdout();
+    // Ids of client requests the server hasn't answered yet
+    let mut outstanding: HashSet<String> = HashSet::new();
+    let mut drain_deadline: Option<Instant> = None;
+    loop {
+        let event = match drain_deadline {
+            None => events.recv().map_err(|_| BridgeError::Protocol(ProtocolError::Closed))?,
+            Some(deadline) => {
+                if outstanding.is_empty() {
+                    return Ok(());
+                }
+                match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => return Ok(()),
+                    Err(RecvTimeoutError::Disconnected) => return Err(BridgeError::Protocol(ProtocolError::Closed)),
+                }
+            }
+        };
+        match event {
+            Event::Client(msg) => {
+                let msg = match filter.as_mut() {
+                    Some(f) => {
+                        let (forward, replies) = f.client_message(msg);
+                        for reply in &replies {
+                            write_message(&mut stdout, reply)?;
+                        }
+                        match forward {
+                            Some(m) => m,
+                            None => continue,
+                        }
+                    }
+                    None => msg,
+                };
+                if let (Some(id), true) = (msg.get("id"), msg.get("method").is_some()) {
+                    outstanding.insert(id.to_string());
+                }
+                if outbound.send(msg).is_err() {
+                    return Err(BridgeError::Protocol(ProtocolError::Closed));
+                }
+            }
+            Event::ClientInvalid(e) => {
+                // JSON-RPC: unparseable input gets a parse error with a null id
+                let reply = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32700, "message": format!("Parse error: {}", e)}
+                });
+                write_message(&mut stdout, &reply)?;
+            }
+            Event::ClientClosed => drain_deadline = Some(Instant::now() + DRAIN_TIMEOUT),
+            Event::Server(mut msg) => {
+                if let Some(f) = filter.as_mut() {
+                    f.server_message(&mut msg);
+                }
+                if let (Some(id), false) = (msg.get("id"), msg.get("method").is_some()) {
+                    outstanding.remove(&id.to_string());
+                }
+                write_message(&mut stdout, &msg)?;
+            }
+            Event::SendFailed(msg, e) => {
+                // Answer requests so the client isn't left waiting
+                if let (Some(id), true) = (msg.get("id"), msg.get("method").is_some()) {
+                    let reply = json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": -32603, "message": e.to_string()}
+                    });
+                    outstanding.remove(&id.to_string());
+                    write_message(&mut stdout, &reply)?;
+                } else {
+                    return Err(BridgeError::Protocol(e));
+                }
+            }
+            Event::ServerFailed(e) => return Err(BridgeError::Protocol(e)),
+        }
+    }
+}
+
+/// Read client messages from stdin, one JSON value per line.
+fn spawn_stdin_reader(events: Sender<Event>) {
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+        for line in stdin.lock().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = match serde_json::from_str::<Value>(&line) {
+                Ok(msg) => Event::Client(msg),
+                Err(e) => Event::ClientInvalid(e.to_string()),
+            };
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+        let _ = events.send(Event::ClientClosed);
+    });
+}
+
+/// Own the connection: send what the relay loop queues and report what the server sends.
+fn spawn_server_thread(
+    mut conn: Box<dyn Connection>,
+    outbound: Receiver<Value>,
+    events: Sender<Event>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || loop {
+        loop {
+            match outbound.try_recv() {
+                Ok(msg) => {
+                    if let Err(e) = conn.send(&msg) {
+                        if events.send(Event::SendFailed(msg, e)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        let event = match conn.recv_timeout(SEND_LATENCY) {
+            Ok(Some(msg)) => Event::Server(msg),
+            Ok(None) => continue,
+            Err(e) => Event::ServerFailed(e),
+        };
+        let failed = matches!(event, Event::ServerFailed(_));
+        if events.send(event).is_err() || failed {
+            return;
+        }
+    })
+}
+
+fn write_message(out: &mut impl Write, msg: &Value) -> Result<(), BridgeError> {
+    let line = serde_json::to_string(msg).map_err(|e| BridgeError::Protocol(ProtocolError::Serialize(e)))?;
+    writeln!(out, "{}", line).map_err(BridgeError::Io)?;
+    out.flush().map_err(BridgeError::Io)
+}
+
+#[derive(Debug)]
+pub enum BridgeError {
+    ServerNotFound,
+    LocalServer,
+    Policy(PolicyError),
+    Protocol(ProtocolError),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BridgeError::ServerNotFound => write!(f, "Server not found"),
+            BridgeError::LocalServer => write!(f, "Server is a local stdio server; use `dmcp run` instead"),
+            BridgeError::Policy(e) => write!(f, "{}", e),
+            BridgeError::Protocol(e) => write!(f, "{}", e),
+            BridgeError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BridgeError {}
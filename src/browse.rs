@@ -1,68 +1,66 @@
 //! Browse MCP servers from registry sources.
 
-use std::error::Error;
-use std::time::Duration;
+use std::collections::HashSet;
 
+use crate::discovery::list_servers;
+use crate::models::RegistryEntry;
 use crate::paths::Paths;
+use crate::registry::{load_registry, FetchMode, RegistryError};
 use crate::sources::list_sources;
 
-fn build_http_client() -> Result<reqwest::blocking::Client, reqwest::Error> {
-    reqwest::blocking::Client::builder()
-        .user_agent("dmcp/1.0")
-        .connect_timeout(Duration::from_secs(15))
-        .timeout(Duration::from_secs(30))
-        .build()
-}
-
-/// A server entry from a registry (for display).
+/// A registry entry together with the source URL it was listed in.
+/// Serialized as the entry plus a `registry` key, since the entry's own
+/// `source` key describes where to fetch the package from.
 #[derive(Debug, serde::Serialize)]
 pub struct RegistryServer {
-    pub id: String,
-    pub name: String,
-    pub summary: String,
-    pub version: String,
-    pub transport: String,
+    #[serde(flatten)]
+    pub entry: RegistryEntry,
+    #[serde(rename = "registry")]
     pub source: String,
 }
 
 /// Fetch and list servers from a specific registry URL.
-pub fn list_registry_servers_from_url(url: &str) -> Result<Vec<RegistryServer>, BrowseError> {
-    let client = build_http_client().map_err(BrowseError::HttpClient)?;
-    fetch_registry(&client, url).map_err(|e| BrowseError::FetchFailed {
-        url: url.to_string(),
-        cause: e,
-    })
+/// Malformed entries are returned as errors next to the valid ones.
+pub fn list_registry_servers_from_url(
+    paths: &Paths,
+    url: &str,
+    offline: bool,
+) -> Result<(Vec<RegistryServer>, Vec<BrowseError>), BrowseError> {
+    let (registry, invalid) = load_registry(paths, url, FetchMode::from_offline(offline)).map_err(BrowseError::Registry)?;
+    let servers = registry
+        .servers
+        .into_iter()
+        .map(|entry| RegistryServer {
+            entry,
+            source: url.to_string(),
+        })
+        .collect();
+    Ok((servers, invalid.into_iter().map(BrowseError::Registry).collect()))
 }
 
 /// Fetch and list all servers from configured registry sources.
-/// Returns (servers, errors). Servers may be duplicated across sources.
+/// Returns (servers, errors). An id listed by several sources is kept from the
+/// first one, so user sources win over system sources.
+/// With `offline`, only cached registries are used.
 pub fn list_registry_servers(
     paths: &Paths,
     include_user: bool,
     include_system: bool,
+    offline: bool,
 ) -> (Vec<RegistryServer>, Vec<BrowseError>) {
     let sources = list_sources(paths, include_user, include_system);
     let mut servers = Vec::new();
     let mut errors = Vec::new();
-
-    let client = match build_http_client() {
-        Ok(c) => c,
-        Err(e) => {
-            errors.push(BrowseError::HttpClient(e));
-            return (servers, errors);
-        }
-    };
+    let mut seen = HashSet::new();
 
     for (url, _scope) in sources {
-        match fetch_registry(&client, &url) {
-            Ok(registry_servers) => {
-                servers.extend(registry_servers);
+        match list_registry_servers_from_url(paths, &url, offline) {
+            Ok((registry_servers, invalid)) => {
+                servers.extend(registry_servers.into_iter().filter(|s| seen.insert(s.entry.id.clone())));
+                errors.extend(invalid);
             }
             Err(e) => {
-                errors.push(BrowseError::FetchFailed {
-                    url: url.clone(),
-                    cause: e,
-                });
+                errors.push(e);
             }
         }
     }
@@ -70,95 +68,321 @@ pub fn list_registry_servers(
     (servers, errors)
 }
 
-fn fetch_registry(
-    client: &reqwest::blocking::Client,
-    url: &str,
-) -> Result<Vec<RegistryServer>, reqwest::Error> {
-    let resp = client.get(url).send()?;
-    if !resp.status().is_success() {
-        return Err(resp.error_for_status().unwrap_err());
-    }
-    let registry: serde_json::Value = resp.json()?;
-    let servers_array = match registry.get("servers").and_then(|s| s.as_array()) {
-        Some(a) => a,
-        None => return Ok(vec![]),
-    };
+/// Find a server by id across configured sources (user sources first), keeping
+/// the source URL it came from. With `offline`, only cached registries are searched.
+pub fn find_registry_server(paths: &Paths, id: &str, offline: bool) -> Result<RegistryServer, BrowseError> {
+    let sources = list_sources(paths, true, true);
+    if sources.is_empty() {
+        return Err(BrowseError::NoSources);
+    }
 
-    let mut result = Vec::new();
-    for server in servers_array {
-        let id = server
-            .get("id")
-            .and_then(|i| i.as_str())
-            .unwrap_or("?")
-            .to_string();
-        let name = server
-            .get("name")
-            .and_then(|n| n.as_str())
-            .unwrap_or("?")
-            .to_string();
-        let summary = server
-            .get("summary")
-            .and_then(|s| s.as_str())
-            .unwrap_or("")
-            .to_string();
-        let version = server
-            .get("version")
-            .and_then(|v| v.as_str())
-            .unwrap_or("?")
-            .to_string();
-
-        let transport = server
-            .get("transports")
-            .and_then(|t| t.as_array())
-            .and_then(|a| a.first())
-            .and_then(|t| t.get("type").and_then(|x| x.as_str()))
-            .unwrap_or("?")
-            .to_string();
-
-        result.push(RegistryServer {
-            id,
-            name,
-            summary,
-            version,
-            transport,
-            source: url.to_string(),
-        });
+    // An unreachable source only matters if no other source has the server
+    let mut first_error = None;
+    for (url, _) in sources {
+        let (registry, invalid) = match load_registry(paths, &url, FetchMode::from_offline(offline)) {
+            Ok(r) => r,
+            Err(e) => {
+                first_error.get_or_insert(e);
+                continue;
+            }
+        };
+        if let Some(entry) = registry.servers.into_iter().find(|s| s.id == id) {
+            return Ok(RegistryServer { entry, source: url });
+        }
+        // The entry exists but is malformed: report why rather than "not found"
+        if let Some(e) = invalid
+            .into_iter()
+            .find(|e| matches!(e, RegistryError::InvalidEntry { id: Some(i), .. } if i == id))
+        {
+            return Err(BrowseError::Registry(e));
+        }
     }
 
-    Ok(result)
+    match first_error {
+        Some(e) => Err(BrowseError::Registry(e)),
+        None => Err(BrowseError::NotFound(id.to_string())),
+    }
+}
+
+/// Criteria for narrowing a server listing. Unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct BrowseFilter {
+    /// Case-insensitive text matched against id, name, summary and description.
+    pub search: Option<String>,
+    /// Exact category, e.g. `mcp-database`.
+    pub category: Option<String>,
+    /// Transport type offered by any of the server's transports.
+    pub transport: Option<String>,
+    /// Case-insensitive substring of the author.
+    pub author: Option<String>,
+    /// `Some(true)` keeps installed servers only, `Some(false)` the others.
+    pub installed: Option<bool>,
+}
+
+impl BrowseFilter {
+    pub fn is_empty(&self) -> bool {
+        self.search.is_none()
+            && self.category.is_none()
+            && self.transport.is_none()
+            && self.author.is_none()
+            && self.installed.is_none()
+    }
+
+    /// Whether `entry` passes every criterion. `installed` holds the ids of installed servers.
+    pub fn matches(&self, entry: &RegistryEntry, installed: &HashSet<String>) -> bool {
+        let contains = |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+        if let Some(q) = &self.search {
+            let fields = [Some(&entry.id), Some(&entry.name), Some(&entry.summary), entry.description.as_ref()];
+            if !fields.into_iter().flatten().any(|f| contains(f, q)) {
+                return false;
+            }
+        }
+        if let Some(c) = &self.category {
+            if !entry.categories.iter().any(|ec| ec.eq_ignore_ascii_case(c)) {
+                return false;
+            }
+        }
+        if let Some(t) = &self.transport {
+            if !entry.transports.iter().any(|et| et.type_name() == t) {
+                return false;
+            }
+        }
+        if let Some(a) = &self.author {
+            if !entry.author.as_deref().is_some_and(|ea| contains(ea, a)) {
+                return false;
+            }
+        }
+        if let Some(want) = self.installed {
+            if installed.contains(&entry.id) != want {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Keep the servers matching `filter`. Installed state covers both scopes.
+pub fn filter_registry_servers(paths: &Paths, servers: Vec<RegistryServer>, filter: &BrowseFilter) -> Vec<RegistryServer> {
+    if filter.is_empty() {
+        return servers;
+    }
+    let installed: HashSet<String> = match filter.installed {
+        Some(_) => list_servers(paths, true, true, false).into_iter().map(|s| s.id).collect(),
+        None => HashSet::new(),
+    };
+    servers.into_iter().filter(|s| filter.matches(&s.entry, &installed)).collect()
 }
 
 #[derive(Debug)]
 pub enum BrowseError {
-    HttpClient(reqwest::Error),
-    FetchFailed {
-        url: String,
-        cause: reqwest::Error,
-    },
+    NoSources,
+    NotFound(String),
+    Registry(RegistryError),
 }
 
 impl std::fmt::Display for BrowseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            BrowseError::HttpClient(e) => {
-                write!(f, "HTTP client error: {}", e)?;
-                if let Some(s) = e.source() {
-                    write!(f, "\n  Caused by: {}", s)?;
-                }
-                Ok(())
-            }
-            BrowseError::FetchFailed { url, cause } => {
-                write!(f, "Failed to fetch {}: {}", url, cause)?;
-                // Show error chain for more diagnostic detail
-                let mut source: Option<&(dyn Error + '_)> = cause.source();
-                while let Some(s) = source {
-                    write!(f, "\n  Caused by: {}", s)?;
-                    source = s.source();
-                }
-                Ok(())
-            }
+            BrowseError::NoSources => write!(f, "No registry sources configured"),
+            BrowseError::NotFound(id) => write!(f, "Server not found in any registry: {}", id),
+            BrowseError::Registry(e) => write!(f, "{}", e),
         }
     }
 }
 
 impl std::error::Error for BrowseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{paths, respond, serve};
+    use serde_json::{json, Value};
+
+    /// Notes server by Jane Doe in `mcp-productivity`, over stdio, with `fields` laid over it.
+    fn notes(fields: Value) -> RegistryEntry {
+        let mut value = json!({
+            "id": "com.example.notes",
+            "name": "Notes",
+            "version": "1.0.0",
+            "summary": "Keep plain-text notes",
+            "description": "Search and edit a folder of Markdown files.",
+            "author": "Jane Doe",
+            "categories": ["mcp-productivity"],
+            "transports": [{"type": "stdio", "command": "notes-server"}]
+        });
+        value.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        RegistryEntry::from_value(value).unwrap()
+    }
+
+    fn matches(filter: BrowseFilter, entry: &RegistryEntry) -> bool {
+        filter.matches(entry, &HashSet::new())
+    }
+
+    fn search(q: &str) -> BrowseFilter {
+        BrowseFilter {
+            search: Some(q.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(BrowseFilter::default().is_empty());
+        assert!(matches(BrowseFilter::default(), &notes(json!({}))));
+    }
+
+    #[test]
+    fn search_looks_at_id_name_summary_and_description() {
+        let entry = notes(json!({}));
+        for q in ["example.notes", "NOTES", "plain-text", "markdown"] {
+            assert!(matches(search(q), &entry), "{}", q);
+        }
+        assert!(!matches(search("jane"), &entry));
+        assert!(!matches(search("markdown"), &notes(json!({"description": null}))));
+    }
+
+    #[test]
+    fn category_must_be_listed() {
+        let filter = |c: &str| BrowseFilter {
+            category: Some(c.to_string()),
+            ..Default::default()
+        };
+        assert!(matches(filter("mcp-productivity"), &notes(json!({}))));
+        assert!(matches(filter("MCP-Productivity"), &notes(json!({}))));
+        assert!(!matches(filter("mcp-prod"), &notes(json!({}))));
+        assert!(!matches(filter("mcp-productivity"), &notes(json!({"categories": []}))));
+    }
+
+    #[test]
+    fn transport_matches_any_offered() {
+        let filter = |t: &str| BrowseFilter {
+            transport: Some(t.to_string()),
+            ..Default::default()
+        };
+        let both = notes(json!({"transports": [
+            {"type": "stdio", "command": "notes-server"},
+            {"type": "streamable_http", "url": "https://notes.example.com/mcp"}
+        ]}));
+        assert!(matches(filter("stdio"), &both));
+        assert!(matches(filter("streamable_http"), &both));
+        assert!(!matches(filter("sse"), &both));
+        assert!(!matches(filter("streamable_http"), &notes(json!({}))));
+    }
+
+    #[test]
+    fn author_is_a_case_insensitive_substring() {
+        let filter = |a: &str| BrowseFilter {
+            author: Some(a.to_string()),
+            ..Default::default()
+        };
+        assert!(matches(filter("jane"), &notes(json!({}))));
+        assert!(matches(filter("DOE"), &notes(json!({}))));
+        assert!(!matches(filter("john"), &notes(json!({}))));
+        assert!(!matches(filter("jane"), &notes(json!({"author": null}))));
+    }
+
+    #[test]
+    fn installed_keeps_one_side() {
+        let filter = |i: bool| BrowseFilter {
+            installed: Some(i),
+            ..Default::default()
+        };
+        let installed = HashSet::from(["com.example.notes".to_string()]);
+        let entry = notes(json!({}));
+        let other = notes(json!({"id": "com.example.other"}));
+        assert!(filter(true).matches(&entry, &installed));
+        assert!(!filter(true).matches(&other, &installed));
+        assert!(!filter(false).matches(&entry, &installed));
+        assert!(filter(false).matches(&other, &installed));
+    }
+
+    #[test]
+    fn every_criterion_must_match() {
+        let filter = BrowseFilter {
+            search: Some("notes".to_string()),
+            author: Some("john".to_string()),
+            ..Default::default()
+        };
+        assert!(!matches(filter, &notes(json!({}))));
+    }
+
+    /// Serve a registry holding `servers`; returns its URL.
+    fn registry(servers: Value) -> String {
+        let body = json!({"servers": servers}).to_string();
+        let base = serve(move |_, stream| respond(stream, "200 OK", &[], &body));
+        format!("{}/registry.json", base)
+    }
+
+    fn write_sources(path: &std::path::Path, urls: &[&str]) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, urls.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn user_sources_win_duplicate_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let entry = |id: &str, version: &str| json!({"id": id, "name": id, "version": version, "transports": [{"type": "stdio", "command": "x"}]});
+        let user = registry(json!([entry("com.example.notes", "2.0.0"), entry("com.example.user", "1.0.0")]));
+        let system = registry(json!([entry("com.example.system", "1.0.0"), entry("com.example.notes", "1.0.0")]));
+        write_sources(paths.user_sources_path(), &[&user]);
+        write_sources(paths.system_sources_path(), &[&system]);
+
+        let (servers, errors) = list_registry_servers(&paths, true, true, false);
+        assert!(errors.is_empty());
+        let listed: Vec<(&str, &str, &str)> = servers
+            .iter()
+            .map(|s| (s.entry.id.as_str(), s.entry.version.as_str(), s.source.as_str()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("com.example.notes", "2.0.0", user.as_str()),
+                ("com.example.user", "1.0.0", user.as_str()),
+                ("com.example.system", "1.0.0", system.as_str()),
+            ]
+        );
+    }
+
+    #[test]
+    fn registry_url_does_not_clash_with_package_source() {
+        let entry = RegistryEntry::from_value(json!({
+            "id": "com.example.files",
+            "name": "Files",
+            "version": "1.0.0",
+            "transports": [{"type": "stdio", "command": "node", "args": ["index.js"]}],
+            "source": {"type": "git", "url": "https://git.example.com/files.git"}
+        }))
+        .unwrap();
+        let server = RegistryServer {
+            entry,
+            source: "https://registry.example.com/registry.json".to_string(),
+        };
+        let out = serde_json::to_string(&server).unwrap();
+        assert_eq!(out.matches("\"source\"").count(), 1);
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["registry"], "https://registry.example.com/registry.json");
+        assert_eq!(value["source"]["url"], "https://git.example.com/files.git");
+        assert!(value["transports"].is_array());
+    }
+
+    #[test]
+    fn find_reports_the_source_listing_the_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let entry = |id: &str| json!({"id": id, "name": id, "version": "1.0.0", "transports": [{"type": "stdio", "command": "x"}]});
+        let first = registry(json!([entry("com.example.notes")]));
+        let second = registry(json!([entry("com.example.files"), entry("com.example.notes")]));
+        write_sources(paths.user_sources_path(), &[&first]);
+        write_sources(paths.system_sources_path(), &[&second]);
+
+        let found = find_registry_server(&paths, "com.example.files", false).unwrap();
+        assert_eq!(found.source, second);
+        let found = find_registry_server(&paths, "com.example.notes", false).unwrap();
+        assert_eq!(found.source, first);
+        assert!(matches!(
+            find_registry_server(&paths, "com.example.missing", false),
+            Err(BrowseError::NotFound(_))
+        ));
+    }
+}
@@ -1,16 +1,393 @@
 //! Server configuration (get/set).
+//!
+//! Values are checked against the manifest's `configurableProperties` and
+//! stored as typed JSON (see [`parse_value`]).
+//!
+//! A server's effective config is layered, later layers winning: property
+//! defaults, the manifest `config` (set by whoever installed it), the calling
+//! user's overlay in `<user config dir>/<id>.json`, and the user's secret store.
 
-use crate::discovery::get_manifest_path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::discovery::{get_manifest_path, get_server};
+use crate::elevation::is_system_scope;
+use crate::models::{ConfigurableProperty, Manifest};
 use crate::paths::Paths;
+use crate::secrets::{self, SecretsError};
+
+/// Config values with `configurableProperties` defaults applied for unset keys.
+pub fn resolve_config(manifest: &Manifest) -> HashMap<String, serde_json::Value> {
+    let mut config = manifest.config.clone();
+    for prop in &manifest.configurable_properties {
+        if let Some(default) = &prop.default {
+            config.entry(prop.key.clone()).or_insert_with(|| default.clone());
+        }
+    }
+    config
+}
+
+/// Merged config for server `id`: defaults, manifest config, the user's overlay
+/// and their secrets (decrypted, so this may ask for the store passphrase).
+pub fn get_config(paths: &Paths, id: &str) -> Result<HashMap<String, Value>, ConfigError> {
+    let (mut manifest, _) = get_server(paths, id).ok_or(ConfigError::ServerNotFound)?;
+    apply_user_config(paths, id, &mut manifest, true)?;
+    Ok(resolve_config(&manifest))
+}
+
+/// Merge the calling user's overlay and secrets into `manifest.config`. Without
+/// `reveal_secrets`, stored secrets appear as [`secrets::MASK`] and no passphrase is needed.
+pub fn apply_user_config(paths: &Paths, id: &str, manifest: &mut Manifest, reveal_secrets: bool) -> Result<(), ConfigError> {
+    manifest.config.extend(read_user_config(paths, id).map_err(ConfigError::UserConfig)?);
+    if reveal_secrets {
+        if !secrets::keys(paths, id).map_err(ConfigError::Secrets)?.is_empty() {
+            manifest.config.extend(secrets::load(paths, id).map_err(ConfigError::Secrets)?);
+        }
+    } else {
+        for key in secrets::keys(paths, id).map_err(ConfigError::Secrets)? {
+            manifest.config.insert(key, Value::String(secrets::MASK.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// The calling user's overlay for server `id` (empty if they have none).
+pub fn read_user_config(paths: &Paths, id: &str) -> Result<Map<String, Value>, ConfigFileError> {
+    let path = user_config_path(paths, id);
+    if !path.exists() {
+        return Ok(Map::new());
+    }
+    read_config_file(&path)
+}
+
+/// Replace the calling user's overlay for `id`; an empty overlay removes the file.
+fn write_user_config(paths: &Paths, id: &str, config: &Map<String, Value>) -> Result<(), SetConfigError> {
+    let path = user_config_path(paths, id);
+    if config.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SetConfigError::WriteFailed(e, path)),
+            _ => Ok(()),
+        };
+    }
+    std::fs::create_dir_all(paths.user_config_dir()).map_err(|e| SetConfigError::WriteFailed(e, path.clone()))?;
+    let output = serde_json::to_string_pretty(config).map_err(SetConfigError::SerializeFailed)?;
+    std::fs::write(&path, output).map_err(|e| SetConfigError::WriteFailed(e, path))
+}
+
+fn user_config_path(paths: &Paths, id: &str) -> PathBuf {
+    paths.user_config_dir().join(format!("{}.json", id))
+}
+
+/// Delete the calling user's overlay for `id`.
+pub fn delete_user_config(paths: &Paths, id: &str) -> std::io::Result<()> {
+    match std::fs::remove_file(user_config_path(paths, id)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Required properties without a value in `config` (absent, null or empty) and without a default.
+pub fn missing_required<'a>(props: &'a [ConfigurableProperty], config: &Map<String, Value>) -> Vec<&'a ConfigurableProperty> {
+    props
+        .iter()
+        .filter(|p| p.required && p.default.is_none() && is_unset(config.get(&p.key)))
+        .collect()
+}
+
+/// Fill unset keys in `config` with their property `default`.
+pub fn apply_defaults(props: &[ConfigurableProperty], config: &mut Map<String, Value>) {
+    for prop in props {
+        if let Some(default) = &prop.default {
+            if is_unset(config.get(&prop.key)) {
+                config.insert(prop.key.clone(), default.clone());
+            }
+        }
+    }
+}
+
+fn is_unset(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => true,
+        Some(Value::String(s)) => s.is_empty(),
+        Some(_) => false,
+    }
+}
+
+/// Read config values from a file holding a JSON object.
+pub fn read_config_file(path: &Path) -> Result<Map<String, Value>, ConfigFileError> {
+    let content = std::fs::read_to_string(path).map_err(|e| ConfigFileError::Read(path.to_path_buf(), e))?;
+    match serde_json::from_str(&content) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err(ConfigFileError::NotObject(path.to_path_buf())),
+        Err(e) => Err(ConfigFileError::Parse(path.to_path_buf(), e)),
+    }
+}
+
+/// Replace `${config.key}` placeholders with config values. Declared properties
+/// without a value expand to ""; a key that is neither set nor declared is an
+/// error, since it is almost always a typo in the manifest.
+pub fn interpolate(
+    template: &str,
+    config: &HashMap<String, serde_json::Value>,
+    props: &[ConfigurableProperty],
+) -> Result<String, UnknownPlaceholder> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("${config.") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + "${config.".len()..];
+        match after.find('}') {
+            Some(end) => {
+                let key = &after[..end];
+                match config.get(key) {
+                    Some(v) => out.push_str(&value_to_string(v)),
+                    None if props.iter().any(|p| p.key == key) => {}
+                    None => return Err(UnknownPlaceholder { key: key.to_string() }),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                // Unterminated placeholder: keep it literally
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Display form of a config value: strings unquoted, everything else as JSON.
+pub fn value_to_string(v: &serde_json::Value) -> String {
+    v.as_str().map(String::from).unwrap_or_else(|| v.to_string())
+}
+
+/// Parse `raw` as the property's `type` and check it against `enum`, `pattern`
+/// and `min`/`max`. Returns the value as it should be stored; paths are stored
+/// with `~` expanded.
+pub fn parse_value(prop: &ConfigurableProperty, raw: &str) -> Result<Value, ConfigValueError> {
+    let invalid = |expected: &'static str| ConfigValueError::Type {
+        key: prop.key.clone(),
+        expected,
+        value: raw.to_string(),
+    };
+    if prop.enum_without_values() {
+        return Err(ConfigValueError::BadDefinition {
+            key: prop.key.clone(),
+            reason: "type enum without an `enum` list",
+        });
+    }
+    let mut value = match prop.kind.as_deref().unwrap_or("string") {
+        "integer" => raw.trim().parse::<i64>().map(Value::from).map_err(|_| invalid("an integer"))?,
+        "boolean" => match raw.trim().to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Value::Bool(true),
+            "false" | "no" | "off" | "0" => Value::Bool(false),
+            _ => return Err(invalid("true or false")),
+        },
+        "url" => {
+            reqwest::Url::parse(raw.trim()).map_err(|_| invalid("a URL"))?;
+            Value::String(raw.trim().to_string())
+        }
+        "path" => {
+            let expanded = shellexpand::tilde(raw.trim()).into_owned();
+            if !Path::new(&expanded).is_absolute() || expanded.contains('\0') {
+                return Err(invalid("an absolute path or ~/path"));
+            }
+            Value::String(expanded)
+        }
+        // string, enum; unknown types are kept as strings
+        _ => Value::String(raw.to_string()),
+    };
+
+    if !prop.allowed.is_empty() {
+        // Store the declared value, so `enum: [1, 2]` keeps numbers
+        value = prop
+            .allowed
+            .iter()
+            .find(|a| value_to_string(a) == value_to_string(&value))
+            .cloned()
+            .ok_or_else(|| ConfigValueError::NotAllowed {
+                key: prop.key.clone(),
+                allowed: prop.allowed.iter().map(value_to_string).collect(),
+            })?;
+    }
+    if let Some(pattern) = &prop.pattern {
+        let re = Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| ConfigValueError::BadPattern {
+            key: prop.key.clone(),
+            error: e.to_string(),
+        })?;
+        if !re.is_match(&value_to_string(&value)) {
+            return Err(ConfigValueError::Pattern {
+                key: prop.key.clone(),
+                pattern: pattern.clone(),
+            });
+        }
+    }
+
+    let (measure, unit) = match &value {
+        Value::Number(n) => (n.as_f64(), ""),
+        Value::String(s) => (Some(s.chars().count() as f64), " characters"),
+        _ => (None, ""),
+    };
+    if let Some(m) = measure {
+        if let Some(min) = prop.min.filter(|min| m < *min) {
+            return Err(ConfigValueError::TooSmall { key: prop.key.clone(), min, unit });
+        }
+        if let Some(max) = prop.max.filter(|max| m > *max) {
+            return Err(ConfigValueError::TooLarge { key: prop.key.clone(), max, unit });
+        }
+    }
+    Ok(value)
+}
 
 /// Set a config value for a server. Persists to manifest.json.
 /// Uses raw JSON to preserve all manifest fields.
-pub fn set_config_value(paths: &Paths, id: &str, key: &str, value: &str) -> Result<(), SetConfigError> {
+///
+/// The value is validated against the key's `configurableProperties` entry; keys
+/// the server doesn't declare are rejected unless `force`, and stored as strings.
+/// `sensitive` values go to the secret store instead, and any plain-text copy is
+/// removed from the manifest. Returns the stored value.
+///
+/// For system-scope servers the value goes to the calling user's overlay, so no
+/// elevation is needed; `system_wide` writes the shared manifest instead.
+pub fn set_config_value(
+    paths: &Paths,
+    id: &str,
+    key: &str,
+    value: &str,
+    force: bool,
+    system_wide: bool,
+) -> Result<Value, SetConfigError> {
+    let mut values = Map::new();
+    values.insert(key.to_string(), Value::String(value.to_string()));
+    let mut stored = set_config_values(paths, id, values, force, system_wide)?;
+    Ok(stored.remove(key).unwrap_or_default())
+}
+
+/// Set several config values at once, as [`set_config_value`] does. Every value is
+/// validated before anything is written. Non-string values are checked through
+/// their display form, so `8080` and `"8080"` are both accepted for an integer.
+pub fn set_config_values(
+    paths: &Paths,
+    id: &str,
+    values: Map<String, Value>,
+    force: bool,
+    system_wide: bool,
+) -> Result<Map<String, Value>, SetConfigError> {
+    change_config_values(paths, id, values, &[], force, system_wide).map(|(stored, _)| stored)
+}
+
+/// Remove config values so they fall back to the next layer down (for a user's
+/// overlay, the shared manifest value; otherwise the property default). Stored
+/// secrets are removed too. Returns the keys that were actually set.
+pub fn unset_config_values(paths: &Paths, id: &str, keys: &[String], system_wide: bool) -> Result<Vec<String>, SetConfigError> {
+    change_config_values(paths, id, Map::new(), keys, false, system_wide).map(|(_, removed)| removed)
+}
+
+/// Lay install-time `values` (from `--config-file` and `-c`) over `config`, checked
+/// as [`set_config_values`] checks them: values are parsed against their property and
+/// stored typed, and keys the server doesn't declare are rejected unless `force`.
+/// Nothing is merged if any value is rejected.
+pub fn merge_config_values(
+    props: &[ConfigurableProperty],
+    config: &mut Map<String, Value>,
+    values: Map<String, Value>,
+    force: bool,
+) -> Result<(), SetConfigError> {
+    config.extend(validate_values(props, values, force)?);
+    Ok(())
+}
+
+/// Parse each value against its property. Undeclared keys are kept as given with `force`.
+fn validate_values(props: &[ConfigurableProperty], values: Map<String, Value>, force: bool) -> Result<Map<String, Value>, SetConfigError> {
+    let mut checked = Map::new();
+    for (key, raw) in values {
+        let value = match props.iter().find(|p| p.key == key) {
+            Some(prop) => parse_value(prop, &value_to_string(&raw)).map_err(SetConfigError::Invalid)?,
+            None if force => raw,
+            None => {
+                return Err(SetConfigError::UnknownKey {
+                    key,
+                    known: props.iter().map(|p| p.key.clone()).collect(),
+                })
+            }
+        };
+        checked.insert(key, value);
+    }
+    Ok(checked)
+}
+
+/// Set `values` and unset `keys` in one change, as [`set_config_values`] and
+/// [`unset_config_values`] do: every value is validated before anything is
+/// written, and the config is written once. Returns the stored values and the
+/// keys that were actually removed.
+pub fn change_config_values(
+    paths: &Paths,
+    id: &str,
+    values: Map<String, Value>,
+    keys: &[String],
+    force: bool,
+    system_wide: bool,
+) -> Result<(Map<String, Value>, Vec<String>), SetConfigError> {
+    update_config(paths, id, system_wide, |props, config| {
+        let stored = validate_values(props, values, force)?;
+
+        let mut removed = Vec::new();
+        for key in keys {
+            let was_secret = secrets::remove(paths, id, key).map_err(SetConfigError::Secrets)?;
+            if config.remove(key).is_some() || was_secret {
+                removed.push(key.clone());
+            }
+        }
+
+        let mut secret = Map::new();
+        for (key, value) in &stored {
+            if props.iter().any(|p| p.key == *key && p.sensitive) {
+                secret.insert(key.clone(), value.clone());
+                config.remove(key);
+            } else {
+                config.insert(key.clone(), value.clone());
+            }
+        }
+        if !secret.is_empty() {
+            secrets::set(paths, id, secret).map_err(SetConfigError::Secrets)?;
+        }
+        Ok((stored, removed))
+    })
+}
+
+/// Apply `change` to the config object changes for `id` are written to: the
+/// calling user's overlay for system-scope servers (unless `system_wide`), the
+/// manifest `config` otherwise. Nothing is written if `change` leaves it as it was.
+fn update_config<T>(
+    paths: &Paths,
+    id: &str,
+    system_wide: bool,
+    change: impl FnOnce(&[ConfigurableProperty], &mut Map<String, Value>) -> Result<T, SetConfigError>,
+) -> Result<T, SetConfigError> {
     let manifest_path = get_manifest_path(paths, id).ok_or(SetConfigError::ServerNotFound)?;
 
     let content = std::fs::read_to_string(&manifest_path).map_err(SetConfigError::ReadFailed)?;
     let mut manifest: serde_json::Value = serde_json::from_str(&content).map_err(SetConfigError::ParseFailed)?;
 
+    let props: Vec<ConfigurableProperty> = manifest
+        .get("configurableProperties")
+        .and_then(|p| serde_json::from_value(p.clone()).ok())
+        .unwrap_or_default();
+
+    if is_system_scope(&manifest_path, paths.system_install_dir()) && !system_wide {
+        let mut config = read_user_config(paths, id).map_err(SetConfigError::UserConfig)?;
+        let before = config.clone();
+        let result = change(&props, &mut config)?;
+        if config != before {
+            write_user_config(paths, id, &config)?;
+        }
+        return Ok(result);
+    }
+
     // Ensure config object exists
     if manifest.get("config").is_none() {
         manifest["config"] = serde_json::json!({});
@@ -21,18 +398,170 @@ pub fn set_config_value(paths: &Paths, id: &str, key: &str, value: &str) -> Resu
         .and_then(|c| c.as_object_mut())
         .ok_or(SetConfigError::InvalidManifest)?;
 
-    config.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+    let before = config.clone();
+    let result = change(&props, config)?;
+    if *config == before {
+        return Ok(result);
+    }
 
     let output = serde_json::to_string_pretty(&manifest).map_err(SetConfigError::SerializeFailed)?;
     std::fs::write(&manifest_path, output).map_err(|e| SetConfigError::WriteFailed(e, manifest_path.clone()))?;
 
-    Ok(())
+    Ok(result)
+}
+
+/// The values explicitly set for server `id` (manifest config, the user's overlay
+/// and, if `include_secrets`, their secrets), without defaults. For moving a
+/// configured server to another machine.
+pub fn export_config(paths: &Paths, id: &str, include_secrets: bool) -> Result<Map<String, Value>, ConfigError> {
+    let (mut manifest, _) = get_server(paths, id).ok_or(ConfigError::ServerNotFound)?;
+    if include_secrets {
+        apply_user_config(paths, id, &mut manifest, true)?;
+    } else {
+        manifest.config.extend(read_user_config(paths, id).map_err(ConfigError::UserConfig)?);
+        for key in secrets::keys(paths, id).map_err(ConfigError::Secrets)? {
+            manifest.config.remove(&key);
+        }
+        for prop in manifest.configurable_properties.iter().filter(|p| p.sensitive) {
+            manifest.config.remove(&prop.key);
+        }
+    }
+    Ok(manifest.config.into_iter().collect())
 }
 
+/// Format config as dotenv lines (`key=value`). Values with spaces or shell
+/// characters are double-quoted, with backslashes, quotes, newlines and `$` escaped.
+pub fn to_dotenv(config: &Map<String, Value>) -> String {
+    let mut out = String::new();
+    for (key, value) in config {
+        let value = value_to_string(value);
+        let plain = !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:@,+%".contains(c));
+        if plain {
+            out.push_str(&format!("{}={}\n", key, value));
+        } else {
+            let escaped = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+                .replace('$', "\\$");
+            out.push_str(&format!("{}=\"{}\"\n", key, escaped));
+        }
+    }
+    out
+}
+
+/// Parse dotenv content: `key=value` lines, optionally prefixed with `export`,
+/// with single- or double-quoted values. Blank lines and `#` comments are skipped.
+/// Values are returned as strings. On error, returns the 1-based line number.
+pub fn parse_dotenv(content: &str) -> Result<Map<String, Value>, usize> {
+    let mut config = Map::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, raw) = line.split_once('=').ok_or(n + 1)?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(n + 1);
+        }
+        let raw = raw.trim();
+        let value = if let Some(inner) = raw.strip_prefix('"') {
+            let inner = inner.strip_suffix('"').ok_or(n + 1)?;
+            let mut value = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    value.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some(other) => value.push(other),
+                    None => value.push('\\'),
+                }
+            }
+            value
+        } else if let Some(inner) = raw.strip_prefix('\'') {
+            inner.strip_suffix('\'').ok_or(n + 1)?.to_string()
+        } else {
+            // Unquoted: drop a trailing ` # comment`
+            raw.split(" #").next().unwrap_or_default().trim_end().to_string()
+        };
+        config.insert(key.to_string(), Value::String(value));
+    }
+    Ok(config)
+}
+
+#[derive(Debug)]
+pub enum ConfigValueError {
+    Type {
+        key: String,
+        expected: &'static str,
+        value: String,
+    },
+    NotAllowed {
+        key: String,
+        allowed: Vec<String>,
+    },
+    Pattern {
+        key: String,
+        pattern: String,
+    },
+    /// The property's `pattern` is not a valid regular expression.
+    BadPattern {
+        key: String,
+        error: String,
+    },
+    /// The property itself is declared wrongly.
+    BadDefinition {
+        key: String,
+        reason: &'static str,
+    },
+    TooSmall {
+        key: String,
+        min: f64,
+        unit: &'static str,
+    },
+    TooLarge {
+        key: String,
+        max: f64,
+        unit: &'static str,
+    },
+}
+
+impl std::fmt::Display for ConfigValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigValueError::Type { key, expected, value } => {
+                write!(f, "Invalid value for {}: expected {}, got \"{}\"", key, expected, value)
+            }
+            ConfigValueError::NotAllowed { key, allowed } => {
+                write!(f, "Invalid value for {}: must be one of {}", key, allowed.join(", "))
+            }
+            ConfigValueError::Pattern { key, pattern } => write!(f, "Invalid value for {}: must match {}", key, pattern),
+            ConfigValueError::BadPattern { key, error } => write!(f, "Property {} has an invalid pattern: {}", key, error),
+            ConfigValueError::BadDefinition { key, reason } => write!(f, "Property {} is invalid: {}", key, reason),
+            ConfigValueError::TooSmall { key, min, unit } => write!(f, "Invalid value for {}: must be at least {}{}", key, min, unit),
+            ConfigValueError::TooLarge { key, max, unit } => write!(f, "Invalid value for {}: must be at most {}{}", key, max, unit),
+        }
+    }
+}
+
+impl std::error::Error for ConfigValueError {}
+
 #[derive(Debug)]
 pub enum SetConfigError {
     ServerNotFound,
     InvalidManifest,
+    UnknownKey { key: String, known: Vec<String> },
+    Invalid(ConfigValueError),
+    Secrets(SecretsError),
+    UserConfig(ConfigFileError),
     ReadFailed(std::io::Error),
     ParseFailed(serde_json::Error),
     SerializeFailed(serde_json::Error),
@@ -44,12 +573,233 @@ impl std::fmt::Display for SetConfigError {
         match self {
             SetConfigError::ServerNotFound => write!(f, "Server not found"),
             SetConfigError::InvalidManifest => write!(f, "Manifest has no config object"),
+            SetConfigError::UnknownKey { key, known } if known.is_empty() => {
+                write!(f, "Unknown config key {} (the server declares none); use --force to set it anyway", key)
+            }
+            SetConfigError::UnknownKey { key, known } => write!(
+                f,
+                "Unknown config key {} (expected one of {}); use --force to set it anyway",
+                key,
+                known.join(", ")
+            ),
+            SetConfigError::Invalid(e) => write!(f, "{}", e),
+            SetConfigError::Secrets(e) => write!(f, "{}", e),
+            SetConfigError::UserConfig(e) => write!(f, "{}", e),
             SetConfigError::ReadFailed(e) => write!(f, "Failed to read manifest: {}", e),
             SetConfigError::ParseFailed(e) => write!(f, "Failed to parse manifest: {}", e),
             SetConfigError::SerializeFailed(e) => write!(f, "Failed to serialize manifest: {}", e),
-            SetConfigError::WriteFailed(e, _) => write!(f, "Failed to write manifest: {}", e),
+            SetConfigError::WriteFailed(e, p) => write!(f, "Failed to write {}: {}", p.display(), e),
         }
     }
 }
 
 impl std::error::Error for SetConfigError {}
+
+/// A `${config.key}` placeholder naming a key that is neither set nor declared.
+#[derive(Debug)]
+pub struct UnknownPlaceholder {
+    pub key: String,
+}
+
+impl std::fmt::Display for UnknownPlaceholder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown config key in placeholder ${{config.{}}}", self.key)
+    }
+}
+
+impl std::error::Error for UnknownPlaceholder {}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    ServerNotFound,
+    UserConfig(ConfigFileError),
+    Secrets(SecretsError),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::ServerNotFound => write!(f, "Server not found"),
+            ConfigError::UserConfig(e) => write!(f, "{}", e),
+            ConfigError::Secrets(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug)]
+pub enum ConfigFileError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_json::Error),
+    NotObject(PathBuf),
+}
+
+impl std::fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFileError::Read(p, e) => write!(f, "Failed to read {}: {}", p.display(), e),
+            ConfigFileError::Parse(p, e) => write!(f, "Failed to parse {}: {}", p.display(), e),
+            ConfigFileError::NotObject(p) => write!(f, "{} must contain a JSON object of key/value pairs", p.display()),
+        }
+    }
+}
+
+impl std::error::Error for ConfigFileError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prop(key: &str) -> ConfigurableProperty {
+        ConfigurableProperty {
+            key: key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn interpolate_fills_set_keys() {
+        let config = HashMap::from([("token".to_string(), Value::from("abc")), ("port".to_string(), Value::from(8080))]);
+        let out = interpolate("Bearer ${config.token} on ${config.port}", &config, &[]).unwrap();
+        assert_eq!(out, "Bearer abc on 8080");
+    }
+
+    #[test]
+    fn interpolate_declared_but_unset_is_empty() {
+        let out = interpolate("x${config.token}y", &HashMap::new(), &[prop("token")]).unwrap();
+        assert_eq!(out, "xy");
+    }
+
+    #[test]
+    fn interpolate_rejects_undeclared_key() {
+        let err = interpolate("Bearer ${config.tokne}", &HashMap::new(), &[prop("token")]).unwrap_err();
+        assert_eq!(err.key, "tokne");
+    }
+
+    #[test]
+    fn interpolate_keeps_unterminated_placeholder() {
+        let out = interpolate("a ${config.token", &HashMap::new(), &[]).unwrap();
+        assert_eq!(out, "a ${config.token");
+    }
+
+    fn typed(key: &str, kind: &str) -> ConfigurableProperty {
+        ConfigurableProperty {
+            kind: Some(kind.to_string()),
+            ..prop(key)
+        }
+    }
+
+    #[test]
+    fn parse_value_types() {
+        assert_eq!(parse_value(&typed("port", "integer"), " 8080 ").unwrap(), Value::from(8080));
+        assert!(matches!(parse_value(&typed("port", "integer"), "80a"), Err(ConfigValueError::Type { .. })));
+        assert_eq!(parse_value(&typed("debug", "boolean"), "Yes").unwrap(), Value::Bool(true));
+        assert_eq!(parse_value(&typed("debug", "boolean"), "off").unwrap(), Value::Bool(false));
+        assert!(parse_value(&typed("debug", "boolean"), "maybe").is_err());
+        assert_eq!(parse_value(&typed("api", "url"), "https://api.example.com").unwrap(), "https://api.example.com");
+        assert!(parse_value(&typed("api", "url"), "api.example.com").is_err());
+        assert_eq!(parse_value(&prop("name"), " spaced ").unwrap(), " spaced ");
+    }
+
+    #[test]
+    fn parse_value_path_expands_home() {
+        let home = dirs::home_dir().unwrap();
+        let out = parse_value(&typed("root", "path"), "~/notes").unwrap();
+        assert_eq!(out, home.join("notes").to_string_lossy().as_ref());
+        assert_eq!(parse_value(&typed("root", "path"), "/srv/notes").unwrap(), "/srv/notes");
+        for bad in ["notes", "./notes", "", "~user-that-does-not-exist/x"] {
+            assert!(
+                matches!(parse_value(&typed("root", "path"), bad), Err(ConfigValueError::Type { .. })),
+                "{:?} accepted",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn parse_value_enum() {
+        let level = ConfigurableProperty {
+            allowed: vec![Value::from("info"), Value::from("debug")],
+            ..typed("level", "enum")
+        };
+        assert_eq!(parse_value(&level, "debug").unwrap(), "debug");
+        assert!(matches!(parse_value(&level, "trace"), Err(ConfigValueError::NotAllowed { .. })));
+
+        let workers = ConfigurableProperty {
+            allowed: vec![Value::from(1), Value::from(2)],
+            ..typed("workers", "integer")
+        };
+        assert_eq!(parse_value(&workers, "2").unwrap(), Value::from(2));
+
+        let err = parse_value(&typed("level", "enum"), "debug").unwrap_err();
+        assert!(matches!(err, ConfigValueError::BadDefinition { .. }));
+    }
+
+    /// A user-scope server with `port` (integer) and `region` set in its manifest.
+    fn installed(dir: &Path) -> (Paths, PathBuf) {
+        let paths = crate::test_support::paths(dir);
+        let manifest = serde_json::json!({
+            "id": "com.example.api",
+            "name": "API",
+            "version": "1.0.0",
+            "transports": [{"type": "streamable_http", "url": "https://api.example.com/mcp"}],
+            "configurableProperties": [{"key": "port", "type": "integer"}, {"key": "region"}],
+            "config": {"port": 8080, "region": "eu"}
+        });
+        let path = paths.user_install_dir().join("com.example.api/manifest.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, manifest.to_string()).unwrap();
+        let index = serde_json::json!({"servers": {"com.example.api": {"location": path}}});
+        std::fs::write(paths.user_install_dir().join("index.json"), index.to_string()).unwrap();
+        (paths, path)
+    }
+
+    fn manifest_config(path: &Path) -> Value {
+        let manifest: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        manifest["config"].clone()
+    }
+
+    #[test]
+    fn change_config_values_is_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (paths, path) = installed(dir.path());
+        let unset = ["region".to_string()];
+
+        let bad = Map::from_iter([("port".to_string(), Value::from("http"))]);
+        let err = change_config_values(&paths, "com.example.api", bad, &unset, false, false).unwrap_err();
+        assert!(matches!(err, SetConfigError::Invalid(_)));
+        assert_eq!(manifest_config(&path), serde_json::json!({"port": 8080, "region": "eu"}));
+
+        let good = Map::from_iter([("port".to_string(), Value::from("9090"))]);
+        let (stored, removed) = change_config_values(&paths, "com.example.api", good, &unset, false, false).unwrap();
+        assert_eq!(stored["port"], 9090);
+        assert_eq!(removed, unset);
+        assert_eq!(manifest_config(&path), serde_json::json!({"port": 9090}));
+    }
+
+    #[test]
+    fn parse_value_pattern_and_bounds() {
+        let token = ConfigurableProperty {
+            pattern: Some("ghp_[A-Za-z0-9]+".to_string()),
+            min: Some(8.0),
+            ..prop("token")
+        };
+        assert!(parse_value(&token, "ghp_abc123").is_ok());
+        assert!(matches!(parse_value(&token, "xghp_abc123"), Err(ConfigValueError::Pattern { .. })));
+        assert!(matches!(parse_value(&token, "ghp_a"), Err(ConfigValueError::TooSmall { .. })));
+
+        let port = ConfigurableProperty {
+            min: Some(1.0),
+            max: Some(65535.0),
+            ..typed("port", "integer")
+        };
+        assert!(matches!(parse_value(&port, "70000"), Err(ConfigValueError::TooLarge { .. })));
+
+        let broken = ConfigurableProperty {
+            pattern: Some("(".to_string()),
+            ..prop("x")
+        };
+        assert!(matches!(parse_value(&broken, "x"), Err(ConfigValueError::BadPattern { .. })));
+    }
+}
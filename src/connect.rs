@@ -1,15 +1,15 @@
-//! Connect to remote (SSE/WebSocket) MCP servers by URL, without a registry.
+//! Connect to remote (Streamable HTTP/SSE/WebSocket) MCP servers by URL, without a registry.
 //!
-//! Tries to fetch manifest from URL first; if valid JSON with id+transports, uses it.
+//! Tries to fetch manifest from URL first; if it parses as a registry entry, uses it.
 //! Otherwise falls back to treating URL as raw endpoint.
 
 use std::time::Duration;
 
-use crate::discovery;
-use crate::elevation::is_elevated;
+use crate::models::RegistryEntry;
 use crate::paths::Paths;
 
 /// Connect to a remote MCP server. Tries to fetch manifest from URL; falls back to raw endpoint.
+#[allow(clippy::too_many_arguments)]
 pub fn connect(
     paths: &Paths,
     url: &str,
@@ -18,6 +18,7 @@ pub fn connect(
     summary: Option<&str>,
     version: Option<&str>,
     config: &[(String, String)],
+    headers: &[(String, String)],
     scope: crate::discovery::Scope,
 ) -> Result<String, ConnectError> {
     let url = url.trim();
@@ -25,12 +26,9 @@ pub fn connect(
         return Err(ConnectError::InvalidUrl);
     }
 
-    if let Some(mut manifest) = try_fetch_manifest(url) {
+    if let Some(mut entry) = try_fetch_manifest(url) {
         // Manifest mode: use fetched manifest, apply overrides
-        let id = id_override
-            .map(String::from)
-            .or_else(|| manifest.get("id").and_then(|v| v.as_str()).map(String::from))
-            .unwrap_or_else(|| next_connected_server_id(paths, scope).unwrap_or_else(|_| "com.user.connected.server1".to_string()));
+        let id = id_override.map(String::from).unwrap_or_else(|| entry.id.clone());
 
         let install_dir = match scope {
             crate::discovery::Scope::User => paths.user_install_dir().join(&id),
@@ -39,35 +37,35 @@ pub fn connect(
 
         std::fs::create_dir_all(&install_dir).map_err(ConnectError::CreateDir)?;
 
-        manifest["installDir"] = serde_json::Value::String(install_dir.to_string_lossy().to_string());
-        manifest["id"] = serde_json::Value::String(id.clone());
-
+        entry.id = id.clone();
         if let Some(n) = name {
-            manifest["name"] = serde_json::Value::String(n.to_string());
-        } else if manifest.get("name").is_none() {
-            manifest["name"] = serde_json::Value::String(id.clone());
+            entry.name = n.to_string();
         }
-
         if let Some(s) = summary {
-            manifest["summary"] = serde_json::Value::String(s.to_string());
-        } else if manifest.get("summary").is_none() {
-            manifest["summary"] = serde_json::Value::String("Connected via dmcp connect".to_string());
+            entry.summary = s.to_string();
         }
-
         if let Some(v) = version {
-            manifest["version"] = serde_json::Value::String(v.to_string());
-        } else if manifest.get("version").is_none() {
-            manifest["version"] = serde_json::Value::String("1.0.0".to_string());
+            entry.version = v.to_string();
+        }
+
+        // Merge header overrides into every remote transport
+        for t in entry.transports.iter_mut() {
+            if let Some(h) = t.headers_mut() {
+                h.extend(headers.iter().cloned());
+            }
         }
 
         // Merge config overrides
-        let mut config_obj = manifest
-            .get("config")
-            .and_then(|c| c.as_object().cloned())
-            .unwrap_or_default();
+        let mut config_obj = match entry.extra.remove("config") {
+            Some(serde_json::Value::Object(c)) => c,
+            _ => serde_json::Map::new(),
+        };
         for (k, v) in config {
             config_obj.insert(k.clone(), serde_json::Value::String(v.clone()));
         }
+
+        let mut manifest = serde_json::to_value(&entry).map_err(ConnectError::Serialize)?;
+        manifest["installDir"] = serde_json::Value::String(install_dir.to_string_lossy().to_string());
         manifest["config"] = serde_json::Value::Object(config_obj);
 
         let manifest_path = install_dir.join("manifest.json");
@@ -81,11 +79,12 @@ pub fn connect(
     }
 
     // Raw fallback: treat URL as endpoint
-    connect_raw(paths, url, id_override, name, summary, version, config, scope)
+    connect_raw(paths, url, id_override, name, summary, version, config, headers, scope)
 }
 
-/// Try to fetch URL as JSON manifest. Returns Some if valid (has id and transports).
-fn try_fetch_manifest(url: &str) -> Option<serde_json::Value> {
+/// Try to fetch URL as JSON manifest. Returns Some if it parses as a registry entry
+/// (non-empty id and transports); missing name, summary and version get defaults.
+fn try_fetch_manifest(url: &str) -> Option<RegistryEntry> {
     let client = reqwest::blocking::Client::builder()
         .user_agent("dmcp/1.0")
         .connect_timeout(Duration::from_secs(15))
@@ -98,17 +97,18 @@ fn try_fetch_manifest(url: &str) -> Option<serde_json::Value> {
         return None;
     }
 
-    let manifest: serde_json::Value = resp.json().ok()?;
-    let id = manifest.get("id").and_then(|v| v.as_str())?;
-    let transports = manifest.get("transports").and_then(|t| t.as_array())?;
-    if id.is_empty() || transports.is_empty() {
-        return None;
-    }
+    let mut manifest: serde_json::Value = resp.json().ok()?;
+    let obj = manifest.as_object_mut()?;
+    let id = obj.get("id").cloned()?;
+    obj.entry("name").or_insert(id);
+    obj.entry("summary").or_insert_with(|| "Connected via dmcp connect".into());
+    obj.entry("version").or_insert_with(|| "1.0.0".into());
 
-    Some(manifest)
+    RegistryEntry::from_value(manifest).ok()
 }
 
 /// Raw endpoint mode: infer transport from URL, auto-generate metadata.
+#[allow(clippy::too_many_arguments)]
 fn connect_raw(
     paths: &Paths,
     url: &str,
@@ -117,13 +117,10 @@ fn connect_raw(
     summary: Option<&str>,
     version: Option<&str>,
     config: &[(String, String)],
+    headers: &[(String, String)],
     scope: crate::discovery::Scope,
 ) -> Result<String, ConnectError> {
-    let transport_type = if url.starts_with("wss://") || url.starts_with("ws://") {
-        "websocket"
-    } else {
-        "sse"
-    };
+    let transport_type = infer_transport_type(url);
 
     let id = id_override
         .map(String::from)
@@ -136,18 +133,26 @@ fn connect_raw(
 
     std::fs::create_dir_all(&install_dir).map_err(ConnectError::CreateDir)?;
 
-    let transport = if transport_type == "websocket" {
+    let mut transport = if transport_type == "websocket" {
         serde_json::json!({
             "type": "websocket",
             "wsUrl": url
         })
     } else {
         serde_json::json!({
-            "type": "sse",
+            "type": transport_type,
             "url": url
         })
     };
 
+    if !headers.is_empty() {
+        let headers_obj: serde_json::Map<_, _> = headers
+            .iter()
+            .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+            .collect();
+        transport["headers"] = serde_json::Value::Object(headers_obj);
+    }
+
     let mut config_obj = serde_json::Map::new();
     for (k, v) in config {
         config_obj.insert(k.clone(), serde_json::Value::String(v.clone()));
@@ -173,6 +178,22 @@ fn connect_raw(
     Ok(id)
 }
 
+/// ws(s):// is WebSocket; an HTTP path ending in `/sse` is the legacy HTTP+SSE
+/// transport; any other HTTP endpoint is assumed to be Streamable HTTP.
+fn infer_transport_type(url: &str) -> &'static str {
+    if url.starts_with("wss://") || url.starts_with("ws://") {
+        return "websocket";
+    }
+    let is_sse = reqwest::Url::parse(url)
+        .map(|u| u.path().trim_end_matches('/').ends_with("/sse"))
+        .unwrap_or(false);
+    if is_sse {
+        "sse"
+    } else {
+        "streamable_http"
+    }
+}
+
 fn next_connected_server_id(paths: &Paths, scope: crate::discovery::Scope) -> Result<String, ConnectError> {
     let index_path = match scope {
         crate::discovery::Scope::User => paths.user_install_dir().join("index.json"),
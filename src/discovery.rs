@@ -17,7 +17,7 @@ pub struct ServerInfo {
     pub install_dir: String,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Scope {
     User,
@@ -109,7 +109,7 @@ fn load_from_scope(base: &Path, scope: Scope, debug: bool) -> Option<Vec<ServerI
             .transports
             .as_ref()
             .and_then(|t| t.first())
-            .map(transport_type_name)
+            .map(|t| t.type_name().to_string())
             .unwrap_or_else(|| "unknown".to_string());
 
         let install_dir = manifest_path
@@ -134,15 +134,6 @@ fn load_from_scope(base: &Path, scope: Scope, debug: bool) -> Option<Vec<ServerI
     Some(servers)
 }
 
-fn transport_type_name(t: &crate::models::Transport) -> String {
-    match t {
-        crate::models::Transport::Stdio { .. } => "stdio",
-        crate::models::Transport::Sse { .. } => "sse",
-        crate::models::Transport::WebSocket { .. } => "websocket",
-    }
-    .to_string()
-}
-
 /// Get a single server by id. User scope is checked first.
 pub fn get_server(paths: &Paths, id: &str) -> Option<(Manifest, Scope)> {
     // Check user scope first
@@ -13,6 +13,31 @@ pub fn is_system_scope(path: &Path, system_install_dir: &Path) -> bool {
     path.starts_with(system_install_dir)
 }
 
+/// When re-executed through pkexec (or sudo), files written to the invoking
+/// user's directories would be owned by root; hand them back to that user.
+pub fn chown_to_invoker(path: &Path) {
+    if !is_elevated() {
+        return;
+    }
+    let uid = std::env::var("PKEXEC_UID")
+        .or_else(|_| std::env::var("SUDO_UID"))
+        .ok()
+        .and_then(|u| u.parse().ok());
+    if let Some(uid) = uid {
+        let _ = nix::unistd::chown(path, Some(nix::unistd::Uid::from_raw(uid)), None);
+    }
+}
+
+/// `create_dir_all`, with every directory it creates given to the invoking user.
+pub fn create_dir_all_for_invoker(dir: &Path) -> std::io::Result<()> {
+    let missing: Vec<&Path> = dir.ancestors().take_while(|d| !d.as_os_str().is_empty() && !d.exists()).collect();
+    std::fs::create_dir_all(dir)?;
+    for d in missing {
+        chown_to_invoker(d);
+    }
+    Ok(())
+}
+
 /// Re-execute the current binary with pkexec for elevation.
 /// Passes through all current args. Exits with the child's exit code.
 /// Preserves HOME so the elevated process can read the invoking user's config (sources.list).
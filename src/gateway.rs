@@ -0,0 +1,468 @@
+//! Aggregating gateway.
+//!
+//! Runs as a single stdio MCP server in front of several installed servers.
+//! Tools, resources and prompts are merged with an `<id>__` prefix and calls are
+//! routed back to the owning server. Each backend runs on its own thread so a
+//! slow call doesn't hold up the others. Tool policy (policy.json) is enforced per backend.
+
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::discovery::{get_server, list_servers};
+use crate::paths::Paths;
+use crate::policy::{Policy, PolicyError, DENIED_CODE};
+use crate::protocol::{Client, ProtocolError, PROTOCOL_VERSION};
+
+/// Separator between the server id and the original name.
+pub const SEPARATOR: &str = "__";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Notifications forwarded from backends to the gateway client.
+const FORWARDED_NOTIFICATIONS: &[&str] = &[
+    "notifications/tools/list_changed",
+    "notifications/resources/list_changed",
+    "notifications/prompts/list_changed",
+];
+
+type Job = Box<dyn FnOnce(&mut Client) + Send>;
+
+/// Handle to a backend server running on its own thread.
+struct Backend {
+    id: String,
+    jobs: Sender<Job>,
+}
+
+impl Backend {
+    /// Start the worker thread. Connection errors are reported and the backend is skipped.
+    fn start(paths: &Paths, id: &str, out: Sender<Value>) -> Self {
+        let paths = paths.clone();
+        let backend_id = id.to_string();
+        Self::spawn(id, out, move || Client::connect(&paths, &backend_id))
+    }
+
+    /// Run `connect` and then the worker loop on a new thread.
+    fn spawn<C>(id: &str, out: Sender<Value>, connect: C) -> Self
+    where
+        C: FnOnce() -> Result<Client, ProtocolError> + Send + 'static,
+    {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let backend_id = id.to_string();
+        std::thread::spawn(move || {
+            let mut client = match connect() {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("dmcp gateway: {}: {}", backend_id, e);
+                    return;
+                }
+            };
+            worker(&mut client, &backend_id, rx, &out);
+        });
+        Self { id: id.to_string(), jobs }
+    }
+
+    /// Queue `f` on the backend thread; the returned receiver yields its result.
+    fn submit<T, F>(&self, f: F) -> Receiver<Result<T, ProtocolError>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Client) -> Result<T, ProtocolError> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        // If the worker is gone, `tx` is dropped with the job and the receiver reports it
+        let _ = self.jobs.send(Box::new(move |c: &mut Client| {
+            let _ = tx.send(f(c));
+        }));
+        rx
+    }
+
+    fn call<T, F>(&self, f: F) -> Result<T, ProtocolError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Client) -> Result<T, ProtocolError> + Send + 'static,
+    {
+        self.submit(f).recv().unwrap_or(Err(ProtocolError::Closed))
+    }
+}
+
+fn worker(client: &mut Client, id: &str, jobs: Receiver<Job>, out: &Sender<Value>) {
+    loop {
+        let notifications = match jobs.recv_timeout(POLL_INTERVAL) {
+            Ok(job) => {
+                job(client);
+                client.take_notifications()
+            }
+            Err(RecvTimeoutError::Timeout) => match client.poll_notifications(POLL_INTERVAL) {
+                Ok(n) => n,
+                Err(e) => {
+                    eprintln!("dmcp gateway: {}: {}", id, e);
+                    return;
+                }
+            },
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        for n in notifications {
+            let method = n.get("method").and_then(|m| m.as_str()).unwrap_or("");
+            if FORWARDED_NOTIFICATIONS.contains(&method) && out.send(n).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Serve the gateway on stdin/stdout until stdin closes.
+/// `ids` selects the backends; empty means every installed server.
+pub fn gateway(paths: &Paths, ids: &[String], debug: bool) -> Result<(), GatewayError> {
+    let ids: Vec<String> = if ids.is_empty() {
+        list_servers(paths, true, true, debug).into_iter().map(|s| s.id).collect()
+    } else {
+        for id in ids {
+            if get_server(paths, id).is_none() {
+                return Err(GatewayError::ServerNotFound(id.clone()));
+            }
+        }
+        ids.to_vec()
+    };
+    if ids.is_empty() {
+        return Err(GatewayError::NoServers);
+    }
+    let policy = Arc::new(Policy::load(paths).map_err(GatewayError::Policy)?);
+
+    let (out, out_rx) = mpsc::channel::<Value>();
+    let writer = std::thread::spawn(move || -> std::io::Result<()> {
+        let mut stdout = std::io::stdout();
+        for msg in out_rx {
+            writeln!(stdout, "{}", msg)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    });
+
+    let backends: Arc<Vec<Backend>> = Arc::new(ids.iter().map(|id| Backend::start(paths, id, out.clone())).collect());
+
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.map_err(GatewayError::Io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let msg: Value = match serde_json::from_str(&line) {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("dmcp gateway: ignoring invalid JSON from client: {}", e);
+                continue;
+            }
+        };
+        // Notifications (including `initialized`) and responses need no reply
+        let Some(id) = msg.get("id").cloned() else { continue };
+        if msg.get("method").is_none() {
+            continue;
+        }
+        let backends = Arc::clone(&backends);
+        let policy = Arc::clone(&policy);
+        let out = out.clone();
+        std::thread::spawn(move || {
+            let reply = match handle_request(&backends, &policy, &msg) {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err((code, message)) => {
+                    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+                }
+            };
+            let _ = out.send(reply);
+        });
+    }
+
+    // Stop the workers, then let the writer flush what's left
+    drop(backends);
+    drop(out);
+    let _ = writer.join();
+    Ok(())
+}
+
+type RpcResult = Result<Value, (i64, String)>;
+
+fn handle_request(backends: &[Backend], policy: &Policy, msg: &Value) -> RpcResult {
+    let method = msg.get("method").and_then(|m| m.as_str()).unwrap_or("");
+    let params = msg.get("params").cloned().unwrap_or_else(|| json!({}));
+    match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {
+                "tools": {"listChanged": true},
+                "resources": {"listChanged": true},
+                "prompts": {"listChanged": true}
+            },
+            "serverInfo": {"name": "dmcp-gateway", "version": env!("CARGO_PKG_VERSION")}
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({"tools": merge_lists(backends, |c| c.list_tools(), &["name"], Some(policy))})),
+        "resources/list" => Ok(json!({"resources": merge_lists(backends, |c| c.list_resources(), &["name", "uri"], None)})),
+        "prompts/list" => Ok(json!({"prompts": merge_lists(backends, |c| c.list_prompts(), &["name"], None)})),
+        "tools/call" | "prompts/get" => {
+            let name = params.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            let (backend, original) = route(backends, name)?;
+            if method == "tools/call" && !policy.is_allowed(&backend.id, original) {
+                return Err((DENIED_CODE, format!("Tool denied by policy: {}", name)));
+            }
+            let mut forwarded = params.clone();
+            forwarded["name"] = json!(original);
+            let method = method.to_string();
+            backend.call(move |c| c.request(&method, Some(forwarded))).map_err(rpc_error)
+        }
+        "resources/read" => {
+            let uri = params.get("uri").and_then(|u| u.as_str()).unwrap_or("");
+            let (backend, original) = route(backends, uri)?;
+            let original = original.to_string();
+            let mut result = backend.call(move |c| c.read_resource(&original)).map_err(rpc_error)?;
+            if let Some(contents) = result.get_mut("contents").and_then(|c| c.as_array_mut()) {
+                for item in contents {
+                    prefix_field(item, "uri", &backend.id);
+                }
+            }
+            Ok(result)
+        }
+        _ => Err((-32601, format!("Method not found: {}", method))),
+    }
+}
+
+/// Ask every backend concurrently and merge the results, prefixing `fields` with the server id.
+/// Backends that fail or don't implement the method are left out. With `tool_policy`, denied tools are dropped.
+fn merge_lists<F>(backends: &[Backend], list: F, fields: &[&str], tool_policy: Option<&Policy>) -> Vec<Value>
+where
+    F: Fn(&mut Client) -> Result<Vec<Value>, ProtocolError> + Send + Copy + 'static,
+{
+    let pending: Vec<_> = backends.iter().map(|b| (b, b.submit(list))).collect();
+    let mut merged = Vec::new();
+    for (backend, rx) in pending {
+        match rx.recv().unwrap_or(Err(ProtocolError::Closed)) {
+            Ok(mut items) => {
+                if let Some(policy) = tool_policy {
+                    policy.filter_tools(&backend.id, &mut items);
+                }
+                for mut item in items {
+                    for field in fields {
+                        prefix_field(&mut item, field, &backend.id);
+                    }
+                    merged.push(item);
+                }
+            }
+            // Dead backends were already reported when they went away
+            Err(ProtocolError::Rpc { code: -32601, .. }) | Err(ProtocolError::Closed) => {}
+            Err(e) => eprintln!("dmcp gateway: {}: {}", backend.id, e),
+        }
+    }
+    merged
+}
+
+fn prefix_field(item: &mut Value, field: &str, id: &str) {
+    if let Some(v) = item.get(field).and_then(|v| v.as_str()) {
+        item[field] = json!(format!("{}{}{}", id, SEPARATOR, v));
+    }
+}
+
+/// Find the backend owning a prefixed name. The longest matching id wins.
+fn route<'a, 'n>(backends: &'a [Backend], name: &'n str) -> Result<(&'a Backend, &'n str), (i64, String)> {
+    backends
+        .iter()
+        .filter_map(|b| {
+            name.strip_prefix(b.id.as_str())
+                .and_then(|rest| rest.strip_prefix(SEPARATOR))
+                .map(|rest| (b, rest))
+        })
+        .max_by_key(|(b, _)| b.id.len())
+        .ok_or_else(|| (-32602, format!("Unknown name: {}", name)))
+}
+
+fn rpc_error(e: ProtocolError) -> (i64, String) {
+    match e {
+        ProtocolError::Rpc { code, message } => (code, message),
+        e => (-32603, e.to_string()),
+    }
+}
+
+#[derive(Debug)]
+pub enum GatewayError {
+    ServerNotFound(String),
+    NoServers,
+    Policy(PolicyError),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GatewayError::ServerNotFound(id) => write!(f, "Server not found: {}", id),
+            GatewayError::NoServers => write!(f, "No servers installed"),
+            GatewayError::Policy(e) => write!(f, "{}", e),
+            GatewayError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GatewayError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{PolicyFile, ToolRules};
+    use crate::test_support::StandIn;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    struct Harness {
+        backends: Vec<Backend>,
+        sent: Vec<Arc<Mutex<Vec<Value>>>>,
+        inboxes: Vec<Arc<Mutex<VecDeque<Value>>>>,
+        out: Receiver<Value>,
+    }
+
+    /// One stand-in backend per `(id, tools)`. Calls answer with `<id>:<tool>`;
+    /// each backend has one resource and doesn't implement prompts.
+    fn harness(servers: &[(&str, &[&str])]) -> Harness {
+        let (out, out_rx) = mpsc::channel();
+        let mut h = Harness {
+            backends: Vec::new(),
+            sent: Vec::new(),
+            inboxes: Vec::new(),
+            out: out_rx,
+        };
+        for (id, tools) in servers {
+            let id = id.to_string();
+            let tools: Vec<Value> = tools.iter().map(|t| json!({"name": t})).collect();
+            let server_id = id.clone();
+            let conn = StandIn::new(move |method, params| match method {
+                "tools/list" => Ok(json!({"tools": tools})),
+                "tools/call" => Ok(json!({"content": [{"type": "text", "text": format!("{}:{}", server_id, params["name"].as_str().unwrap())}]})),
+                "resources/list" => Ok(json!({"resources": [{"name": "readme", "uri": "file:///readme"}]})),
+                "resources/read" => Ok(json!({"contents": [{"uri": params["uri"], "text": server_id}]})),
+                _ => Err((-32601, "Method not found".to_string())),
+            });
+            h.sent.push(Arc::clone(&conn.sent));
+            h.inboxes.push(Arc::clone(&conn.inbox));
+            h.backends.push(Backend::spawn(&id, out.clone(), move || Ok(Client::new(Box::new(conn)))));
+        }
+        h
+    }
+
+    fn request(h: &Harness, policy: &Policy, method: &str, params: Value) -> RpcResult {
+        handle_request(&h.backends, policy, &json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+    }
+
+    fn calls(sent: &Mutex<Vec<Value>>) -> Vec<Value> {
+        sent.lock()
+            .unwrap()
+            .iter()
+            .filter(|m| m["method"] == "tools/call")
+            .map(|m| m["params"]["name"].clone())
+            .collect()
+    }
+
+    fn names(list: &Value, field: &str) -> Vec<String> {
+        list[field]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tools_are_merged_with_prefixes() {
+        let h = harness(&[("a", &["echo"]), ("a.b", &["echo", "extra"])]);
+        let list = request(&h, &Policy::default(), "tools/list", json!({})).unwrap();
+        assert_eq!(names(&list, "tools"), ["a__echo", "a.b__echo", "a.b__extra"]);
+    }
+
+    #[test]
+    fn calls_are_routed_without_the_prefix() {
+        let h = harness(&[("a", &["echo"]), ("a.b", &["echo"])]);
+        let result = request(&h, &Policy::default(), "tools/call", json!({"name": "a.b__echo"})).unwrap();
+        assert_eq!(result["content"][0]["text"], "a.b:echo");
+        let result = request(&h, &Policy::default(), "tools/call", json!({"name": "a__echo"})).unwrap();
+        assert_eq!(result["content"][0]["text"], "a:echo");
+        assert_eq!(calls(&h.sent[0]), [json!("echo")]);
+        assert_eq!(calls(&h.sent[1]), [json!("echo")]);
+    }
+
+    #[test]
+    fn longest_id_wins() {
+        let h = harness(&[("a", &[]), ("a__b", &[])]);
+        let (backend, rest) = route(&h.backends, "a__b__x").unwrap();
+        assert_eq!((backend.id.as_str(), rest), ("a__b", "x"));
+        let (backend, rest) = route(&h.backends, "a__c__x").unwrap();
+        assert_eq!((backend.id.as_str(), rest), ("a", "c__x"));
+    }
+
+    #[test]
+    fn unknown_ids_are_rejected() {
+        let h = harness(&[("a", &["echo"])]);
+        for name in ["zzz__echo", "echo", "ab__echo"] {
+            let (code, message) = request(&h, &Policy::default(), "tools/call", json!({"name": name})).unwrap_err();
+            assert_eq!(code, -32602);
+            assert!(message.contains(name));
+        }
+        assert!(calls(&h.sent[0]).is_empty());
+    }
+
+    #[test]
+    fn resource_uris_round_trip() {
+        let h = harness(&[("a", &[]), ("b", &[])]);
+        let list = request(&h, &Policy::default(), "resources/list", json!({})).unwrap();
+        let uris: Vec<&str> = list["resources"].as_array().unwrap().iter().map(|r| r["uri"].as_str().unwrap()).collect();
+        assert_eq!(uris, ["a__file:///readme", "b__file:///readme"]);
+
+        let result = request(&h, &Policy::default(), "resources/read", json!({"uri": "b__file:///readme"})).unwrap();
+        assert_eq!(result["contents"][0]["uri"], "b__file:///readme");
+        assert_eq!(result["contents"][0]["text"], "b");
+        let read = h.sent[1].lock().unwrap().iter().find(|m| m["method"] == "resources/read").cloned().unwrap();
+        assert_eq!(read["params"]["uri"], "file:///readme");
+    }
+
+    #[test]
+    fn unimplemented_lists_are_empty() {
+        let h = harness(&[("a", &["echo"])]);
+        let list = request(&h, &Policy::default(), "prompts/list", json!({})).unwrap();
+        assert_eq!(list, json!({"prompts": []}));
+    }
+
+    #[test]
+    fn policy_filters_and_blocks_tools() {
+        let h = harness(&[("a", &["echo", "secret"]), ("b", &["secret"])]);
+        let mut system = PolicyFile::default();
+        system.servers.insert(
+            "a".to_string(),
+            ToolRules {
+                allow: Vec::new(),
+                deny: vec!["secret".to_string()],
+            },
+        );
+        let policy = Policy {
+            system,
+            user: PolicyFile::default(),
+        };
+
+        let list = request(&h, &policy, "tools/list", json!({})).unwrap();
+        assert_eq!(names(&list, "tools"), ["a__echo", "b__secret"]);
+
+        let (code, _) = request(&h, &policy, "tools/call", json!({"name": "a__secret"})).unwrap_err();
+        assert_eq!(code, DENIED_CODE);
+        assert!(calls(&h.sent[0]).is_empty());
+        assert!(request(&h, &policy, "tools/call", json!({"name": "b__secret"})).is_ok());
+    }
+
+    #[test]
+    fn list_changed_is_forwarded() {
+        let h = harness(&[("a", &[])]);
+        {
+            let mut inbox = h.inboxes[0].lock().unwrap();
+            inbox.push_back(json!({"jsonrpc": "2.0", "method": "notifications/message", "params": {"data": "log"}}));
+            inbox.push_back(json!({"jsonrpc": "2.0", "method": "notifications/tools/list_changed"}));
+        }
+        let forwarded = h.out.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(forwarded["method"], "notifications/tools/list_changed");
+        assert!(h.out.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
@@ -1,156 +1,220 @@
 //! Install and uninstall MCP servers.
 
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
 
+use crate::browse::{find_registry_server, BrowseError};
 use crate::discovery;
 use crate::elevation::is_elevated;
+use crate::models::{InstalledFrom, RegistryEntry, Transport};
 use crate::paths::Paths;
-use crate::sources::list_sources;
+use crate::registry::RegistryError;
+use crate::secrets::{self, SecretsError};
+use crate::source::{fetch_source, SourceError};
+use serde_json::Value;
 
 /// Install a server from registry by id.
 /// When server_override is Some, uses it instead of fetching (avoids double fetch when main already fetched for scope resolution).
+///
+/// The server is staged in a sibling directory of the install dir and renamed
+/// into place. An existing install is kept until the index update succeeds, and
+/// any failure leaves the previous state (install dir, index and secrets) untouched.
+///
+/// The entry's `setup` commands run in the staged dir only when `run_setup` is set,
+/// since they are third-party code; callers must get the user's consent first.
+///
+/// Returns the sensitive config keys that were left out of a system-wide install.
 pub fn install(
     paths: &Paths,
     id: &str,
     scope: crate::discovery::Scope,
-    server_override: Option<serde_json::Value>,
-) -> Result<(), InstallError> {
-    let server = match server_override {
+    server_override: Option<RegistryEntry>,
+    run_setup: bool,
+) -> Result<Vec<String>, InstallError> {
+    let mut server = match server_override {
         Some(s) => s,
-        None => fetch_server_from_registry(paths, id)?,
+        None => fetch_server_from_registry(paths, id, false)?,
     };
 
-    let install_dir = match scope {
-        crate::discovery::Scope::User => paths.user_install_dir().join(id),
-        crate::discovery::Scope::System => paths.system_install_dir().join(id),
+    // Sensitive values go to the user's secret store, never into the manifest.
+    // They are stored once staging succeeded and put back if the commit fails.
+    let mut sensitive = match server.extra.get_mut("config") {
+        Some(Value::Object(config)) => secrets::take_sensitive(&server.configurable_properties, config),
+        _ => serde_json::Map::new(),
+    };
+    // A system-wide install runs as root for every user; its secrets would only reach
+    // root's store, so they are dropped and each user sets their own
+    let skipped: Vec<String> = match scope {
+        crate::discovery::Scope::System => std::mem::take(&mut sensitive).into_iter().map(|(k, _)| k).collect(),
+        crate::discovery::Scope::User => Vec::new(),
+    };
+
+    let base = match scope {
+        crate::discovery::Scope::User => paths.user_install_dir(),
+        crate::discovery::Scope::System => paths.system_install_dir(),
+    };
+    let install_dir = base.join(id);
+    std::fs::create_dir_all(base).map_err(InstallError::CreateDir)?;
+
+    // Staging and backup live next to the install dir so the renames stay on one filesystem
+    let staging = base.join(format!(".{}.staging-{}", id, std::process::id()));
+    let previous = base.join(format!(".{}.previous-{}", id, std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    let saved_secrets = stage(&server, &staging, &install_dir, run_setup).and_then(|()| {
+        let saved = secrets::snapshot(paths, id).map_err(InstallError::Secrets)?;
+        secrets::set(paths, id, sensitive).map_err(InstallError::Secrets)?;
+        Ok(saved)
+    });
+    let saved_secrets = match saved_secrets {
+        Ok(saved) => saved,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    let had_previous = install_dir.exists();
+    if had_previous {
+        let _ = std::fs::remove_dir_all(&previous);
+        if let Err(e) = std::fs::rename(&install_dir, &previous) {
+            let _ = std::fs::remove_dir_all(&staging);
+            let _ = secrets::restore(paths, &saved_secrets);
+            return Err(InstallError::Commit(e));
+        }
+    }
+    let rollback = || {
+        let _ = std::fs::remove_dir_all(&install_dir);
+        if had_previous {
+            let _ = std::fs::rename(&previous, &install_dir);
+        }
+        let _ = secrets::restore(paths, &saved_secrets);
     };
 
-    std::fs::create_dir_all(&install_dir).map_err(InstallError::CreateDir)?;
+    if let Err(e) = std::fs::rename(&staging, &install_dir) {
+        let _ = std::fs::remove_dir_all(&staging);
+        rollback();
+        return Err(InstallError::Commit(e));
+    }
 
-    let transports = server
-        .get("transports")
-        .and_then(|t| t.as_array())
-        .ok_or(InstallError::InvalidRegistry)?;
+    if let Err(e) = update_index_add(paths, id, &install_dir.join("manifest.json"), scope) {
+        rollback();
+        return Err(e);
+    }
 
-    let first_transport = transports.first().ok_or(InstallError::InvalidRegistry)?;
-    let transport_type = first_transport.get("type").and_then(|t| t.as_str()).unwrap_or("");
+    if had_previous {
+        let _ = std::fs::remove_dir_all(&previous);
+    }
+    Ok(skipped)
+}
 
-    if transport_type == "stdio" {
-        install_stdio(&server, &install_dir)?;
-    } else if transport_type == "sse" || transport_type == "websocket" {
-        // Remote: just write manifest
-    } else {
-        return Err(InstallError::UnsupportedTransport);
+/// Populate `staging` with the server files and a manifest pointing at `install_dir`.
+fn stage(server: &RegistryEntry, staging: &Path, install_dir: &Path, run_setup: bool) -> Result<(), InstallError> {
+    std::fs::create_dir_all(staging).map_err(InstallError::CreateDir)?;
+
+    // Remote servers only need the manifest
+    let mut server = server.clone();
+    let installed_from = match server.transports.first() {
+        Some(Transport::Stdio { .. }) => Some(install_stdio(&mut server, staging)?),
+        _ => None,
+    };
+    if run_setup && installed_from.is_some() && !server.setup.is_empty() {
+        let kept_log = install_dir.with_file_name(format!("{}.install.log", server.id));
+        setup(&server.setup, staging, &kept_log)?;
     }
 
     // Build manifest
-    let mut manifest = server.clone();
+    let mut manifest = serde_json::to_value(&server).map_err(InstallError::Serialize)?;
     manifest["installDir"] = serde_json::Value::String(install_dir.to_string_lossy().to_string());
+    if let Some(from) = installed_from {
+        manifest["installedFrom"] = serde_json::to_value(from).map_err(InstallError::Serialize)?;
+    }
     if manifest.get("config").is_none() {
         manifest["config"] = serde_json::json!({});
     }
 
-    let manifest_path = install_dir.join("manifest.json");
     let output = serde_json::to_string_pretty(&manifest).map_err(InstallError::Serialize)?;
-    std::fs::write(&manifest_path, output).map_err(InstallError::WriteManifest)?;
-
-    // Update index
-    update_index_add(paths, id, &manifest_path, scope)?;
-
-    Ok(())
+    std::fs::write(staging.join("manifest.json"), output).map_err(InstallError::WriteManifest)
 }
 
-/// Resolve install scope: from --system/--user override, or from registry's "scope" field (default "user").
-pub fn scope_from_registry_server(server: &serde_json::Value) -> crate::discovery::Scope {
-    let s = server.get("scope").and_then(|v| v.as_str()).unwrap_or("user");
-    if s == "system" {
-        crate::discovery::Scope::System
-    } else {
-        crate::discovery::Scope::User
-    }
+/// Resolve install scope from the registry's "scope" field (default user).
+pub fn scope_from_registry_server(server: &RegistryEntry) -> crate::discovery::Scope {
+    server.scope.unwrap_or(crate::discovery::Scope::User)
 }
 
-pub fn fetch_server_from_registry(paths: &Paths, id: &str) -> Result<serde_json::Value, InstallError> {
-    let sources = list_sources(paths, true, true);
-    if sources.is_empty() {
-        return Err(InstallError::NoSources);
+/// Find a server entry by id across registry sources (user sources first).
+/// With `offline`, only cached registries are searched.
+pub fn fetch_server_from_registry(paths: &Paths, id: &str, offline: bool) -> Result<RegistryEntry, InstallError> {
+    match find_registry_server(paths, id, offline) {
+        Ok(s) => Ok(s.entry),
+        Err(BrowseError::NoSources) => Err(InstallError::NoSources),
+        Err(BrowseError::NotFound(_)) => Err(InstallError::ServerNotFound),
+        Err(BrowseError::Registry(e)) => Err(InstallError::Registry(e)),
     }
+}
 
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("dmcp/1.0")
-        .build()
-        .map_err(|e| InstallError::HttpClient(e))?;
-
-    for (url, _) in sources {
-        let resp = client.get(&url).send().map_err(InstallError::FetchFailed)?;
-        if !resp.status().is_success() {
-            continue;
-        }
-        let registry: serde_json::Value = resp.json().map_err(InstallError::FetchFailed)?;
-        let servers = registry
-            .get("servers")
-            .and_then(|s| s.as_array())
-            .ok_or(InstallError::InvalidRegistry)?;
-
-        for server in servers {
-            if server.get("id").and_then(|i| i.as_str()) == Some(id) {
-                return Ok(server.clone());
+/// Fetch a stdio server's files into `install_dir` and report what was installed.
+/// For package sources, stdio commands are rewritten to run the installed package.
+fn install_stdio(server: &mut RegistryEntry, install_dir: &Path) -> Result<InstalledFrom, InstallError> {
+    let source = server
+        .source
+        .as_ref()
+        .filter(|s| !s.is_empty())
+        .ok_or(InstallError::MissingSource)?;
+    let fetched = fetch_source(source, install_dir).map_err(InstallError::Source)?;
+    if let (Some(launcher), Some(package)) = (fetched.launcher, source.package.clone()) {
+        for t in server.transports.iter_mut() {
+            if let Transport::Stdio { command, args, .. } = t {
+                let (c, a) = launcher
+                    .resolve(install_dir, &package, command, args.as_deref().unwrap_or_default())
+                    .map_err(InstallError::Source)?;
+                *command = c;
+                *args = (!a.is_empty()).then_some(a);
             }
         }
     }
-
-    Err(InstallError::ServerNotFound)
+    Ok(fetched.from)
 }
 
-fn install_stdio(server: &serde_json::Value, install_dir: &Path) -> Result<(), InstallError> {
-    let source = server.get("source").and_then(|s| s.as_object()).ok_or(InstallError::InvalidRegistry)?;
-    let url = source.get("url").and_then(|u| u.as_str()).ok_or(InstallError::InvalidRegistry)?;
-    let path = source.get("path").and_then(|p| p.as_str()).unwrap_or("");
-
-    let temp = std::env::temp_dir().join(format!("dmcp-clone-{}", std::process::id()));
-    let _ = std::fs::remove_dir_all(&temp);
-    std::fs::create_dir_all(&temp).map_err(InstallError::CreateDir)?;
-
-    let status = Command::new("git")
-        .args(["clone", "--depth", "1", "--filter=blob:none", url, temp.to_str().unwrap()])
-        .status()
-        .map_err(InstallError::GitFailed)?;
-    if !status.success() {
-        return Err(InstallError::GitFailed(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "git clone failed",
-        )));
-    }
-
-    let src = if path.is_empty() {
-        temp.clone()
-    } else {
-        temp.join(path)
-    };
-
-    if !src.exists() {
-        return Err(InstallError::InvalidRegistry);
-    }
-
-    copy_dir_all(&src, install_dir).map_err(InstallError::CopyFailed)?;
-    std::fs::remove_dir_all(&temp).ok();
-
-    Ok(())
-}
-
-fn copy_dir_all(src: &Path, dst: &Path) -> std::io::Result<()> {
-    std::fs::create_dir_all(dst)?;
-    for entry in std::fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        let dst_path = dst.join(entry.file_name());
-        if ty.is_dir() {
-            copy_dir_all(&entry.path(), &dst_path)?;
-        } else {
-            std::fs::copy(entry.path(), dst_path)?;
-        }
+/// Run `steps` with `sh -c` in `dir`, logging their output to `dir/install.log`.
+/// On failure the log is moved to `kept`, since `dir` is about to be removed. `kept`
+/// sits next to the install dir, so only the install's owner can write there, and the
+/// rename replaces whatever is at that path rather than following it.
+fn setup(steps: &[String], dir: &Path, kept: &Path) -> Result<(), InstallError> {
+    use std::io::Write;
+
+    let log_path = dir.join("install.log");
+    let mut log = std::fs::File::create(&log_path).map_err(InstallError::SetupLog)?;
+    for step in steps {
+        eprintln!("Running setup: {}", step);
+        writeln!(log, "$ {}", step).map_err(InstallError::SetupLog)?;
+        let stdout = log.try_clone().map_err(InstallError::SetupLog)?;
+        let stderr = log.try_clone().map_err(InstallError::SetupLog)?;
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(step)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .status();
+        let failure = match status {
+            Ok(status) if status.success() => continue,
+            Ok(status) => SetupFailure::Status(status),
+            Err(e) => SetupFailure::Spawn(e),
+        };
+        drop(log);
+        let log = match std::fs::rename(&log_path, kept) {
+            Ok(()) => kept.to_path_buf(),
+            Err(_) => log_path,
+        };
+        return Err(InstallError::Setup {
+            step: step.clone(),
+            failure,
+            log,
+        });
     }
+    // A log left by an earlier failed attempt no longer applies
+    let _ = std::fs::remove_file(kept);
     Ok(())
 }
 
@@ -178,7 +242,7 @@ pub fn update_index_add(
 
     if scope == crate::discovery::Scope::System && is_elevated() {
         // Already root from re_exec; write directly
-        std::fs::write(&index_path, output).map_err(InstallError::WriteIndex)?;
+        write_atomic(&index_path, output.as_bytes()).map_err(InstallError::WriteIndex)?;
     } else if scope == crate::discovery::Scope::System {
         let temp = std::env::temp_dir().join(format!("dmcp-index-{}.json", std::process::id()));
         std::fs::write(&temp, &output).map_err(InstallError::WriteIndex)?;
@@ -190,49 +254,74 @@ pub fn update_index_add(
             .map_err(InstallError::WriteIndex)?;
         let _ = std::fs::remove_file(&temp);
         if !status.success() {
-            return Err(InstallError::WriteIndex(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "pkexec cp failed",
-            )));
+            return Err(InstallError::WriteIndex(std::io::Error::other("pkexec cp failed")));
         }
     } else {
-        std::fs::write(&index_path, output).map_err(InstallError::WriteIndex)?;
+        write_atomic(&index_path, output.as_bytes()).map_err(InstallError::WriteIndex)?;
     }
 
     Ok(())
 }
 
+/// Write via a temp file in the same directory and rename, so readers never see a partial file.
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
 #[derive(Debug)]
 pub enum InstallError {
     NoSources,
     ServerNotFound,
-    InvalidRegistry,
-    UnsupportedTransport,
-    HttpClient(reqwest::Error),
-    FetchFailed(reqwest::Error),
+    MissingSource,
+    Source(SourceError),
+    Registry(RegistryError),
     CreateDir(std::io::Error),
-    GitFailed(std::io::Error),
-    CopyFailed(std::io::Error),
     Serialize(serde_json::Error),
     WriteManifest(std::io::Error),
+    Secrets(SecretsError),
+    SetupLog(std::io::Error),
+    Setup {
+        step: String,
+        failure: SetupFailure,
+        log: PathBuf,
+    },
+    Commit(std::io::Error),
     ParseIndex(serde_json::Error),
     WriteIndex(std::io::Error),
 }
 
+/// Why a setup step failed.
+#[derive(Debug)]
+pub enum SetupFailure {
+    Spawn(std::io::Error),
+    Status(ExitStatus),
+}
+
 impl std::fmt::Display for InstallError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             InstallError::NoSources => write!(f, "No registry sources configured"),
             InstallError::ServerNotFound => write!(f, "Server not found in any registry"),
-            InstallError::InvalidRegistry => write!(f, "Invalid registry or server entry"),
-            InstallError::UnsupportedTransport => write!(f, "Unsupported transport type"),
-            InstallError::HttpClient(e) => write!(f, "HTTP client error: {}", e),
-            InstallError::FetchFailed(e) => write!(f, "Failed to fetch registry: {}", e),
+            InstallError::MissingSource => write!(f, "Registry entry has a stdio transport but no `source`"),
+            InstallError::Source(e) => write!(f, "{}", e),
+            InstallError::Registry(e) => write!(f, "{}", e),
             InstallError::CreateDir(e) => write!(f, "Failed to create directory: {}", e),
-            InstallError::GitFailed(e) => write!(f, "Git operation failed: {}", e),
-            InstallError::CopyFailed(e) => write!(f, "Failed to copy files: {}", e),
             InstallError::Serialize(e) => write!(f, "Failed to serialize: {}", e),
             InstallError::WriteManifest(e) => write!(f, "Failed to write manifest: {}", e),
+            InstallError::Secrets(e) => write!(f, "{}", e),
+            InstallError::SetupLog(e) => write!(f, "Failed to write install log: {}", e),
+            InstallError::Setup { step, failure, log } => {
+                match failure {
+                    SetupFailure::Spawn(e) => write!(f, "Setup step `{}` could not be started: {}", step, e)?,
+                    SetupFailure::Status(s) => write!(f, "Setup step `{}` failed ({})", step, s)?,
+                }
+                write!(f, "; see {}", log.display())
+            }
+            InstallError::Commit(e) => write!(f, "Failed to move install into place: {}", e),
             InstallError::ParseIndex(e) => write!(f, "Failed to parse index: {}", e),
             InstallError::WriteIndex(e) => write!(f, "Failed to write index: {}", e),
         }
@@ -257,10 +346,7 @@ pub fn uninstall(paths: &Paths, id: &str) -> Result<(), UninstallError> {
             .status()
             .map_err(UninstallError::RmFailed)?;
         if !status.success() {
-            return Err(UninstallError::RmFailed(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "pkexec rm -rf failed",
-            )));
+            return Err(UninstallError::RmFailed(std::io::Error::other("pkexec rm -rf failed")));
         }
     } else {
         std::fs::remove_dir_all(&install_dir).map_err(UninstallError::RmFailed)?;
@@ -274,6 +360,9 @@ pub fn uninstall(paths: &Paths, id: &str) -> Result<(), UninstallError> {
     };
 
     update_index_remove(&index_path, id, scope)?;
+    // Only this user's secrets and overlay; other users' are theirs to clean up
+    let _ = secrets::delete(paths, id);
+    let _ = crate::config::delete_user_config(paths, id);
 
     Ok(())
 }
@@ -293,7 +382,7 @@ fn update_index_remove(
 
     if scope == crate::discovery::Scope::System && is_elevated() {
         // Already root from re_exec; write directly
-        std::fs::write(index_path, output).map_err(UninstallError::WriteIndex)?;
+        write_atomic(index_path, output.as_bytes()).map_err(UninstallError::WriteIndex)?;
     } else if scope == crate::discovery::Scope::System {
         let temp = std::env::temp_dir().join(format!("dmcp-index-{}.json", std::process::id()));
         std::fs::write(&temp, &output).map_err(UninstallError::WriteIndex)?;
@@ -305,13 +394,10 @@ fn update_index_remove(
             .map_err(UninstallError::WriteIndex)?;
         let _ = std::fs::remove_file(&temp);
         if !status.success() {
-            return Err(UninstallError::WriteIndex(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "pkexec cp failed",
-            )));
+            return Err(UninstallError::WriteIndex(std::io::Error::other("pkexec cp failed")));
         }
     } else {
-        std::fs::write(index_path, output).map_err(UninstallError::WriteIndex)?;
+        write_atomic(index_path, output.as_bytes()).map_err(UninstallError::WriteIndex)?;
     }
 
     Ok(())
@@ -353,8 +439,8 @@ fn rfc3339_now() -> String {
 }
 
 fn epoch_to_datetime(secs: i64) -> (i64, u32, u32, u32, u32, u32) {
-    let days = (secs / 86400) as i64;
-    let time = (secs % 86400 + 86400) % 86400;
+    let days = secs / 86400;
+    let time = secs.rem_euclid(86400);
     let hour = (time / 3600) as u32;
     let min = ((time % 3600) / 60) as u32;
     let sec = (time % 60) as u32;
@@ -365,7 +451,7 @@ fn epoch_to_datetime(secs: i64) -> (i64, u32, u32, u32, u32, u32) {
 fn days_to_ymd(days: i64) -> (i64, u32, u32) {
     let days = days + 719468; // epoch adjust
     let era = days / 146097;
-    let day_of_era = (days - era * 146097) as i64;
+    let day_of_era = days - era * 146097;
     let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
     let year = year_of_era + era * 400;
     let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
@@ -384,3 +470,152 @@ fn doy_to_md(doy: u32) -> (u32, u32) {
     }
     (12, 31)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::Scope;
+    use crate::test_support::paths;
+    use serde_json::json;
+
+    const ID: &str = "com.example.notes";
+
+    /// Entry with a sensitive `token`, given the value `new`.
+    fn entry(transport: Value) -> RegistryEntry {
+        RegistryEntry::from_value(json!({
+            "id": ID,
+            "name": "Notes",
+            "version": "1.0.0",
+            "transports": [transport],
+            "configurableProperties": [{"key": "token", "sensitive": true}],
+            "config": {"token": "new"}
+        }))
+        .unwrap()
+    }
+
+    fn remote() -> Value {
+        json!({"type": "streamable_http", "url": "https://notes.example.com/mcp"})
+    }
+
+    fn stored_token(paths: &Paths) -> Option<Value> {
+        secrets::load(paths, ID).unwrap().remove("token")
+    }
+
+    #[test]
+    fn sensitive_config_goes_to_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        assert!(install(&paths, ID, Scope::User, Some(entry(remote())), false).unwrap().is_empty());
+
+        assert_eq!(stored_token(&paths), Some(json!("new")));
+        let manifest = std::fs::read_to_string(paths.user_install_dir().join(ID).join("manifest.json")).unwrap();
+        assert!(!manifest.contains("new"));
+    }
+
+    #[test]
+    fn system_install_leaves_secrets_out() {
+        // Otherwise the index is written through pkexec
+        if !is_elevated() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let skipped = install(&paths, ID, Scope::System, Some(entry(remote())), false).unwrap();
+
+        assert_eq!(skipped, ["token"]);
+        assert_eq!(stored_token(&paths), None);
+        let manifest = std::fs::read_to_string(paths.system_install_dir().join(ID).join("manifest.json")).unwrap();
+        assert!(!manifest.contains("new"));
+    }
+
+    #[test]
+    fn failed_staging_leaves_secrets_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        secrets::set(&paths, ID, serde_json::Map::from_iter([("token".to_string(), json!("old"))])).unwrap();
+
+        // A stdio entry without a `source` fails while staging
+        let stdio = json!({"type": "stdio", "command": "notes-server"});
+        let err = install(&paths, ID, Scope::User, Some(entry(stdio)), false).unwrap_err();
+        assert!(matches!(err, InstallError::MissingSource));
+        assert_eq!(stored_token(&paths), Some(json!("old")));
+    }
+
+    /// Stdio entry installed from a local directory, with `setup` steps.
+    fn stdio_with_setup(source: &Path, steps: &[&str]) -> RegistryEntry {
+        let mut server = entry(json!({"type": "stdio", "command": "./server"}));
+        server.source = serde_json::from_value(json!({"type": "local", "url": source.to_str().unwrap()})).unwrap();
+        server.setup = steps.iter().map(|s| s.to_string()).collect();
+        server
+    }
+
+    #[test]
+    fn failed_setup_rolls_back_and_keeps_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let source = dir.path().join("src");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("server"), "").unwrap();
+
+        install(&paths, ID, Scope::User, Some(stdio_with_setup(&source, &["echo v1 > built"])), true).unwrap();
+        let install_dir = paths.user_install_dir().join(ID);
+        assert_eq!(std::fs::read_to_string(install_dir.join("built")).unwrap(), "v1\n");
+
+        let steps = ["echo building v2", "echo compiler exploded >&2; exit 3", "echo unreachable"];
+        let err = install(&paths, ID, Scope::User, Some(stdio_with_setup(&source, &steps)), true).unwrap_err();
+        let InstallError::Setup { step, log, .. } = err else {
+            panic!("expected a setup error, got {:?}", err);
+        };
+        assert!(step.contains("exit 3"));
+        assert_eq!(log, paths.user_install_dir().join(format!("{}.install.log", ID)));
+        let log = std::fs::read_to_string(&log).unwrap();
+        assert!(log.contains("building v2"));
+        assert!(log.contains("compiler exploded"));
+        assert!(!log.contains("unreachable"));
+
+        // The previous install, its secrets and the index are untouched, and no staging is left behind
+        assert_eq!(std::fs::read_to_string(install_dir.join("built")).unwrap(), "v1\n");
+        assert_eq!(stored_token(&paths), Some(json!("new")));
+        assert!(discovery::get_server(&paths, ID).is_some());
+        let leftovers: Vec<_> = std::fs::read_dir(paths.user_install_dir())
+            .unwrap()
+            .filter_map(|e| e.ok()?.file_name().into_string().ok())
+            .filter(|n| n.contains("staging"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn failed_setup_never_writes_through_a_planted_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let source = dir.path().join("src");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(paths.user_install_dir()).unwrap();
+        let target = dir.path().join("target");
+        std::fs::write(&target, "precious").unwrap();
+        let kept = paths.user_install_dir().join(format!("{}.install.log", ID));
+        std::os::unix::fs::symlink(&target, &kept).unwrap();
+
+        assert!(install(&paths, ID, Scope::User, Some(stdio_with_setup(&source, &["false"])), true).is_err());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "precious");
+        assert!(!std::fs::symlink_metadata(&kept).unwrap().file_type().is_symlink());
+        assert!(!paths.user_install_dir().join(ID).exists());
+    }
+
+    #[test]
+    fn failed_commit_restores_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        // An index path that can't be replaced makes the last commit step fail
+        std::fs::create_dir_all(paths.user_install_dir().join("index.json")).unwrap();
+
+        assert!(install(&paths, ID, Scope::User, Some(entry(remote())), false).is_err());
+        assert_eq!(stored_token(&paths), None);
+        assert!(!paths.user_install_dir().join(ID).exists());
+
+        secrets::set(&paths, ID, serde_json::Map::from_iter([("token".to_string(), json!("old"))])).unwrap();
+        assert!(install(&paths, ID, Scope::User, Some(entry(remote())), false).is_err());
+        assert_eq!(stored_token(&paths), Some(json!("old")));
+    }
+}
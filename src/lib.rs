@@ -2,21 +2,45 @@
 //!
 //! Discovers, manages, and invokes MCP servers at user and system scope.
 
+pub mod bridge;
 pub mod browse;
 pub mod config;
 pub mod connect;
 pub mod discovery;
 pub mod elevation;
+pub mod gateway;
 pub mod install;
 pub mod models;
 pub mod paths;
+pub mod policy;
+pub mod protocol;
+pub mod registry;
+pub mod run;
+pub mod secrets;
+pub mod serve;
+pub mod source;
 pub mod sources;
+pub mod sse;
+pub mod streamable_http;
+pub mod update;
+pub mod websocket;
 
-pub use browse::{list_registry_servers, list_registry_servers_from_url, RegistryServer};
+#[cfg(test)]
+mod test_support;
+
+pub use bridge::bridge;
+pub use browse::{filter_registry_servers, find_registry_server, list_registry_servers, list_registry_servers_from_url, BrowseFilter, RegistryServer};
 pub use connect::connect;
-pub use config::set_config_value;
+pub use config::{get_config, set_config_value};
 pub use install::{fetch_server_from_registry, install, scope_from_registry_server, uninstall};
+pub use gateway::gateway;
 pub use discovery::{get_manifest_path, get_server, list_servers, ServerInfo};
-pub use models::{Index, Manifest};
+pub use models::{Index, Manifest, Registry, RegistryEntry};
 pub use paths::Paths;
+pub use policy::{Policy, PolicyFilter};
+pub use protocol::{Client, Connection, ProtocolError};
+pub use registry::{fetch_registry, load_registry, parse_registry, FetchMode, FetchStatus, RegistryError};
+pub use run::spawn_server;
+pub use serve::{serve, Listen};
 pub use sources::{add_source, list_sources, remove_source, SourceScope, SourcesError};
+pub use update::{list_outdated, update, Outdated, UpdateError, UpdateOutcome};
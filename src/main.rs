@@ -1,9 +1,12 @@
 //! dmcp - MCP Manager CLI
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use dmcp::config;
 use dmcp::elevation::{is_elevated, is_system_scope, re_exec_with_pkexec};
-use dmcp::{add_source, connect, discovery, fetch_server_from_registry, get_server, install, list_registry_servers, list_registry_servers_from_url, list_servers, list_sources, remove_source, scope_from_registry_server, set_config_value, uninstall, Paths};
+use dmcp::protocol::build_arguments;
+use dmcp::run::run_filtered;
+use dmcp::secrets;
+use dmcp::{add_source, bridge, connect, discovery, fetch_registry, fetch_server_from_registry, filter_registry_servers, find_registry_server, gateway, get_server, install, list_registry_servers, list_registry_servers_from_url, list_outdated, list_servers, list_sources, remove_source, scope_from_registry_server, serve, set_config_value, spawn_server, uninstall, update, BrowseFilter, Client, FetchMode, FetchStatus, Listen, Paths, Policy, PolicyFilter, UpdateOutcome};
 
 #[derive(Parser)]
 #[command(name = "dmcp")]
@@ -15,6 +18,10 @@ struct Cli {
     /// Enable debug output
     #[arg(short, long, global = true)]
     debug: bool,
+
+    /// Use cached registries only (no network)
+    #[arg(long, global = true)]
+    offline: bool,
 }
 
 #[derive(Subcommand)]
@@ -59,6 +66,12 @@ enum Commands {
         action: SourcesAction,
     },
 
+    /// Manage the per-user secret store for sensitive config values
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
+
     /// Install an MCP server from registry
     Install {
         /// Server ID to install
@@ -67,6 +80,93 @@ enum Commands {
         /// Install to system scope (requires elevation)
         #[arg(long)]
         system: bool,
+
+        /// Config key=value (repeatable, overrides --config-file and registry config)
+        #[arg(short, long, value_parser = parse_config)]
+        config: Vec<(String, String)>,
+
+        /// Read config values from a JSON object file (-c values take precedence)
+        #[arg(long, value_name = "FILE")]
+        config_file: Option<std::path::PathBuf>,
+
+        /// Accept config keys the server doesn't declare
+        #[arg(long)]
+        force: bool,
+
+        /// Fail instead of prompting for missing required config
+        #[arg(long)]
+        non_interactive: bool,
+
+        /// Run the entry's setup commands without asking
+        #[arg(long, conflicts_with = "no_setup")]
+        trust: bool,
+
+        /// Install without running the entry's setup commands
+        #[arg(long)]
+        no_setup: bool,
+    },
+
+    /// Run an installed stdio server, wiring stdin/stdout straight through
+    Run {
+        /// Server ID to run
+        id: String,
+    },
+
+    /// Expose a remote (Streamable HTTP/SSE/WebSocket) server as a stdio MCP server
+    Bridge {
+        /// Server ID to bridge
+        id: String,
+    },
+
+    /// Run a single stdio MCP server that aggregates installed servers (tools as <id>__<name>)
+    Gateway {
+        /// Server IDs to include (default: all installed)
+        ids: Vec<String>,
+    },
+
+    /// Serve an installed stdio server over HTTP (Streamable HTTP at /mcp, SSE at /sse)
+    Serve {
+        /// Server ID to serve
+        id: String,
+
+        /// Listen on 127.0.0.1:<port>
+        #[arg(long, conflicts_with = "socket", required_unless_present = "socket")]
+        port: Option<u16>,
+
+        /// Listen on a Unix domain socket instead
+        #[arg(long)]
+        socket: Option<std::path::PathBuf>,
+
+        /// Share one server process between all sessions (default: one per session)
+        #[arg(long)]
+        shared: bool,
+    },
+
+    /// List the tools an installed server exposes (starts the server)
+    Tools {
+        /// Server ID
+        id: String,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Call a tool on an installed server
+    Call {
+        /// Server ID
+        id: String,
+
+        /// Tool name
+        tool: String,
+
+        /// Tool argument key=value (repeatable; values are parsed as JSON unless the schema says string)
+        #[arg(short, long = "arg", value_parser = parse_config)]
+        arg: Vec<(String, String)>,
+
+        /// Output the raw result as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Uninstall an MCP server
@@ -75,9 +175,52 @@ enum Commands {
         id: String,
     },
 
+    /// List installed servers with a newer version in a registry
+    Outdated {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Update installed servers to the registry version, keeping their config
+    #[command(alias = "upgrade")]
+    Update {
+        /// Server ID to update
+        #[arg(conflicts_with = "all", required_unless_present = "all")]
+        id: Option<String>,
+
+        /// Update every outdated server
+        #[arg(long)]
+        all: bool,
+
+        /// Config key=value (repeatable, overrides --config-file and the kept config)
+        #[arg(short, long, value_parser = parse_config, conflicts_with = "all")]
+        config: Vec<(String, String)>,
+
+        /// Read config values from a JSON object file (-c values take precedence)
+        #[arg(long, value_name = "FILE", conflicts_with = "all")]
+        config_file: Option<std::path::PathBuf>,
+
+        /// Accept config keys the server doesn't declare
+        #[arg(long, conflicts_with = "all")]
+        force: bool,
+
+        /// Fail instead of prompting for missing required config
+        #[arg(long)]
+        non_interactive: bool,
+
+        /// Run the entry's setup commands without asking
+        #[arg(long, conflicts_with = "no_setup")]
+        trust: bool,
+
+        /// Install without running the entry's setup commands
+        #[arg(long)]
+        no_setup: bool,
+    },
+
     /// Connect to a remote server. Fetches manifest from URL if valid JSON; otherwise treats URL as raw endpoint.
     Connect {
-        /// URL to manifest.json (fetched and used) or raw SSE/WebSocket endpoint (fallback)
+        /// URL to manifest.json (fetched and used) or raw Streamable HTTP/SSE/WebSocket endpoint (fallback)
         url: String,
 
         /// Override server ID
@@ -100,6 +243,10 @@ enum Commands {
         #[arg(short, long, value_parser = parse_config)]
         config: Vec<(String, String)>,
 
+        /// HTTP header "Name: value" for remote transports (repeatable; values may use ${config.key})
+        #[arg(short = 'H', long = "header", value_parser = parse_header)]
+        header: Vec<(String, String)>,
+
         /// Install to system scope (requires elevation)
         #[arg(long)]
         system: bool,
@@ -118,6 +265,40 @@ enum Commands {
         #[arg(long)]
         system: bool,
 
+        /// Only servers whose id, name, summary or description contains TEXT (case-insensitive)
+        #[arg(long, value_name = "TEXT")]
+        search: Option<String>,
+
+        /// Only servers in this category (e.g. mcp-database)
+        #[arg(long)]
+        category: Option<String>,
+
+        /// Only servers offering this transport
+        #[arg(long, value_parser = ["stdio", "sse", "streamable_http", "websocket"])]
+        transport: Option<String>,
+
+        /// Only servers whose author contains this text (case-insensitive)
+        #[arg(long)]
+        author: Option<String>,
+
+        /// Only servers that are installed (user or system)
+        #[arg(long, conflicts_with = "not_installed")]
+        installed: bool,
+
+        /// Only servers that are not installed
+        #[arg(long)]
+        not_installed: bool,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show details of a registry entry before installing it
+    Show {
+        /// Server ID (e.g. com.example.calculator)
+        id: String,
+
         /// Output as JSON
         #[arg(long)]
         json: bool,
@@ -137,6 +318,10 @@ enum ConfigAction {
         /// Output as JSON
         #[arg(long)]
         json: bool,
+
+        /// Show sensitive values instead of masking them
+        #[arg(long)]
+        reveal: bool,
     },
 
     /// Set a config value
@@ -144,9 +329,93 @@ enum ConfigAction {
         /// Config key
         key: String,
 
-        /// Config value
+        /// Config value (checked against the server's configurableProperties)
         value: String,
+
+        /// Set a key the server doesn't declare
+        #[arg(long)]
+        force: bool,
+
+        /// For a system-scope server, change the shared manifest (needs root)
+        /// instead of your own overlay
+        #[arg(long)]
+        system: bool,
+    },
+
+    /// Remove a config value so it falls back to the default
+    Unset {
+        /// Config key
+        key: String,
+
+        /// For a system-scope server, change the shared manifest (needs root)
+        #[arg(long)]
+        system: bool,
+    },
+
+    /// Edit the merged config in $EDITOR; changes are validated on save
+    Edit {
+        /// For a system-scope server, change the shared manifest (needs root)
+        #[arg(long)]
+        system: bool,
+    },
+
+    /// Print the values set for this server (defaults omitted)
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ConfigFormat::Json)]
+        format: ConfigFormat,
+
+        /// Leave out sensitive values
+        #[arg(long)]
+        no_secrets: bool,
+
+        /// Write to a file (mode 0600) instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
     },
+
+    /// Load config values from a JSON or dotenv file (`-` for stdin)
+    Import {
+        /// File to read
+        file: std::path::PathBuf,
+
+        /// Input format (detected from the content if omitted)
+        #[arg(long, value_enum)]
+        format: Option<ConfigFormat>,
+
+        /// Skip sensitive values
+        #[arg(long)]
+        no_secrets: bool,
+
+        /// Accept keys the server doesn't declare
+        #[arg(long)]
+        force: bool,
+
+        /// For a system-scope server, change the shared manifest (needs root)
+        #[arg(long)]
+        system: bool,
+    },
+}
+
+/// File format of `config export` and `config import`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ConfigFormat {
+    /// JSON object of key/value pairs
+    Json,
+    /// KEY=value lines
+    Dotenv,
+}
+
+#[derive(Subcommand)]
+enum SecretsAction {
+    /// Move plain-text sensitive values out of installed manifests into the store
+    Migrate,
+
+    /// Encrypt the store with a passphrase (new secrets are encrypted too)
+    Encrypt,
+
+    /// Decrypt the store back to plain 0600 files
+    Decrypt,
 }
 
 #[derive(Subcommand)]
@@ -189,12 +458,24 @@ enum SourcesAction {
         #[arg(long)]
         system: bool,
     },
+
+    /// Re-download registries into the local cache (conditional requests)
+    Refresh {
+        /// Refresh user-scope sources only
+        #[arg(long)]
+        user: bool,
+
+        /// Refresh system-scope sources only
+        #[arg(long)]
+        system: bool,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
     let paths = Paths::resolve();
     let debug = cli.debug;
+    let offline = cli.offline;
 
     match cli.command {
         Commands::Paths => {
@@ -204,10 +485,15 @@ fn main() {
             let system_index = paths.system_install_dir().join("index.json");
             println!("User index exists:  {}", user_index.exists());
             println!("System index exists: {}", system_index.exists());
+            println!("User policy:  {}", paths.user_policy_path().display());
+            println!("System policy: {}", paths.system_policy_path().display());
+            println!("Registry cache: {}", paths.registry_cache_dir().display());
+            println!("Secrets dir: {}", paths.secrets_dir().display());
+            println!("User config dir: {}", paths.user_config_dir().display());
         }
         Commands::List { user, system, json } => {
-            let include_user = user || (!user && !system);
-            let include_system = system || (!user && !system);
+            let include_user = user || !system;
+            let include_system = system || !user;
             let servers = list_servers(&paths, include_user, include_system, debug);
 
             if json {
@@ -223,11 +509,16 @@ fn main() {
         }
         Commands::Info { id, json } => {
             match get_server(&paths, &id) {
-                Some((manifest, scope)) => {
+                Some((mut manifest, scope)) => {
                     let scope_str = match scope {
                         dmcp::discovery::Scope::User => "user",
                         dmcp::discovery::Scope::System => "system",
                     };
+                    // Show the effective config, with stored secrets masked
+                    if let Err(e) = config::apply_user_config(&paths, &id, &mut manifest, false) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
                     if json {
                         let output = serde_json::to_string_pretty(&manifest).unwrap();
                         println!("{output}");
@@ -242,36 +533,135 @@ fn main() {
             }
         }
         Commands::Config { id, action } => match action {
-            ConfigAction::Set { key, value } => {
-                match set_config_value(&paths, &id, &key, &value) {
-                    Ok(()) => println!("Set {} = {}", key, value),
-                    Err(config::SetConfigError::WriteFailed(_, manifest_path)) if !is_elevated() => {
-                        if is_system_scope(&manifest_path, paths.system_install_dir()) {
-                            re_exec_with_pkexec();
-                        } else {
-                            eprintln!("Error: Failed to write manifest (permission denied)");
+            ConfigAction::Set { key, value, force, system } => {
+                match set_config_value(&paths, &id, &key, &value, force, system) {
+                    Ok(v) => println!("Set {} = {}", key, config::value_to_string(&v)),
+                    Err(e) => exit_config_write_error(&paths, e),
+                }
+            }
+            ConfigAction::Unset { key, system } => {
+                match config::unset_config_values(&paths, &id, std::slice::from_ref(&key), system) {
+                    Ok(removed) if removed.is_empty() => {
+                        eprintln!("Config key not set: {}", key);
+                        std::process::exit(1);
+                    }
+                    Ok(_) => println!("Unset {}", key),
+                    Err(e) => exit_config_write_error(&paths, e),
+                }
+            }
+            ConfigAction::Edit { system } => edit_config(&paths, &id, system),
+            ConfigAction::Export { format, no_secrets, output } => {
+                let values = match config::export_config(&paths, &id, !no_secrets) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let text = match format {
+                    ConfigFormat::Dotenv => config::to_dotenv(&values),
+                    ConfigFormat::Json => format!("{}\n", serde_json::to_string_pretty(&values).unwrap()),
+                };
+                match output {
+                    Some(path) => {
+                        use std::io::Write;
+                        use std::os::unix::fs::OpenOptionsExt;
+                        let written = std::fs::OpenOptions::new()
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .mode(0o600)
+                            .open(&path)
+                            .and_then(|mut f| f.write_all(text.as_bytes()));
+                        if let Err(e) = written {
+                            eprintln!("Error: Failed to write {}: {}", path.display(), e);
                             std::process::exit(1);
                         }
+                        println!("Exported {} value(s) to {}", values.len(), path.display());
                     }
+                    None => print!("{}", text),
+                }
+            }
+            ConfigAction::Import { file, format, no_secrets, force, system } => {
+                let content = if file.as_os_str() == "-" {
+                    std::io::read_to_string(std::io::stdin())
+                } else {
+                    std::fs::read_to_string(&file)
+                };
+                let content = match content {
+                    Ok(c) => c,
                     Err(e) => {
-                        eprintln!("Error: {}", e);
+                        eprintln!("Error: Failed to read {}: {}", file.display(), e);
+                        std::process::exit(1);
+                    }
+                };
+                let format = format.unwrap_or(if content.trim_start().starts_with('{') {
+                    ConfigFormat::Json
+                } else {
+                    ConfigFormat::Dotenv
+                });
+                let parsed = match format {
+                    ConfigFormat::Json => match serde_json::from_str(&content) {
+                        Ok(serde_json::Value::Object(map)) => Ok(map),
+                        Ok(_) => Err("expected a JSON object".to_string()),
+                        Err(e) => Err(e.to_string()),
+                    },
+                    ConfigFormat::Dotenv => config::parse_dotenv(&content).map_err(|line| format!("invalid dotenv line {}", line)),
+                };
+                let mut values = match parsed {
+                    Ok(v) => v,
+                    Err(e) => {
+                        eprintln!("Error: {}: {}", file.display(), e);
                         std::process::exit(1);
                     }
+                };
+                if no_secrets {
+                    if let Some((manifest, _)) = get_server(&paths, &id) {
+                        for p in manifest.configurable_properties.iter().filter(|p| p.sensitive) {
+                            values.remove(&p.key);
+                        }
+                    }
+                }
+                match config::set_config_values(&paths, &id, values, force, system) {
+                    Ok(stored) => println!("Imported {} value(s)", stored.len()),
+                    Err(e) => exit_config_write_error(&paths, e),
                 }
             }
-            ConfigAction::Get { key, json } => {
+            ConfigAction::Get { key, json, reveal } => {
                 match get_server(&paths, &id) {
-                    Some((manifest, _)) => {
+                    Some((mut manifest, _)) => {
+                        // Merged view: defaults < manifest < user overlay < secrets; sensitive values masked
+                        if let Err(e) = config::apply_user_config(&paths, &id, &mut manifest, reveal) {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(1);
+                        }
+                        let mut values = config::resolve_config(&manifest);
+                        if !reveal {
+                            for p in manifest.configurable_properties.iter().filter(|p| p.sensitive) {
+                                if let Some(v) = values.get_mut(&p.key) {
+                                    *v = secrets::MASK.into();
+                                }
+                            }
+                        }
+                        let current: serde_json::Map<_, _> = values.clone().into_iter().collect();
+                        let missing: Vec<_> = config::missing_required(&manifest.configurable_properties, &current)
+                            .into_iter()
+                            .map(|p| p.key.as_str())
+                            .collect();
                         if let Some(k) = key {
-                            match manifest.config.get(&k) {
+                            match values.get(&k) {
                                 Some(v) => {
                                     if json {
                                         println!("{}", serde_json::to_string_pretty(v).unwrap());
                                     } else {
-                                        let val: String = v.as_str().map(String::from).unwrap_or_else(|| v.to_string());
+                                        let val = config::value_to_string(v);
                                         println!("{}", val);
                                     }
                                 }
+                                None if missing.contains(&k.as_str()) => {
+                                    eprintln!("Config key not set: {} (required)", k);
+                                    std::process::exit(1);
+                                }
                                 None => {
                                     eprintln!("Config key not found: {}", k);
                                     std::process::exit(1);
@@ -279,17 +669,20 @@ fn main() {
                             }
                         } else {
                             if json {
-                                let output = serde_json::to_string_pretty(&manifest.config).unwrap();
+                                let output = serde_json::to_string_pretty(&values).unwrap();
                                 println!("{output}");
                             } else {
-                                if manifest.config.is_empty() {
+                                if values.is_empty() {
                                     println!("No config set.");
                                 } else {
-                                    for (k, v) in &manifest.config {
-                                        let val: String = v.as_str().map(String::from).unwrap_or_else(|| v.to_string());
+                                    for (k, v) in &values {
+                                        let val = config::value_to_string(v);
                                         println!("{} = {}", k, val);
                                     }
                                 }
+                                if !missing.is_empty() {
+                                    println!("Missing required: {}", missing.join(", "));
+                                }
                             }
                         }
                     }
@@ -300,17 +693,65 @@ fn main() {
                 }
             }
         },
+        Commands::Secrets { action } => match action {
+            SecretsAction::Migrate => {
+                let migration = match secrets::migrate(&paths) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                for (id, key) in &migration.moved {
+                    println!("Moved {} {}", id, key);
+                }
+                for (id, key) in &migration.skipped {
+                    eprintln!(
+                        "Warning: {} is installed system-wide, so its {} is shared by all users and was left in place. \
+                         Set your own with `dmcp config {} set {} <value>`; remove the shared copy with \
+                         `dmcp config {} unset {} --system`.",
+                        id, key, id, key, id, key
+                    );
+                }
+                if migration.moved.is_empty() && migration.skipped.is_empty() {
+                    println!("No plain-text secrets found.");
+                }
+            }
+            SecretsAction::Encrypt => {
+                let pass = match read_new_passphrase() {
+                    Some(p) => p,
+                    None => {
+                        eprintln!("Error: Passphrases do not match");
+                        std::process::exit(1);
+                    }
+                };
+                match secrets::encrypt_store(&paths, &pass) {
+                    Ok(n) => println!("Encrypted secret store ({} servers)", n),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            SecretsAction::Decrypt => match secrets::decrypt_store(&paths) {
+                Ok(n) => println!("Decrypted secret store ({} servers)", n),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            },
+        },
         Commands::Sources { action } => match action {
             SourcesAction::List { user, system } => {
-                let include_user = user || (!user && !system);
-                let include_system = system || (!user && !system);
+                let include_user = user || !system;
+                let include_system = system || !user;
                 let sources = list_sources(&paths, include_user, include_system);
                 if sources.is_empty() {
                     println!("No registry sources configured.");
                     println!("Add URLs to ~/.config/mcp/sources.list or /etc/mcp/sources.list");
                     return;
                 }
-                println!("{:<8} {}", "SCOPE", "URL");
+                println!("{:<8} URL", "SCOPE");
                 println!("{}", "-".repeat(80));
                 for (url, scope) in sources {
                     let scope_str = match scope {
@@ -356,9 +797,36 @@ fn main() {
                     }
                 }
             }
+            SourcesAction::Refresh { user, system } => {
+                let include_user = user || !system;
+                let include_system = system || !user;
+                let mut failed = false;
+                for (url, _) in list_sources(&paths, include_user, include_system) {
+                    match fetch_registry(&paths, &url, FetchMode::Refresh) {
+                        Ok((_, FetchStatus::NotModified)) => println!("Not modified {}", url),
+                        Ok(_) => println!("Updated {}", url),
+                        Err(e) => {
+                            eprintln!("Error: {}", e);
+                            failed = true;
+                        }
+                    }
+                }
+                if failed {
+                    std::process::exit(1);
+                }
+            }
         },
-        Commands::Install { id, system } => {
-            let server = match fetch_server_from_registry(&paths, &id) {
+        Commands::Install {
+            id,
+            system,
+            config,
+            config_file,
+            force,
+            non_interactive,
+            trust,
+            no_setup,
+        } => {
+            let mut server = match fetch_server_from_registry(&paths, &id, offline) {
                 Ok(s) => s,
                 Err(e) => {
                     eprintln!("Error: {}", e);
@@ -373,12 +841,119 @@ fn main() {
             if scope == dmcp::discovery::Scope::System && !is_elevated() {
                 re_exec_with_pkexec();
             }
-            match install(&paths, &id, scope, Some(server)) {
-                Ok(()) => println!("Installed {}", id),
+            if let Err(e) = collect_install_config(&paths, &mut server, &config, config_file.as_deref(), force, non_interactive) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            let run_setup = confirm_setup(&server, trust, no_setup);
+            match install(&paths, &id, scope, Some(server), run_setup) {
+                Ok(skipped) => {
+                    println!("Installed {}", id);
+                    warn_skipped_secrets(&id, &skipped);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Run { id } => {
+            let policy = match Policy::load(&paths) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            // Only proxy the stdio stream when a policy has something to enforce
+            if policy.applies_to(&id) {
+                match run_filtered(&paths, &id, PolicyFilter::new(policy, &id)) {
+                    Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            let mut child = match spawn_server(&paths, &id) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            match child.wait() {
+                Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Bridge { id } => {
+            if let Err(e) = bridge(&paths, &id) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Gateway { ids } => {
+            if let Err(e) = gateway(&paths, &ids, debug) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Serve { id, port, socket, shared } => {
+            let listen = match (port, socket) {
+                (_, Some(path)) => Listen::Unix(path),
+                (Some(port), None) => Listen::Tcp(port),
+                (None, None) => unreachable!("clap requires --port or --socket"),
+            };
+            if let Err(e) = serve(&paths, &id, &listen, shared) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Tools { id, json } => {
+            let tools = match Client::connect(&paths, &id).and_then(|mut c| c.list_tools()) {
+                Ok(t) => t,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if json {
+                let output = serde_json::to_string_pretty(&tools).unwrap();
+                println!("{output}");
+            } else if tools.is_empty() {
+                println!("No tools.");
+            } else {
+                print_tools_table(&tools);
+            }
+        }
+        Commands::Call { id, tool, arg, json } => {
+            let result = Client::connect(&paths, &id).and_then(|mut c| {
+                let tools = c.list_tools()?;
+                let schema = tools
+                    .iter()
+                    .find(|t| t.get("name").and_then(|n| n.as_str()) == Some(tool.as_str()));
+                let arguments = build_arguments(schema, &arg);
+                c.call_tool(&tool, arguments)
+            });
+            let result = match result {
+                Ok(r) => r,
                 Err(e) => {
                     eprintln!("Error: {}", e);
                     std::process::exit(1);
                 }
+            };
+            if json {
+                let output = serde_json::to_string_pretty(&result).unwrap();
+                println!("{output}");
+            } else {
+                print_tool_result(&result);
+            }
+            if result.get("isError").and_then(|v| v.as_bool()) == Some(true) {
+                std::process::exit(1);
             }
         }
         Commands::Uninstall { id } => {
@@ -395,6 +970,82 @@ fn main() {
                 }
             }
         }
+        Commands::Outdated { json } => {
+            let (outdated, errors) = list_outdated(&paths, offline);
+            for e in &errors {
+                eprintln!("Warning: {}", e);
+            }
+            if json {
+                let output = serde_json::to_string_pretty(&outdated).unwrap();
+                println!("{output}");
+            } else if outdated.is_empty() {
+                println!("All installed servers are up to date.");
+            } else {
+                print_outdated_table(&outdated);
+            }
+        }
+        Commands::Update {
+            id,
+            all,
+            config,
+            config_file,
+            force,
+            non_interactive,
+            trust,
+            no_setup,
+        } => {
+            let ids: Vec<(String, dmcp::discovery::Scope)> = match id {
+                Some(id) => match get_server(&paths, &id) {
+                    Some((_, scope)) => vec![(id, scope)],
+                    None => {
+                        eprintln!("Error: Server not installed: {}", id);
+                        std::process::exit(1);
+                    }
+                },
+                None => {
+                    let (outdated, errors) = list_outdated(&paths, offline);
+                    for e in &errors {
+                        eprintln!("Warning: {}", e);
+                    }
+                    outdated.into_iter().map(|o| (o.id, o.scope)).collect()
+                }
+            };
+            if ids.iter().any(|(_, scope)| *scope == dmcp::discovery::Scope::System) && !is_elevated() {
+                re_exec_with_pkexec();
+            }
+            if all && ids.is_empty() {
+                println!("All installed servers are up to date.");
+                return;
+            }
+            let mut failed = false;
+            for (id, _) in &ids {
+                // With --all the registries were just fetched by list_outdated; reuse the cache
+                let collect = |s: &mut dmcp::RegistryEntry| {
+                    if let Err(e) = collect_install_config(&paths, s, &config, config_file.as_deref(), force, non_interactive) {
+                        eprintln!("Error: {}: {}", id, e);
+                        std::process::exit(1);
+                    }
+                };
+                match update(&paths, id, offline || all, collect, |s| confirm_setup(s, trust, no_setup)) {
+                    Ok(UpdateOutcome::Updated {
+                        from,
+                        to,
+                        skipped_secrets,
+                    }) => {
+                        println!("Updated {} {} -> {}", id, from, to);
+                        warn_skipped_secrets(id, &skipped_secrets);
+                    }
+                    Ok(UpdateOutcome::UpToDate(v)) => println!("{} is up to date ({})", id, v),
+                    Err(e) => {
+                        eprintln!("Error: {}: {}", id, e);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
         Commands::Connect {
             url,
             id,
@@ -402,6 +1053,7 @@ fn main() {
             summary,
             version,
             config,
+            header,
             system,
         } => {
             let scope = if system {
@@ -412,7 +1064,7 @@ fn main() {
             if scope == dmcp::discovery::Scope::System && !is_elevated() {
                 re_exec_with_pkexec();
             }
-            let config_ref: Vec<(String, String)> = config.iter().cloned().collect();
+            let config_ref: Vec<(String, String)> = config.to_vec();
             match connect(
                 &paths,
                 &url,
@@ -421,6 +1073,7 @@ fn main() {
                 summary.as_deref(),
                 version.as_deref(),
                 &config_ref,
+                &header,
                 scope,
             ) {
                 Ok(id) => println!("Connected {}", id),
@@ -430,25 +1083,46 @@ fn main() {
                 }
             }
         }
-        Commands::Browse { url, user, system, json } => {
+        Commands::Browse {
+            url,
+            user,
+            system,
+            search,
+            category,
+            transport,
+            author,
+            installed,
+            not_installed,
+            json,
+        } => {
             let (servers, errors): (Vec<_>, Vec<_>) = if let Some(ref u) = url {
-                match list_registry_servers_from_url(u) {
-                    Ok(s) => (s, vec![]),
+                match list_registry_servers_from_url(&paths, u, offline) {
+                    Ok(found) => found,
                     Err(e) => {
                         eprintln!("Error: {}", e);
                         std::process::exit(1);
                     }
                 }
             } else {
-                let include_user = user || (!user && !system);
-                let include_system = system || (!user && !system);
-                list_registry_servers(&paths, include_user, include_system)
+                let include_user = user || !system;
+                let include_system = system || !user;
+                list_registry_servers(&paths, include_user, include_system, offline)
             };
 
             for e in &errors {
                 eprintln!("Warning: {}", e);
             }
 
+            let filter = BrowseFilter {
+                search,
+                category,
+                transport,
+                author,
+                installed: (installed || not_installed).then_some(installed),
+            };
+            let total = servers.len();
+            let servers = filter_registry_servers(&paths, servers, &filter);
+
             if json {
                 let output = serde_json::to_string_pretty(&servers).unwrap();
                 println!("{output}");
@@ -457,13 +1131,39 @@ fn main() {
                     println!("No registry sources configured. Add one with: dmcp sources add <url>");
                     return;
                 }
-                if servers.is_empty() {
+                if total == 0 {
                     println!("No servers found in registries.");
                     return;
                 }
+                if servers.is_empty() {
+                    println!("No servers match the given filters ({} in registries).", total);
+                    return;
+                }
                 print_browse_table(&servers);
             }
         }
+        Commands::Show { id, json } => {
+            let server = match find_registry_server(&paths, &id, offline) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if json {
+                let output = serde_json::to_string_pretty(&server).unwrap();
+                println!("{output}");
+            } else {
+                let installed = get_server(&paths, &id).map(|(m, scope)| {
+                    let scope_str = match scope {
+                        dmcp::discovery::Scope::User => "user",
+                        dmcp::discovery::Scope::System => "system",
+                    };
+                    format!("{} ({})", m.version.as_deref().unwrap_or("?"), scope_str)
+                });
+                print_show_output(&server, installed.as_deref());
+            }
+        }
     }
 }
 
@@ -482,6 +1182,13 @@ fn parse_config(s: &str) -> Result<(String, String), String> {
     }
 }
 
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.trim().to_string())),
+        _ => Err("header must be \"Name: value\"".to_string()),
+    }
+}
+
 fn format_tools(tools: &[serde_json::Value]) -> String {
     tools
         .iter()
@@ -510,6 +1217,7 @@ fn format_transports(transports: &[dmcp::models::Transport]) -> String {
                 format!("stdio ({command} {args_str})")
             }
             dmcp::models::Transport::Sse { url, .. } => format!("sse ({url})"),
+            dmcp::models::Transport::StreamableHttp { url, .. } => format!("streamable_http ({url})"),
             dmcp::models::Transport::WebSocket { ws_url, .. } => format!("websocket ({ws_url})"),
         })
         .collect::<Vec<_>>()
@@ -556,14 +1264,443 @@ fn print_info_output(manifest: &dmcp::Manifest, scope_str: &str) {
     if let Some(ref dir) = manifest.install_dir {
         println!("{}Install:     {}", INDENT, dir);
     }
+    if let Some(ref from) = manifest.installed_from {
+        let mut line = match (&from.kind, &from.package) {
+            (Some(kind), Some(package)) => {
+                format!("{} {} {}", kind, package, from.version.as_deref().unwrap_or("?"))
+            }
+            _ => String::new(),
+        };
+        if !from.url.is_empty() {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&from.url);
+        }
+        if let Some(r) = &from.git_ref {
+            line.push_str(&format!(" @ {}", r));
+        }
+        if let Some(c) = &from.commit {
+            line.push_str(&format!(" ({})", c));
+        }
+        if let Some(h) = &from.sha256 {
+            line.push_str(&format!(" (sha256 {})", h));
+        }
+        println!("{}From:        {}", INDENT, line);
+    }
     if !manifest.config.is_empty() {
         for (k, v) in &manifest.config {
-            let val: String = v.as_str().map(String::from).unwrap_or_else(|| v.to_string());
+            let val = config::value_to_string(v);
             println!("{}Config.{}:   {}", INDENT, k, val);
         }
     }
 }
 
+fn print_outdated_table(outdated: &[dmcp::Outdated]) {
+    const INDENT: &str = "        ";
+
+    for o in outdated {
+        let scope = match o.scope {
+            dmcp::discovery::Scope::User => "user",
+            dmcp::discovery::Scope::System => "system",
+        };
+        println!("{}", o.id);
+        println!("{}Installed: {} ({})", INDENT, o.installed, scope);
+        println!("{}Available: {}", INDENT, o.available);
+        println!("{}Source:    {}", INDENT, o.source);
+        println!();
+    }
+}
+
+/// New store passphrase from `DMCP_SECRETS_PASSPHRASE`, or asked twice on the terminal.
+/// `None` if the two entries differ.
+fn read_new_passphrase() -> Option<String> {
+    if let Ok(p) = std::env::var(secrets::PASSPHRASE_ENV) {
+        if !p.is_empty() {
+            return Some(p);
+        }
+    }
+    let first = rpassword::prompt_password("New secrets passphrase: ").unwrap_or_default();
+    let second = rpassword::prompt_password("Repeat passphrase: ").unwrap_or_default();
+    (first == second && !first.is_empty()).then_some(first)
+}
+
+/// Build the install-time `config` from the registry's values, `--config-file` and
+/// `-c`, prompting for required properties that are still missing and applying
+/// defaults. The user's values are checked as `config set` checks them: undeclared
+/// keys need `force`. Fails if required values are missing and the user can't be asked.
+fn collect_install_config(
+    paths: &Paths,
+    server: &mut dmcp::RegistryEntry,
+    values: &[(String, String)],
+    config_file: Option<&std::path::Path>,
+    force: bool,
+    non_interactive: bool,
+) -> Result<(), String> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let mut config = match server.extra.remove("config") {
+        Some(serde_json::Value::Object(c)) => c,
+        _ => serde_json::Map::new(),
+    };
+    let mut given = match config_file {
+        Some(path) => config::read_config_file(path).map_err(|e| e.to_string())?,
+        None => serde_json::Map::new(),
+    };
+    for (k, v) in values {
+        given.insert(k.clone(), serde_json::Value::String(v.clone()));
+    }
+    config::merge_config_values(&server.configurable_properties, &mut config, given, force).map_err(|e| e.to_string())?;
+
+    // Secrets kept from a previous install count as set
+    let stored = secrets::keys(paths, &server.id).unwrap_or_default();
+    let missing: Vec<_> = config::missing_required(&server.configurable_properties, &config)
+        .into_iter()
+        .filter(|p| !stored.contains(&p.key))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        if non_interactive || !std::io::stdin().is_terminal() {
+            let keys: Vec<_> = missing.iter().map(|p| p.key.as_str()).collect();
+            return Err(format!(
+                "Missing required config for {}: {} (pass -c key=value or --config-file)",
+                server.id,
+                keys.join(", ")
+            ));
+        }
+        eprintln!("{} needs the following configuration:", server.id);
+        for prop in missing {
+            if let Some(d) = prop.description.as_deref() {
+                eprintln!("{}", d);
+            }
+            let prompt = format!("{}: ", prop.label.as_deref().unwrap_or(&prop.key));
+            let value = loop {
+                let answer = if prop.sensitive {
+                    rpassword::prompt_password(&prompt).unwrap_or_default()
+                } else {
+                    eprint!("{}", prompt);
+                    let _ = std::io::stderr().flush();
+                    let mut line = String::new();
+                    if std::io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+                        eprintln!();
+                        std::process::exit(1);
+                    }
+                    line
+                };
+                let answer = answer.trim();
+                if answer.is_empty() {
+                    eprintln!("{} is required.", prop.key);
+                    continue;
+                }
+                match config::parse_value(&prop, answer) {
+                    Ok(v) => break v,
+                    Err(e) => eprintln!("{}", e),
+                }
+            };
+            config.insert(prop.key.clone(), value);
+        }
+    }
+
+    config::apply_defaults(&server.configurable_properties, &mut config);
+    server.extra.insert("config".to_string(), serde_json::Value::Object(config));
+    Ok(())
+}
+
+/// Report a failed config change and exit. Writes to a system-scope manifest are
+/// retried under pkexec.
+fn exit_config_write_error(paths: &Paths, e: config::SetConfigError) -> ! {
+    if let config::SetConfigError::WriteFailed(_, path) = &e {
+        if !is_elevated() && is_system_scope(path, paths.system_install_dir()) {
+            re_exec_with_pkexec();
+        }
+    }
+    eprintln!("Error: {}", e);
+    std::process::exit(1);
+}
+
+/// `dmcp config <id> edit`: open the merged config (sensitive values masked) in
+/// `$VISUAL`/`$EDITOR` and apply what changed. Removed keys are unset. Invalid
+/// input can be fixed in the editor again.
+fn edit_config(paths: &Paths, id: &str, system: bool) {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let fail = |msg: String, tmp: &std::path::Path| -> ! {
+        let _ = std::fs::remove_file(tmp);
+        eprintln!("Error: {}", msg);
+        std::process::exit(1);
+    };
+
+    let Some((mut manifest, _)) = get_server(paths, id) else {
+        eprintln!("Server not found: {}", id);
+        std::process::exit(1);
+    };
+    if let Err(e) = config::apply_user_config(paths, id, &mut manifest, false) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let mut current: serde_json::Map<_, _> = config::resolve_config(&manifest).into_iter().collect();
+    let sensitive: Vec<&str> = manifest
+        .configurable_properties
+        .iter()
+        .filter(|p| p.sensitive)
+        .map(|p| p.key.as_str())
+        .collect();
+    for key in &sensitive {
+        if let Some(v) = current.get_mut(*key) {
+            *v = secrets::MASK.into();
+        }
+    }
+
+    // A fresh 0600 file with a random name; removed on drop or by `fail`
+    let tmp_file = match tempfile::Builder::new().prefix(&format!("dmcp-{}-", id)).suffix(".json").tempfile() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("Error: Failed to create a temp file: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let tmp = tmp_file.path();
+    if let Err(e) = writeln!(tmp_file.as_file(), "{}", serde_json::to_string_pretty(&current).unwrap()) {
+        fail(format!("Failed to write {}: {}", tmp.display(), e), tmp);
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    loop {
+        // Through the shell, so EDITOR may carry arguments (`code --wait`)
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(tmp)
+            .status();
+        match status {
+            Ok(s) if s.success() => {}
+            Ok(s) => fail(format!("{} exited with {}; config unchanged", editor, s), tmp),
+            Err(e) => fail(format!("Failed to run {}: {}", editor, e), tmp),
+        }
+
+        let problem = match config::read_config_file(tmp) {
+            Err(e) => e.to_string(),
+            Ok(edited) => {
+                let changed: serde_json::Map<_, _> = edited
+                    .iter()
+                    .filter(|(k, v)| current.get(*k) != Some(*v))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                let removed: Vec<String> = current.keys().filter(|k| !edited.contains_key(*k)).cloned().collect();
+                if changed.is_empty() && removed.is_empty() {
+                    println!("No changes.");
+                    return;
+                }
+                // Everything is validated before the single write, so a bad value changes nothing
+                match config::change_config_values(paths, id, changed, &removed, false, system) {
+                    Err(e @ (config::SetConfigError::Invalid(_) | config::SetConfigError::UnknownKey { .. })) => e.to_string(),
+                    Err(e) => {
+                        let _ = std::fs::remove_file(tmp);
+                        exit_config_write_error(paths, e);
+                    }
+                    Ok((stored, unset)) => {
+                        for (k, v) in &stored {
+                            let shown = if sensitive.contains(&k.as_str()) { secrets::MASK.to_string() } else { config::value_to_string(v) };
+                            println!("Set {} = {}", k, shown);
+                        }
+                        unset.iter().for_each(|k| println!("Unset {}", k));
+                        return;
+                    }
+                }
+            }
+        };
+
+        eprintln!("Error: {}", problem);
+        if !std::io::stdin().is_terminal() {
+            fail("config unchanged".to_string(), tmp);
+        }
+        eprint!("Edit again? [Y/n] ");
+        let _ = std::io::stderr().flush();
+        let mut answer = String::new();
+        let _ = std::io::stdin().lock().read_line(&mut answer);
+        if matches!(answer.trim().to_lowercase().as_str(), "n" | "no") {
+            fail("config unchanged".to_string(), tmp);
+        }
+    }
+}
+
+/// Whether `server`'s setup commands may run: decided by `--trust`/`--no-setup`,
+/// otherwise by asking on the terminal. Exits if the user declines or can't be asked.
+/// Sensitive values given for a system-wide install are not stored anywhere;
+/// tell the user how to set their own.
+fn warn_skipped_secrets(id: &str, keys: &[String]) {
+    for key in keys {
+        eprintln!(
+            "Warning: {} is installed system-wide, so its {} was not stored. \
+             Each user sets their own with `dmcp config {} set {} <value>`.",
+            id, key, id, key
+        );
+    }
+}
+
+fn confirm_setup(server: &dmcp::RegistryEntry, trust: bool, no_setup: bool) -> bool {
+    const INDENT: &str = "        ";
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if server.setup.is_empty() || trust {
+        return true;
+    }
+    if no_setup {
+        eprintln!("Skipping setup commands for {} (--no-setup)", server.id);
+        return false;
+    }
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "Error: {} runs setup commands after install; pass --trust to run them or --no-setup to skip them",
+            server.id
+        );
+        std::process::exit(1);
+    }
+    eprintln!("{} runs these commands in its install directory:", server.id);
+    for step in &server.setup {
+        eprintln!("{}{}", INDENT, step);
+    }
+    eprint!("Run them? [y/N] ");
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    let _ = std::io::stdin().lock().read_line(&mut answer);
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        return true;
+    }
+    eprintln!("Aborted. Pass --no-setup to install without running them.");
+    std::process::exit(1);
+}
+
+fn print_show_output(server: &dmcp::RegistryServer, installed: Option<&str>) {
+    const INDENT: &str = "        ";
+
+    let e = &server.entry;
+    println!("{}", e.id);
+    println!("{}Name:         {}", INDENT, e.name);
+    println!("{}Version:      {}", INDENT, e.version);
+    if let Some(v) = installed {
+        println!("{}Installed:    {}", INDENT, v);
+    }
+    if !e.summary.is_empty() {
+        println!("{}Summary:      {}", INDENT, e.summary);
+    }
+    if let Some(d) = e.description.as_deref().filter(|x| !x.is_empty()) {
+        println!("{}Description:", INDENT);
+        for line in d.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            println!("{}{}{}", INDENT, INDENT, line);
+        }
+    }
+    if let Some(a) = e.author.as_deref().filter(|x| !x.is_empty()) {
+        println!("{}Author:       {}", INDENT, a);
+    }
+    if let Some(l) = &e.license {
+        let license = match (l.name.as_deref(), l.url.as_deref()) {
+            (Some(n), Some(u)) => format!("{} ({})", n, u),
+            (Some(n), None) => n.to_string(),
+            (None, Some(u)) => u.to_string(),
+            (None, None) => String::new(),
+        };
+        if !license.is_empty() {
+            println!("{}License:      {}", INDENT, license);
+        }
+    }
+    for (label, value) in [
+        ("Homepage:    ", &e.homepage),
+        ("Bug URL:     ", &e.bug_url),
+        ("Donate:      ", &e.donation_url),
+        ("Released:    ", &e.release_date),
+    ] {
+        if let Some(v) = value.as_deref().filter(|x| !x.is_empty()) {
+            println!("{}{} {}", INDENT, label, v);
+        }
+    }
+    if let Some(size) = e.size {
+        println!("{}Size:         {}", INDENT, format_size(size));
+    }
+    if !e.categories.is_empty() {
+        println!("{}Categories:   {}", INDENT, e.categories.join(", "));
+    }
+    if !e.capabilities.is_empty() {
+        println!("{}Capabilities: {}", INDENT, e.capabilities.join(", "));
+    }
+    if !e.permissions.is_empty() {
+        println!("{}Permissions:  {}", INDENT, e.permissions.join(", "));
+    }
+    if !e.tools.is_empty() {
+        println!("{}Tools:        {}", INDENT, format_tools(&e.tools));
+    }
+    println!("{}Transports:   {}", INDENT, format_transports(&e.transports));
+    if let Some(scope) = e.scope {
+        let scope_str = match scope {
+            dmcp::discovery::Scope::User => "user",
+            dmcp::discovery::Scope::System => "system",
+        };
+        println!("{}Scope:        {}", INDENT, scope_str);
+    }
+    if let Some(src) = e.source.as_ref().filter(|s| !s.is_empty()) {
+        let mut repo = format!("{} {}", src.kind.as_deref().unwrap_or("git"), src.url.as_deref().unwrap_or("?"));
+        if let Some(c) = src.commit.as_deref().or(src.git_ref.as_deref()) {
+            repo.push_str(&format!(" @ {}", c));
+        }
+        if let Some(p) = src.path.as_deref().filter(|p| !p.is_empty()) {
+            repo.push_str(&format!(" (path {})", p));
+        }
+        println!("{}Repository:   {}", INDENT, repo);
+    }
+    if !e.setup.is_empty() {
+        println!("{}Setup:", INDENT);
+        for step in &e.setup {
+            println!("{}{}{}", INDENT, INDENT, step);
+        }
+    }
+    let (required, optional): (Vec<_>, Vec<_>) = e.configurable_properties.iter().partition(|p| p.required);
+    for (title, props) in [("Required config:", required), ("Optional config:", optional)] {
+        if props.is_empty() {
+            continue;
+        }
+        println!("{}{}", INDENT, title);
+        for p in props {
+            let mut line = p.key.clone();
+            if p.sensitive {
+                line.push_str(" (sensitive)");
+            }
+            if let Some(d) = p.description.as_deref().or(p.label.as_deref()) {
+                line.push_str(&format!(" - {}", d));
+            }
+            if let Some(d) = &p.default {
+                line.push_str(&format!(" [default: {}]", config::value_to_string(d)));
+            }
+            println!("{}{}{}", INDENT, INDENT, line);
+        }
+    }
+    if let Some(c) = e.changelog.as_deref().filter(|x| !x.is_empty()) {
+        println!("{}Changelog:", INDENT);
+        for line in c.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            println!("{}{}{}", INDENT, INDENT, line);
+        }
+    }
+    println!("{}Source:       {}", INDENT, server.source);
+}
+
+/// Human-readable byte count (1024-based).
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 fn print_list_table(servers: &[dmcp::ServerInfo]) {
     const INDENT: &str = "        ";
 
@@ -586,14 +1723,186 @@ fn print_browse_table(servers: &[dmcp::RegistryServer]) {
     const INDENT: &str = "        ";
 
     for s in servers {
-        println!("{}", s.id);
-        println!("{}Name:      {}", INDENT, s.name);
-        println!("{}Version:   {}", INDENT, s.version);
-        println!("{}Transport: {}", INDENT, s.transport);
-        if !s.summary.is_empty() {
-            println!("{}Summary:   {}", INDENT, s.summary.lines().next().unwrap_or("").trim());
+        let e = &s.entry;
+        println!("{}", e.id);
+        println!("{}Name:      {}", INDENT, e.name);
+        println!("{}Version:   {}", INDENT, e.version);
+        println!("{}Transport: {}", INDENT, e.transport_type());
+        if !e.summary.is_empty() {
+            println!("{}Summary:   {}", INDENT, e.summary.lines().next().unwrap_or("").trim());
         }
         println!("{}Source:    {}", INDENT, s.source);
         println!();
     }
 }
+
+fn print_tools_table(tools: &[serde_json::Value]) {
+    const INDENT: &str = "        ";
+
+    for t in tools {
+        println!("{}", t.get("name").and_then(|n| n.as_str()).unwrap_or("?"));
+        if let Some(d) = t.get("description").and_then(|d| d.as_str()).filter(|x| !x.is_empty()) {
+            println!("{}{}", INDENT, d.lines().next().unwrap_or("").trim());
+        }
+        let schema = t.get("inputSchema");
+        let required: Vec<&str> = schema
+            .and_then(|s| s.get("required"))
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+            .unwrap_or_default();
+        if let Some(props) = schema.and_then(|s| s.get("properties")).and_then(|p| p.as_object()) {
+            let params: Vec<String> = props
+                .iter()
+                .map(|(k, v)| {
+                    let ty = v.get("type").and_then(|t| t.as_str()).unwrap_or("any");
+                    if required.contains(&k.as_str()) {
+                        format!("{k}: {ty} (required)")
+                    } else {
+                        format!("{k}: {ty}")
+                    }
+                })
+                .collect();
+            if !params.is_empty() {
+                println!("{}Params: {}", INDENT, params.join(", "));
+            }
+        }
+        println!();
+    }
+}
+
+fn print_tool_result(result: &serde_json::Value) {
+    let content = result
+        .get("content")
+        .and_then(|c| c.as_array())
+        .cloned()
+        .unwrap_or_default();
+    for item in &content {
+        match item.get("type").and_then(|t| t.as_str()) {
+            Some("text") => println!("{}", item.get("text").and_then(|t| t.as_str()).unwrap_or("")),
+            Some("resource") => {
+                let res = item.get("resource");
+                match res.and_then(|r| r.get("text")).and_then(|t| t.as_str()) {
+                    Some(text) => println!("{}", text),
+                    None => println!(
+                        "[resource: {}]",
+                        res.and_then(|r| r.get("uri")).and_then(|u| u.as_str()).unwrap_or("?")
+                    ),
+                }
+            }
+            Some(other) => println!(
+                "[{}: {}]",
+                other,
+                item.get("mimeType").and_then(|m| m.as_str()).unwrap_or("?")
+            ),
+            None => println!("{}", item),
+        }
+    }
+    if let Some(structured) = result.get("structuredContent").filter(|_| content.is_empty()) {
+        println!("{}", serde_json::to_string_pretty(structured).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn scratch(root: &std::path::Path) -> Paths {
+        Paths {
+            user_sources: root.join("user/sources.list"),
+            user_install_dir: root.join("user/servers"),
+            system_sources: root.join("system/sources.list"),
+            system_install_dir: root.join("system/servers"),
+            user_policy: root.join("user/policy.json"),
+            system_policy: root.join("system/policy.json"),
+            registry_cache: root.join("cache"),
+            secrets: root.join("user/secrets"),
+            user_config: root.join("user/config"),
+        }
+    }
+
+    /// Entry with a required `root`, an integer `port` defaulting to 8080 and an optional `mode`.
+    fn notes(config: Value) -> dmcp::RegistryEntry {
+        dmcp::RegistryEntry::from_value(json!({
+            "id": "com.example.notes",
+            "name": "Notes",
+            "version": "1.0.0",
+            "transports": [{"type": "stdio", "command": "notes-server"}],
+            "configurableProperties": [
+                {"key": "root", "required": true},
+                {"key": "port", "type": "integer", "default": 8080},
+                {"key": "mode"}
+            ],
+            "config": config
+        }))
+        .unwrap()
+    }
+
+    fn pairs(values: &[(&str, &str)]) -> Vec<(String, String)> {
+        values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn collect(server: &mut dmcp::RegistryEntry, values: &[(&str, &str)], file: Option<&std::path::Path>, force: bool) -> Result<(), String> {
+        let dir = tempfile::tempdir().unwrap();
+        collect_install_config(&scratch(dir.path()), server, &pairs(values), file, force, true)
+    }
+
+    #[test]
+    fn install_config_fills_defaults() {
+        let mut server = notes(json!({}));
+        collect(&mut server, &[("root", "/srv/notes")], None, false).unwrap();
+        assert_eq!(server.extra["config"], json!({"root": "/srv/notes", "port": 8080}));
+    }
+
+    #[test]
+    fn install_config_layers_and_types_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.json");
+        std::fs::write(&file, r#"{"root": "/from/file", "port": "9000", "mode": "fast"}"#).unwrap();
+        let mut server = notes(json!({"mode": "slow"}));
+        collect(&mut server, &[("root", "/from/flag")], Some(&file), false).unwrap();
+        assert_eq!(server.extra["config"], json!({"root": "/from/flag", "port": 9000, "mode": "fast"}));
+    }
+
+    #[test]
+    fn install_config_rejects_invalid_values() {
+        let mut server = notes(json!({}));
+        let err = collect(&mut server, &[("root", "/srv"), ("port", "80a")], None, false).unwrap_err();
+        assert!(err.contains("port"));
+    }
+
+    #[test]
+    fn install_config_rejects_unknown_keys_without_force() {
+        let mut server = notes(json!({}));
+        let err = collect(&mut server, &[("root", "/srv"), ("prot", "9000")], None, false).unwrap_err();
+        assert!(err.contains("prot") && err.contains("--force"));
+
+        let mut server = notes(json!({}));
+        collect(&mut server, &[("root", "/srv"), ("prot", "9000")], None, true).unwrap();
+        assert_eq!(server.extra["config"]["prot"], "9000");
+    }
+
+    #[test]
+    fn non_interactive_install_reports_missing_required() {
+        let mut server = notes(json!({}));
+        let err = collect(&mut server, &[("mode", "fast")], None, false).unwrap_err();
+        assert!(err.contains("Missing required config"));
+        assert!(err.contains("root"));
+
+        // A value from the registry counts as given
+        let mut server = notes(json!({"root": "/srv/notes"}));
+        collect(&mut server, &[], None, false).unwrap();
+    }
+
+    #[test]
+    fn format_size_picks_a_unit() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+        // GiB is the largest unit
+        assert_eq!(format_size(2048 * 1024 * 1024 * 1024), "2048.0 GiB");
+    }
+}
@@ -1,6 +1,219 @@
-//! Data structures for index and manifest files.
+//! Data structures for registry, index and manifest files.
+
+use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::discovery::Scope;
+
+/// Registry file served from a source URL (see MCP-REGISTRY-GUIDE.md).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Registry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+    #[serde(default)]
+    pub servers: Vec<RegistryEntry>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// One server in a registry's `servers` array. Fields not modelled here are
+/// kept in `extra` so an entry written out as a manifest loses nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryEntry {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub summary: String,
+    pub version: String,
+    pub transports: Vec<Transport>,
+    /// Where to fetch a local server from; absent or empty for remote servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bug_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub donation_url: Option<String>,
+    /// Freedesktop icon name or image URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+    /// Tool names or `{"name", "description"}` objects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configurable_properties: Vec<ConfigurableProperty>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Shell commands run in the install dir after the files are fetched (e.g. `npm ci`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub setup: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<License>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<String>,
+    /// Approximate size in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub screenshots: Vec<Screenshot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<String>,
+    /// Install scope requested by the registry (default user).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Scope>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl RegistryEntry {
+    /// Parse and validate one registry entry. The legacy single-transport form
+    /// (top-level `type` plus a `transport` object) is converted to `transports`.
+    pub fn from_value(mut value: Value) -> Result<Self, serde_json::Error> {
+        use serde::de::Error;
+
+        if let Some(obj) = value.as_object_mut() {
+            if !obj.contains_key("transports") {
+                if let (Some(Value::String(kind)), Some(Value::Object(mut transport))) =
+                    (obj.get("type").cloned(), obj.get("transport").cloned())
+                {
+                    transport.insert("type".to_string(), Value::String(kind));
+                    obj.remove("type");
+                    obj.remove("transport");
+                    obj.insert("transports".to_string(), Value::Array(vec![Value::Object(transport)]));
+                }
+            }
+        }
+
+        let entry: RegistryEntry = serde_json::from_value(value)?;
+        if entry.id.trim().is_empty() {
+            return Err(serde_json::Error::custom("`id` is empty"));
+        }
+        if entry.transports.is_empty() {
+            return Err(serde_json::Error::custom("`transports` is empty"));
+        }
+        if let Some(p) = entry.configurable_properties.iter().find(|p| p.enum_without_values()) {
+            return Err(serde_json::Error::custom(format!(
+                "configurable property `{}` has type enum but no `enum` list",
+                p.key
+            )));
+        }
+        Ok(entry)
+    }
+
+    /// Type name of the primary (first) transport.
+    pub fn transport_type(&self) -> &'static str {
+        self.transports.first().map(Transport::type_name).unwrap_or("unknown")
+    }
+}
+
+/// `source` of a registry entry. The `type` selects how it is fetched:
+/// `git` (default), `archive`, `local`, `npm`, `pypi` or `cargo` (see `source::fetch_source`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Source {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Project root within the fetched tree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Git tag or branch to check out (default: the remote's default branch).
+    #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Exact git commit to check out. Takes precedence over `ref`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Hex SHA-256 of the archive (required for `archive`, optional for `local` files).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Leading path components dropped from archive entries.
+    #[serde(rename = "stripComponents", default, skip_serializing_if = "Option::is_none")]
+    pub strip_components: Option<usize>,
+    /// Package name for `npm`, `pypi` and `cargo` sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// Package version for `npm`, `pypi` and `cargo` sources (default: latest).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Source {
+    /// A remote server's `source` is omitted or `{}`.
+    pub fn is_empty(&self) -> bool {
+        self.kind.is_none()
+            && self.url.is_none()
+            && self.path.is_none()
+            && self.git_ref.is_none()
+            && self.commit.is_none()
+            && self.sha256.is_none()
+            && self.strip_components.is_none()
+            && self.package.is_none()
+            && self.version.is_none()
+            && self.extra.is_empty()
+    }
+}
+
+/// `installedFrom` in a manifest: what a local server's files were fetched from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstalledFrom {
+    /// Source type for package installs (`npm`, `pypi`, `cargo`).
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Repository, archive or package file; empty for packages from their default registry.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Resolved commit SHA.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// SHA-256 of the archive the files were extracted from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// Installed package version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct License {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Screenshot as a plain URL or a `{"thumbnail", "url"}` object.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Screenshot {
+    Url(String),
+    Image {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thumbnail: Option<String>,
+    },
+}
 
 /// Index file at `<base>/mcp/installed/index.json`
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -30,8 +243,15 @@ pub struct Manifest {
     pub transports: Option<Vec<Transport>>,
     #[serde(default)]
     pub config: std::collections::HashMap<String, serde_json::Value>,
+    /// Extra environment for the spawned process. Values may use `${config.key}`.
+    #[serde(default)]
+    pub env: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub configurable_properties: Vec<ConfigurableProperty>,
     pub install_dir: Option<String>,
     #[serde(default)]
+    pub installed_from: Option<InstalledFrom>,
+    #[serde(default)]
     pub categories: Vec<String>,
     #[serde(default)]
     pub capabilities: Vec<String>,
@@ -41,25 +261,104 @@ pub struct Manifest {
     pub tools: Vec<serde_json::Value>,
 }
 
+/// Entry of `configurableProperties` in a registry entry or manifest.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigurableProperty {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    #[serde(default)]
+    pub sensitive: bool,
+    #[serde(default)]
+    pub required: bool,
+    /// Environment variable the value is exported as when the server is spawned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    /// Value type: `string` (default), `integer`, `boolean`, `url`, `enum` or `path`.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Regular expression the whole value must match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Allowed values.
+    #[serde(rename = "enum", default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<serde_json::Value>,
+    /// Lower bound for integers, or minimum length for strings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Upper bound for integers, or maximum length for strings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+impl ConfigurableProperty {
+    /// `type: "enum"` needs the allowed values; without them nothing could be set.
+    pub fn enum_without_values(&self) -> bool {
+        self.kind.as_deref() == Some("enum") && self.allowed.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Transport {
     Stdio {
         command: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         args: Option<Vec<String>>,
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         description: Option<String>,
     },
     Sse {
         url: String,
-        #[serde(default)]
+        /// HTTP headers sent with every request. Values may use `${config.key}`.
+        #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+        headers: std::collections::HashMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+    },
+    /// Single-endpoint HTTP transport (POST, optional SSE response).
+    StreamableHttp {
+        url: String,
+        /// HTTP headers sent with every request. Values may use `${config.key}`.
+        #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+        headers: std::collections::HashMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         description: Option<String>,
     },
     #[serde(rename = "websocket")]
     WebSocket {
         #[serde(rename = "wsUrl")]
         ws_url: String,
-        #[serde(default)]
+        /// HTTP headers sent with the upgrade request. Values may use `${config.key}`.
+        #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+        headers: std::collections::HashMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         description: Option<String>,
     },
 }
+
+impl Transport {
+    /// The `type` tag as written in JSON.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Transport::Stdio { .. } => "stdio",
+            Transport::Sse { .. } => "sse",
+            Transport::StreamableHttp { .. } => "streamable_http",
+            Transport::WebSocket { .. } => "websocket",
+        }
+    }
+
+    /// HTTP headers of a remote transport; `None` for stdio.
+    pub fn headers_mut(&mut self) -> Option<&mut HashMap<String, String>> {
+        match self {
+            Transport::Stdio { .. } => None,
+            Transport::Sse { headers, .. }
+            | Transport::StreamableHttp { headers, .. }
+            | Transport::WebSocket { headers, .. } => Some(headers),
+        }
+    }
+}
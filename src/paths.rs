@@ -11,6 +11,11 @@ pub struct Paths {
     pub user_install_dir: PathBuf,
     pub system_sources: PathBuf,
     pub system_install_dir: PathBuf,
+    pub user_policy: PathBuf,
+    pub system_policy: PathBuf,
+    pub registry_cache: PathBuf,
+    pub secrets: PathBuf,
+    pub user_config: PathBuf,
 }
 
 impl Paths {
@@ -36,12 +41,42 @@ impl Paths {
             Some(PathBuf::from("/usr/share/mcp/installed/")),
             "/usr/share/mcp/installed/",
         );
+        let user_policy = resolve_path(
+            "MCP_USER_POLICY_PATH",
+            dirs::config_dir().map(|p| p.join("mcp/policy.json")),
+            "~/.config/mcp/policy.json",
+        );
+        let system_policy = resolve_path(
+            "MCP_SYSTEM_POLICY_PATH",
+            Some(PathBuf::from("/etc/mcp/policy.json")),
+            "/etc/mcp/policy.json",
+        );
+        let registry_cache = resolve_path(
+            "MCP_REGISTRY_CACHE_DIR",
+            dirs::cache_dir().map(|p| p.join("mcp/registries")),
+            "~/.cache/mcp/registries/",
+        );
+        let secrets = resolve_path(
+            "MCP_SECRETS_DIR",
+            dirs::data_local_dir().map(|p| p.join("mcp/secrets")),
+            "~/.local/share/mcp/secrets/",
+        );
+        let user_config = resolve_path(
+            "MCP_USER_CONFIG_DIR",
+            dirs::config_dir().map(|p| p.join("mcp/config")),
+            "~/.config/mcp/config/",
+        );
 
         Self {
             user_sources,
             user_install_dir,
             system_sources,
             system_install_dir,
+            user_policy,
+            system_policy,
+            registry_cache,
+            secrets,
+            user_config,
         }
     }
 
@@ -64,6 +99,31 @@ impl Paths {
     pub fn system_install_dir(&self) -> &Path {
         &self.system_install_dir
     }
+
+    /// User tool policy path.
+    pub fn user_policy_path(&self) -> &Path {
+        &self.user_policy
+    }
+
+    /// System tool policy path (overrides user policy).
+    pub fn system_policy_path(&self) -> &Path {
+        &self.system_policy
+    }
+
+    /// Registry cache directory (one file per source URL).
+    pub fn registry_cache_dir(&self) -> &Path {
+        &self.registry_cache
+    }
+
+    /// Per-user secret store (one 0600 file per server).
+    pub fn secrets_dir(&self) -> &Path {
+        &self.secrets
+    }
+
+    /// Per-user config overlays (`<id>.json`), merged over manifest config.
+    pub fn user_config_dir(&self) -> &Path {
+        &self.user_config
+    }
 }
 
 fn resolve_path(
@@ -0,0 +1,323 @@
+//! Tool allow/deny policy (policy.json).
+//!
+//! Policies map server id globs to tool name globs:
+//!
+//! ```json
+//! { "servers": { "com.example.shell": { "deny": ["exec*"] }, "*": { "allow": ["read_*"] } } }
+//! ```
+//!
+//! A deny match always denies. A non-empty `allow` list denies everything it
+//! doesn't match. A tool is allowed only if neither the system nor the user
+//! policy denies it: users can narrow what the system allows, never widen it.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::paths::Paths;
+
+/// JSON-RPC error code for rejected `tools/call` requests.
+pub const DENIED_CODE: i64 = -32602;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PolicyFile {
+    #[serde(default)]
+    pub servers: HashMap<String, ToolRules>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ToolRules {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl PolicyFile {
+    /// `Some(allowed)` if any rule for this server decides the tool, `None` otherwise.
+    fn verdict(&self, server_id: &str, tool: &str) -> Option<bool> {
+        let rules: Vec<&ToolRules> = self
+            .servers
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern, server_id))
+            .map(|(_, r)| r)
+            .collect();
+        if rules.iter().any(|r| r.deny.iter().any(|p| glob_match(p, tool))) {
+            return Some(false);
+        }
+        if rules.iter().any(|r| r.allow.iter().any(|p| glob_match(p, tool))) {
+            return Some(true);
+        }
+        if rules.iter().any(|r| !r.allow.is_empty()) {
+            return Some(false);
+        }
+        None
+    }
+
+    fn applies_to(&self, server_id: &str) -> bool {
+        self.servers
+            .iter()
+            .any(|(pattern, r)| glob_match(pattern, server_id) && (!r.allow.is_empty() || !r.deny.is_empty()))
+    }
+}
+
+/// User and system policies combined.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub user: PolicyFile,
+    pub system: PolicyFile,
+}
+
+impl Policy {
+    /// Load both policy files. Missing files are empty; unreadable or invalid ones are errors
+    /// so a broken admin policy never silently allows everything.
+    pub fn load(paths: &Paths) -> Result<Self, PolicyError> {
+        Ok(Self {
+            user: load_file(paths.user_policy_path())?,
+            system: load_file(paths.system_policy_path())?,
+        })
+    }
+
+    /// A deny from either policy wins; a tool neither policy decides is allowed.
+    pub fn is_allowed(&self, server_id: &str, tool: &str) -> bool {
+        self.system.verdict(server_id, tool) != Some(false) && self.user.verdict(server_id, tool) != Some(false)
+    }
+
+    /// Whether any rule targets this server (so traffic needs filtering at all).
+    pub fn applies_to(&self, server_id: &str) -> bool {
+        self.system.applies_to(server_id) || self.user.applies_to(server_id)
+    }
+
+    /// Drop denied tools from a `tools/list` result.
+    pub fn filter_tools(&self, server_id: &str, tools: &mut Vec<Value>) {
+        tools.retain(|t| {
+            let name = t.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            self.is_allowed(server_id, name)
+        });
+    }
+
+    /// Error reply for a `tools/call` request that is denied, or `None` if it may proceed.
+    pub fn check_call(&self, server_id: &str, request: &Value) -> Option<Value> {
+        if request.get("method").and_then(|m| m.as_str()) != Some("tools/call") {
+            return None;
+        }
+        let tool = request
+            .get("params")
+            .and_then(|p| p.get("name"))
+            .and_then(|n| n.as_str())
+            .unwrap_or("");
+        if self.is_allowed(server_id, tool) {
+            return None;
+        }
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": DENIED_CODE, "message": format!("Tool denied by policy: {}", tool)}
+        }))
+    }
+}
+
+/// Applies a policy to a raw JSON-RPC stream between a client and one server.
+/// Tracks the client's `tools/list` request ids so the matching responses can be filtered.
+pub struct PolicyFilter {
+    policy: Policy,
+    server_id: String,
+    list_requests: HashSet<String>,
+}
+
+impl PolicyFilter {
+    pub fn new(policy: Policy, server_id: &str) -> Self {
+        Self {
+            policy,
+            server_id: server_id.to_string(),
+            list_requests: HashSet::new(),
+        }
+    }
+
+    /// Inspect a client message. Returns the message to forward (if any) and
+    /// error replies to send back to the client for denied calls.
+    pub fn client_message(&mut self, msg: Value) -> (Option<Value>, Vec<Value>) {
+        match msg {
+            Value::Array(batch) => {
+                let mut forward = Vec::new();
+                let mut replies = Vec::new();
+                for m in batch {
+                    let (f, r) = self.client_message(m);
+                    forward.extend(f);
+                    replies.extend(r);
+                }
+                ((!forward.is_empty()).then_some(Value::Array(forward)), replies)
+            }
+            msg => {
+                if let Some(reply) = self.policy.check_call(&self.server_id, &msg) {
+                    return (None, vec![reply]);
+                }
+                if msg.get("method").and_then(|m| m.as_str()) == Some("tools/list") {
+                    if let Some(id) = msg.get("id") {
+                        self.list_requests.insert(id.to_string());
+                    }
+                }
+                (Some(msg), Vec::new())
+            }
+        }
+    }
+
+    /// Filter a server message in place (responses to `tools/list`).
+    pub fn server_message(&mut self, msg: &mut Value) {
+        if let Value::Array(batch) = msg {
+            for m in batch {
+                self.server_message(m);
+            }
+            return;
+        }
+        if msg.get("method").is_some() {
+            return;
+        }
+        let Some(id) = msg.get("id").map(|v| v.to_string()) else {
+            return;
+        };
+        if self.list_requests.remove(&id) {
+            if let Some(tools) = msg.pointer_mut("/result/tools").and_then(|t| t.as_array_mut()) {
+                self.policy.filter_tools(&self.server_id, tools);
+            }
+        }
+    }
+}
+
+fn load_file(path: &Path) -> Result<PolicyFile, PolicyError> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| PolicyError::Invalid(path.to_path_buf(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(PolicyFile::default()),
+        Err(e) => Err(PolicyError::ReadFailed(path.to_path_buf(), e)),
+    }
+}
+
+/// Glob match supporting `*` (any run of characters) and `?` (one character).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Position of the last `*` and the text index it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[derive(Debug)]
+pub enum PolicyError {
+    ReadFailed(PathBuf, std::io::Error),
+    Invalid(PathBuf, serde_json::Error),
+}
+
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyError::ReadFailed(p, e) => write!(f, "Failed to read policy {}: {}", p.display(), e),
+            PolicyError::Invalid(p, e) => write!(f, "Invalid policy {}: {}", p.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(value: Value) -> PolicyFile {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn glob_wildcards_and_anchoring() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("read_*", "read_file"));
+        assert!(glob_match("read_*", "read_"));
+        assert!(glob_match("*_file", "read_file"));
+        assert!(glob_match("r*d*e", "read_file"));
+        assert!(glob_match("com.*.shell", "com.example.shell"));
+        assert!(glob_match("exe?", "exec"));
+        assert!(!glob_match("exe?", "exe"));
+        assert!(!glob_match("exe?", "execs"));
+        // Patterns match the whole name, not a substring
+        assert!(!glob_match("exec", "exec_shell"));
+        assert!(!glob_match("exec", "run_exec"));
+        assert!(!glob_match("read_*", "xread_file"));
+        assert!(!glob_match("*_file", "read_files"));
+    }
+
+    #[test]
+    fn deny_beats_allow() {
+        let policy = file(json!({"servers": {"*": {"allow": ["*"], "deny": ["exec*"]}}}));
+        assert_eq!(policy.verdict("com.example.shell", "exec_shell"), Some(false));
+        assert_eq!(policy.verdict("com.example.shell", "read_file"), Some(true));
+    }
+
+    #[test]
+    fn deny_in_any_matching_rule_wins() {
+        let policy = file(json!({"servers": {
+            "com.example.*": {"allow": ["exec"]},
+            "*.shell": {"deny": ["exec"]}
+        }}));
+        assert_eq!(policy.verdict("com.example.shell", "exec"), Some(false));
+        assert_eq!(policy.verdict("com.example.notes", "exec"), Some(true));
+    }
+
+    #[test]
+    fn allow_list_restricts_everything_else() {
+        let policy = file(json!({"servers": {"com.example.notes": {"allow": ["read_*"]}}}));
+        assert_eq!(policy.verdict("com.example.notes", "read_note"), Some(true));
+        assert_eq!(policy.verdict("com.example.notes", "delete_note"), Some(false));
+        // Other servers are not covered
+        assert_eq!(policy.verdict("com.example.shell", "delete_note"), None);
+        assert!(policy.applies_to("com.example.notes"));
+        assert!(!policy.applies_to("com.example.shell"));
+    }
+
+    #[test]
+    fn deny_from_either_layer_wins() {
+        let policy = Policy {
+            system: file(json!({"servers": {"*": {"allow": ["*"], "deny": ["format_disk"]}}})),
+            user: file(json!({"servers": {
+                "com.example.shell": {"deny": ["exec"]},
+                "com.example.notes": {"allow": ["exec", "format_disk"]}
+            }})),
+        };
+        // The user denies what the system allows
+        assert!(!policy.is_allowed("com.example.shell", "exec"));
+        assert!(policy.is_allowed("com.example.shell", "read_file"));
+        // The user can't allow what the system denies
+        assert!(!policy.is_allowed("com.example.notes", "format_disk"));
+        assert!(policy.is_allowed("com.example.notes", "exec"));
+    }
+
+    #[test]
+    fn user_allow_list_narrows_the_system_policy() {
+        let policy = Policy {
+            system: PolicyFile::default(),
+            user: file(json!({"servers": {"com.example.notes": {"allow": ["read_*"]}}})),
+        };
+        assert!(policy.is_allowed("com.example.notes", "read_note"));
+        assert!(!policy.is_allowed("com.example.notes", "delete_note"));
+        assert!(policy.is_allowed("com.example.shell", "delete_note"));
+        assert!(Policy::default().is_allowed("com.example.shell", "exec"));
+    }
+}
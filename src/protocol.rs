@@ -0,0 +1,634 @@
+//! MCP JSON-RPC client.
+//!
+//! A `Client` speaks MCP over any `Connection`. The stdio connection spawns the
+//! server's primary stdio transport and exchanges newline-delimited JSON;
+//! remote transports live in their own modules (`sse`, `streamable_http`,
+//! `websocket`).
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::config::{interpolate, resolve_config};
+use crate::discovery::{get_manifest_path, get_server};
+use crate::models::{Manifest, Transport};
+use crate::paths::Paths;
+use crate::policy::{Policy, PolicyError, DENIED_CODE};
+use crate::run::{build_command, RunError};
+use crate::sse::SseConnection;
+use crate::streamable_http::StreamableHttpConnection;
+use crate::websocket::WebSocketConnection;
+
+/// Protocol version sent in `initialize`. Servers may answer with an older one.
+pub const PROTOCOL_VERSION: &str = "2025-03-26";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A bidirectional JSON-RPC message channel to an MCP server.
+pub trait Connection: Send {
+    /// Send one JSON-RPC message (request, notification or response).
+    fn send(&mut self, message: &Value) -> Result<(), ProtocolError>;
+
+    /// Receive the next message, or `None` if nothing arrived within `timeout`.
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Value>, ProtocolError>;
+}
+
+/// Open a connection to an installed server using its primary transport.
+pub fn open_connection(paths: &Paths, id: &str) -> Result<Box<dyn Connection>, ProtocolError> {
+    let (mut manifest, _) = get_server(paths, id).ok_or(ProtocolError::ServerNotFound)?;
+    let manifest_path = get_manifest_path(paths, id).ok_or(ProtocolError::ServerNotFound)?;
+    crate::config::apply_user_config(paths, id, &mut manifest, true).map_err(|e| ProtocolError::Run(RunError::Config(e)))?;
+    connection_for_manifest(&manifest, &manifest_path)
+}
+
+/// Open a connection for a loaded manifest using its primary (first) transport.
+pub fn connection_for_manifest(
+    manifest: &Manifest,
+    manifest_path: &std::path::Path,
+) -> Result<Box<dyn Connection>, ProtocolError> {
+    let transports = manifest.transports.as_deref().unwrap_or_default();
+    match transports.first() {
+        Some(Transport::Stdio { .. }) => {
+            let cmd = build_command(manifest, manifest_path).map_err(ProtocolError::Run)?;
+            Ok(Box::new(StdioConnection::spawn(cmd)?))
+        }
+        Some(Transport::Sse { url, headers, .. }) => {
+            let headers = resolve_headers(manifest, headers)?;
+            Ok(Box::new(SseConnection::open(url, &headers)?))
+        }
+        Some(Transport::StreamableHttp { url, headers, .. }) => {
+            let headers = resolve_headers(manifest, headers)?;
+            Ok(Box::new(StreamableHttpConnection::open(url, &headers)?))
+        }
+        Some(Transport::WebSocket { ws_url, headers, .. }) => {
+            let headers = resolve_headers(manifest, headers)?;
+            Ok(Box::new(WebSocketConnection::open(ws_url, &headers)?))
+        }
+        None => Err(ProtocolError::UnsupportedTransport),
+    }
+}
+
+/// Transport headers with `${config.key}` placeholders filled from the manifest config.
+pub fn resolve_headers(
+    manifest: &Manifest,
+    headers: &std::collections::HashMap<String, String>,
+) -> Result<Vec<(String, String)>, ProtocolError> {
+    let config = resolve_config(manifest);
+    let mut out = headers
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), interpolate(v, &config, &manifest.configurable_properties)?)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ProtocolError::Run(RunError::Placeholder(e)))?;
+    out.sort();
+    Ok(out)
+}
+
+/// Connection to a child process over its stdin/stdout.
+pub struct StdioConnection {
+    child: Option<Child>,
+    stdin: Box<dyn Write + Send>,
+    rx: Receiver<Value>,
+}
+
+impl StdioConnection {
+    /// Spawn `cmd` with piped stdin/stdout. stderr stays attached to ours.
+    pub fn spawn(mut cmd: Command) -> Result<Self, ProtocolError> {
+        cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::inherit());
+        let mut child = cmd.spawn().map_err(|e| ProtocolError::Run(RunError::SpawnFailed(e)))?;
+        let stdin = child.stdin.take().ok_or(ProtocolError::Closed)?;
+        let stdout = child.stdout.take().ok_or(ProtocolError::Closed)?;
+        let mut conn = Self::from_streams(stdout, stdin);
+        conn.child = Some(child);
+        Ok(conn)
+    }
+
+    /// Exchange newline-delimited JSON over `reader` and `writer` (a child's
+    /// stdout and stdin, or any other pair of streams).
+    pub fn from_streams(reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else { break };
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                // Servers sometimes log to stdout; skip anything that isn't JSON
+                if let Ok(msg) = serde_json::from_str::<Value>(line) {
+                    if tx.send(msg).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            child: None,
+            stdin: Box::new(writer),
+            rx,
+        }
+    }
+}
+
+impl Connection for StdioConnection {
+    fn send(&mut self, message: &Value) -> Result<(), ProtocolError> {
+        let mut line = serde_json::to_string(message).map_err(ProtocolError::Serialize)?;
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).map_err(ProtocolError::Io)?;
+        self.stdin.flush().map_err(ProtocolError::Io)
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Value>, ProtocolError> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(msg) => Ok(Some(msg)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(ProtocolError::Closed),
+        }
+    }
+}
+
+impl Drop for StdioConnection {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// MCP client over a `Connection`.
+pub struct Client {
+    conn: Box<dyn Connection>,
+    next_id: u64,
+    timeout: Duration,
+    notifications: VecDeque<Value>,
+    server: Option<Value>,
+    /// Tool policy and the server id it is checked against.
+    policy: Option<(Policy, String)>,
+}
+
+impl Client {
+    pub fn new(conn: Box<dyn Connection>) -> Self {
+        Self {
+            conn,
+            next_id: 1,
+            timeout: DEFAULT_TIMEOUT,
+            notifications: VecDeque::new(),
+            server: None,
+            policy: None,
+        }
+    }
+
+    /// Apply a tool policy: denied tools are left out of `list_tools` and
+    /// `call_tool` refuses them without contacting the server.
+    pub fn with_policy(mut self, policy: Policy, server_id: &str) -> Self {
+        self.policy = Some((policy, server_id.to_string()));
+        self
+    }
+
+    /// Open a connection to an installed server and run the handshake.
+    /// The user and system tool policies are applied.
+    pub fn connect(paths: &Paths, id: &str) -> Result<Self, ProtocolError> {
+        let policy = Policy::load(paths).map_err(ProtocolError::Policy)?;
+        let mut client = Self::new(open_connection(paths, id)?).with_policy(policy, id);
+        client.initialize()?;
+        Ok(client)
+    }
+
+    /// Per-request timeout (default 60s).
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// `initialize` request followed by `notifications/initialized`. Returns the server's result.
+    pub fn initialize(&mut self) -> Result<Value, ProtocolError> {
+        let result = self.request(
+            "initialize",
+            Some(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": {"name": "dmcp", "version": env!("CARGO_PKG_VERSION")}
+            })),
+        )?;
+        self.notify("notifications/initialized", None)?;
+        self.server = Some(result.clone());
+        Ok(result)
+    }
+
+    /// Result of `initialize`, once the handshake has run.
+    pub fn server_info(&self) -> Option<&Value> {
+        self.server.as_ref()
+    }
+
+    pub fn ping(&mut self) -> Result<(), ProtocolError> {
+        self.request("ping", None).map(|_| ())
+    }
+
+    /// All tools, following `nextCursor` pagination.
+    pub fn list_tools(&mut self) -> Result<Vec<Value>, ProtocolError> {
+        let mut tools = self.list_all("tools/list", "tools")?;
+        if let Some((policy, server_id)) = &self.policy {
+            policy.filter_tools(server_id, &mut tools);
+        }
+        Ok(tools)
+    }
+
+    pub fn call_tool(&mut self, name: &str, arguments: Value) -> Result<Value, ProtocolError> {
+        if let Some((policy, server_id)) = &self.policy {
+            if !policy.is_allowed(server_id, name) {
+                return Err(ProtocolError::Rpc {
+                    code: DENIED_CODE,
+                    message: format!("Tool denied by policy: {}", name),
+                });
+            }
+        }
+        self.request("tools/call", Some(json!({"name": name, "arguments": arguments})))
+    }
+
+    pub fn list_resources(&mut self) -> Result<Vec<Value>, ProtocolError> {
+        self.list_all("resources/list", "resources")
+    }
+
+    pub fn read_resource(&mut self, uri: &str) -> Result<Value, ProtocolError> {
+        self.request("resources/read", Some(json!({"uri": uri})))
+    }
+
+    pub fn list_prompts(&mut self) -> Result<Vec<Value>, ProtocolError> {
+        self.list_all("prompts/list", "prompts")
+    }
+
+    pub fn get_prompt(&mut self, name: &str, arguments: Value) -> Result<Value, ProtocolError> {
+        self.request("prompts/get", Some(json!({"name": name, "arguments": arguments})))
+    }
+
+    /// Notifications received while waiting for responses, oldest first.
+    pub fn take_notifications(&mut self) -> Vec<Value> {
+        self.notifications.drain(..).collect()
+    }
+
+    /// Wait up to `timeout` for unsolicited server messages while no request is
+    /// in flight, then return all queued notifications.
+    pub fn poll_notifications(&mut self, timeout: Duration) -> Result<Vec<Value>, ProtocolError> {
+        if let Some(msg) = self.conn.recv_timeout(timeout)? {
+            if msg.get("method").is_some() {
+                if msg.get("id").is_some() {
+                    self.answer_server_request(&msg)?;
+                } else {
+                    self.notifications.push_back(msg);
+                }
+            }
+        }
+        Ok(self.take_notifications())
+    }
+
+    /// Send a notification (no response expected).
+    pub fn notify(&mut self, method: &str, params: Option<Value>) -> Result<(), ProtocolError> {
+        let mut msg = json!({"jsonrpc": "2.0", "method": method});
+        if let Some(p) = params {
+            msg["params"] = p;
+        }
+        self.conn.send(&msg)
+    }
+
+    /// Send a request and wait for its response. JSON-RPC errors become `ProtocolError::Rpc`.
+    pub fn request(&mut self, method: &str, params: Option<Value>) -> Result<Value, ProtocolError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut msg = json!({"jsonrpc": "2.0", "id": id, "method": method});
+        if let Some(p) = params {
+            msg["params"] = p;
+        }
+        self.conn.send(&msg)?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(ProtocolError::Timeout);
+            }
+            let Some(msg) = self.conn.recv_timeout(remaining)? else {
+                continue;
+            };
+
+            if msg.get("method").is_some() {
+                if msg.get("id").is_some() {
+                    self.answer_server_request(&msg)?;
+                } else {
+                    self.notifications.push_back(msg);
+                }
+                continue;
+            }
+            if msg.get("id").and_then(|v| v.as_u64()) != Some(id) {
+                continue;
+            }
+            if let Some(err) = msg.get("error") {
+                return Err(ProtocolError::Rpc {
+                    code: err.get("code").and_then(|c| c.as_i64()).unwrap_or(0),
+                    message: err.get("message").and_then(|m| m.as_str()).unwrap_or("").to_string(),
+                });
+            }
+            return Ok(msg.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    fn list_all(&mut self, method: &str, field: &str) -> Result<Vec<Value>, ProtocolError> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.as_ref().map(|c| json!({"cursor": c}));
+            let result = self.request(method, params)?;
+            let page = result
+                .get(field)
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| ProtocolError::InvalidResponse(format!("{} result has no {}", method, field)))?;
+            items.extend(page.iter().cloned());
+            match result.get("nextCursor").and_then(|c| c.as_str()) {
+                Some(c) if !c.is_empty() => cursor = Some(c.to_string()),
+                _ => break,
+            }
+        }
+        Ok(items)
+    }
+
+    /// dmcp exposes no client capabilities; answer `ping` and refuse everything else.
+    fn answer_server_request(&mut self, msg: &Value) -> Result<(), ProtocolError> {
+        let id = msg.get("id").cloned().unwrap_or(Value::Null);
+        let reply = if msg.get("method").and_then(|m| m.as_str()) == Some("ping") {
+            json!({"jsonrpc": "2.0", "id": id, "result": {}})
+        } else {
+            json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": "Method not found"}})
+        };
+        self.conn.send(&reply)
+    }
+}
+
+/// Build `tools/call` arguments from `key=value` pairs. Values are parsed as JSON
+/// unless the tool's input schema declares the property as a string.
+pub fn build_arguments(tool: Option<&Value>, args: &[(String, String)]) -> Value {
+    let props = tool
+        .and_then(|t| t.get("inputSchema"))
+        .and_then(|s| s.get("properties"));
+    let mut out = serde_json::Map::new();
+    for (k, v) in args {
+        let is_string = props
+            .and_then(|p| p.get(k))
+            .and_then(|p| p.get("type"))
+            .and_then(|t| t.as_str())
+            == Some("string");
+        let value = if is_string {
+            Value::String(v.clone())
+        } else {
+            serde_json::from_str(v).unwrap_or_else(|_| Value::String(v.clone()))
+        };
+        out.insert(k.clone(), value);
+    }
+    Value::Object(out)
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    ServerNotFound,
+    UnsupportedTransport,
+    Run(RunError),
+    Policy(PolicyError),
+    Io(std::io::Error),
+    Serialize(serde_json::Error),
+    Http(reqwest::Error),
+    HttpStatus(u16),
+    InvalidUrl(String),
+    InvalidHeader(String),
+    WebSocket(String),
+    Closed,
+    Timeout,
+    InvalidResponse(String),
+    Rpc { code: i64, message: String },
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::ServerNotFound => write!(f, "Server not found"),
+            ProtocolError::UnsupportedTransport => write!(f, "No supported transport in manifest"),
+            ProtocolError::Run(e) => write!(f, "{}", e),
+            ProtocolError::Policy(e) => write!(f, "{}", e),
+            ProtocolError::Io(e) => write!(f, "I/O error: {}", e),
+            ProtocolError::Serialize(e) => write!(f, "Failed to serialize message: {}", e),
+            ProtocolError::Http(e) => write!(f, "HTTP error: {}", e),
+            ProtocolError::HttpStatus(code) => write!(f, "Server returned HTTP {}", code),
+            ProtocolError::InvalidUrl(u) => write!(f, "Invalid URL: {}", u),
+            ProtocolError::InvalidHeader(h) => write!(f, "Invalid header: {}", h),
+            ProtocolError::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            ProtocolError::Closed => write!(f, "Connection closed by server"),
+            ProtocolError::Timeout => write!(f, "Timed out waiting for server response"),
+            ProtocolError::InvalidResponse(s) => write!(f, "Invalid response: {}", s),
+            ProtocolError::Rpc { code, message } => write!(f, "Server error {}: {}", code, message),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{PolicyFile, ToolRules};
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory server with tools `read_file` and `exec`. Records every message it receives.
+    struct FakeServer {
+        sent: Arc<Mutex<Vec<Value>>>,
+        replies: VecDeque<Value>,
+    }
+
+    impl Connection for FakeServer {
+        fn send(&mut self, message: &Value) -> Result<(), ProtocolError> {
+            self.sent.lock().unwrap().push(message.clone());
+            let Some(id) = message.get("id") else { return Ok(()) };
+            let result = match message["method"].as_str() {
+                Some("tools/list") => json!({"tools": [{"name": "read_file"}, {"name": "exec"}]}),
+                Some("tools/call") => json!({"content": [{"type": "text", "text": "ran"}]}),
+                _ => json!({}),
+            };
+            self.replies.push_back(json!({"jsonrpc": "2.0", "id": id, "result": result}));
+            Ok(())
+        }
+
+        fn recv_timeout(&mut self, _: Duration) -> Result<Option<Value>, ProtocolError> {
+            Ok(self.replies.pop_front())
+        }
+    }
+
+    fn client(deny: &[&str]) -> (Client, Arc<Mutex<Vec<Value>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let conn = FakeServer {
+            sent: Arc::clone(&sent),
+            replies: VecDeque::new(),
+        };
+        let mut system = PolicyFile::default();
+        system.servers.insert(
+            "com.example.*".to_string(),
+            ToolRules {
+                allow: Vec::new(),
+                deny: deny.iter().map(|d| d.to_string()).collect(),
+            },
+        );
+        let policy = Policy {
+            system,
+            user: PolicyFile::default(),
+        };
+        (Client::new(Box::new(conn)).with_policy(policy, "com.example.shell"), sent)
+    }
+
+    fn tool_names(tools: &[Value]) -> Vec<&str> {
+        tools.iter().filter_map(|t| t["name"].as_str()).collect()
+    }
+
+    #[test]
+    fn denied_tools_are_not_listed() {
+        let (mut c, _) = client(&["exec*"]);
+        assert_eq!(tool_names(&c.list_tools().unwrap()), ["read_file"]);
+    }
+
+    #[test]
+    fn denied_call_never_reaches_the_server() {
+        let (mut c, sent) = client(&["exec*"]);
+        match c.call_tool("exec", json!({})) {
+            Err(ProtocolError::Rpc { code, message }) => {
+                assert_eq!(code, DENIED_CODE);
+                assert!(message.contains("exec"));
+            }
+            other => panic!("expected a policy error, got {:?}", other),
+        }
+        assert!(sent.lock().unwrap().iter().all(|m| m["method"] != "tools/call"));
+    }
+
+    #[test]
+    fn allowed_call_goes_through() {
+        let (mut c, sent) = client(&["exec*"]);
+        let result = c.call_tool("read_file", json!({"path": "/tmp/x"})).unwrap();
+        assert_eq!(result["content"][0]["text"], "ran");
+        assert_eq!(sent.lock().unwrap().last().unwrap()["params"]["name"], "read_file");
+    }
+
+    #[test]
+    fn no_policy_lists_everything() {
+        let (mut c, _) = client(&[]);
+        assert_eq!(tool_names(&c.list_tools().unwrap()), ["read_file", "exec"]);
+    }
+
+    /// Client speaking over in-memory pipes to a server thread. `script` gets each
+    /// message the client sends and returns raw lines to write back, or `None` to
+    /// close the connection; every message is also reported on the returned channel.
+    fn piped(mut script: impl FnMut(&Value) -> Option<Vec<String>> + Send + 'static) -> (Client, mpsc::Receiver<Value>) {
+        let (client_in, mut server_out) = std::io::pipe().unwrap();
+        let (server_in, client_out) = std::io::pipe().unwrap();
+        let (seen, seen_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(server_in).lines() {
+                let msg: Value = serde_json::from_str(&line.unwrap()).unwrap();
+                let Some(replies) = script(&msg) else { break };
+                for reply in replies {
+                    writeln!(server_out, "{}", reply).unwrap();
+                }
+                let _ = seen.send(msg);
+            }
+        });
+        let mut client = Client::new(Box::new(StdioConnection::from_streams(client_in, client_out)));
+        client.set_timeout(Duration::from_secs(5));
+        (client, seen_rx)
+    }
+
+    fn result(msg: &Value, result: Value) -> String {
+        json!({"jsonrpc": "2.0", "id": msg["id"], "result": result}).to_string()
+    }
+
+    #[test]
+    fn handshake_skips_noise_and_answers_pings() {
+        let (mut c, seen) = piped(|msg| match msg["method"].as_str() {
+            Some("initialize") => Some(vec![
+                "server starting on stdio".to_string(),
+                json!({"jsonrpc": "2.0", "method": "notifications/message", "params": {"data": "hello"}}).to_string(),
+                json!({"jsonrpc": "2.0", "id": "s1", "method": "ping"}).to_string(),
+                json!({"jsonrpc": "2.0", "id": "s2", "method": "sampling/createMessage"}).to_string(),
+                json!({"jsonrpc": "2.0", "id": 99, "result": {}}).to_string(),
+                result(msg, json!({"protocolVersion": PROTOCOL_VERSION, "serverInfo": {"name": "notes"}})),
+            ]),
+            _ => Some(Vec::new()),
+        });
+        let info = c.initialize().unwrap();
+        assert_eq!(info["serverInfo"]["name"], "notes");
+        assert_eq!(c.server_info(), Some(&info));
+        assert_eq!(c.take_notifications()[0]["params"]["data"], "hello");
+
+        let sent: Vec<Value> = (0..4).map(|_| seen.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        assert_eq!(sent[0]["method"], "initialize");
+        assert_eq!(sent[0]["params"]["protocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(sent[0]["params"]["clientInfo"]["name"], "dmcp");
+        assert_eq!(sent[1], json!({"jsonrpc": "2.0", "id": "s1", "result": {}}));
+        assert_eq!(sent[2]["id"], "s2");
+        assert_eq!(sent[2]["error"]["code"], -32601);
+        assert_eq!(sent[3], json!({"jsonrpc": "2.0", "method": "notifications/initialized"}));
+    }
+
+    #[test]
+    fn lists_follow_next_cursor() {
+        let (mut c, seen) = piped(|msg| {
+            let page = match msg["params"]["cursor"].as_str() {
+                None => json!({"tools": [{"name": "a"}, {"name": "b"}], "nextCursor": "page2"}),
+                Some("page2") => json!({"tools": [{"name": "c"}], "nextCursor": "page3"}),
+                Some(_) => json!({"tools": [{"name": "d"}], "nextCursor": ""}),
+            };
+            Some(vec![result(msg, page)])
+        });
+        assert_eq!(tool_names(&c.list_tools().unwrap()), ["a", "b", "c", "d"]);
+        let cursors: Vec<Value> = seen.try_iter().map(|m| m["params"]["cursor"].clone()).collect();
+        assert_eq!(cursors, [Value::Null, json!("page2"), json!("page3")]);
+    }
+
+    #[test]
+    fn list_without_items_is_invalid() {
+        let (mut c, _) = piped(|msg| Some(vec![result(msg, json!({"resources": []}))]));
+        assert!(matches!(c.list_tools(), Err(ProtocolError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn error_replies_and_closed_streams() {
+        let (mut c, _) = piped(|msg| {
+            let error = json!({"jsonrpc": "2.0", "id": msg["id"], "error": {"code": -32602, "message": "bad args"}});
+            (msg["method"] == "tools/call").then(|| vec![error.to_string()])
+        });
+        match c.call_tool("echo", json!({})) {
+            Err(ProtocolError::Rpc { code, message }) => assert_eq!((code, message.as_str()), (-32602, "bad args")),
+            other => panic!("expected an RPC error, got {:?}", other),
+        }
+        assert!(matches!(c.ping(), Err(ProtocolError::Closed)));
+    }
+
+    fn args(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn arguments_follow_the_schema() {
+        let tool = json!({"name": "search", "inputSchema": {"properties": {
+            "query": {"type": "string"},
+            "limit": {"type": "integer"}
+        }}});
+        let built = build_arguments(
+            Some(&tool),
+            &args(&[("query", "42"), ("limit", "10"), ("filter", r#"{"tag": "x"}"#), ("mode", "fast")]),
+        );
+        assert_eq!(built, json!({"query": "42", "limit": 10, "filter": {"tag": "x"}, "mode": "fast"}));
+    }
+
+    #[test]
+    fn arguments_without_a_schema_are_parsed_as_json() {
+        let built = build_arguments(None, &args(&[("n", "1.5"), ("flag", "true"), ("name", "notes"), ("list", "[1, 2]")]));
+        assert_eq!(built, json!({"n": 1.5, "flag": true, "name": "notes", "list": [1, 2]}));
+        assert_eq!(build_arguments(None, &[]), json!({}));
+    }
+}
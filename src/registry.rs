@@ -0,0 +1,381 @@
+//! Registry fetching with an on-disk cache.
+//!
+//! Each source URL is cached in the registry cache dir as `<sha256(url)>.json`,
+//! with a `.meta.json` sidecar holding the ETag and Last-Modified validators.
+//! Online fetches are conditional; an unreachable registry falls back to its
+//! cached copy so browse and install keep working offline.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::elevation::{chown_to_invoker, create_dir_all_for_invoker};
+use crate::models::{Registry, RegistryEntry};
+use crate::paths::Paths;
+
+/// How to obtain a registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchMode {
+    /// Conditional request; fall back to the cache if the registry can't be reached.
+    Default,
+    /// Cached copy only, no network.
+    Offline,
+    /// Conditional request; failures are errors even if a cached copy exists.
+    Refresh,
+}
+
+impl FetchMode {
+    pub fn from_offline(offline: bool) -> Self {
+        if offline {
+            FetchMode::Offline
+        } else {
+            FetchMode::Default
+        }
+    }
+}
+
+/// Where the returned registry came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchStatus {
+    /// Downloaded and written to the cache.
+    Updated,
+    /// Server answered 304; cached copy is current.
+    NotModified,
+    /// Cached copy used without contacting the server (offline or unreachable).
+    Cached,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheMeta {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+/// Fetch a registry document, using and updating the cache according to `mode`.
+pub fn fetch_registry(paths: &Paths, url: &str, mode: FetchMode) -> Result<(Value, FetchStatus), RegistryError> {
+    let (body_path, meta_path) = cache_paths(paths.registry_cache_dir(), url);
+    let cached = read_cached(&body_path);
+
+    if mode == FetchMode::Offline {
+        return cached
+            .map(|v| (v, FetchStatus::Cached))
+            .ok_or_else(|| RegistryError::NotCached(url.to_string()));
+    }
+
+    let meta: CacheMeta = match cached {
+        Some(_) => std::fs::read_to_string(&meta_path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default(),
+        None => CacheMeta::default(),
+    };
+
+    let fetched = fetch_remote(url, &meta).and_then(|remote| match remote {
+        Remote::NotModified => cached
+            .clone()
+            .map(|v| (v, FetchStatus::NotModified))
+            // Validators without a body only happen if the cache was removed mid-way
+            .ok_or_else(|| RegistryError::NotCached(url.to_string())),
+        Remote::Body(body, new_meta) => {
+            let registry: Value = serde_json::from_slice(&body).map_err(RegistryError::Parse)?;
+            // A read-only cache shouldn't stop the fetch itself
+            if let Err(e) = write_cache(&body_path, &meta_path, &body, &new_meta) {
+                eprintln!("Warning: {}", RegistryError::Cache(e));
+            }
+            Ok((registry, FetchStatus::Updated))
+        }
+    });
+
+    match (fetched, mode, cached) {
+        (Err(e), FetchMode::Default, Some(v)) => {
+            eprintln!("Warning: {}; using cached copy", e);
+            Ok((v, FetchStatus::Cached))
+        }
+        (result, _, _) => result,
+    }
+}
+
+/// Fetch a registry and parse it. Malformed entries are skipped and returned
+/// as errors next to the registry so one bad entry doesn't hide the rest.
+pub fn load_registry(paths: &Paths, url: &str, mode: FetchMode) -> Result<(Registry, Vec<RegistryError>), RegistryError> {
+    let (value, _) = fetch_registry(paths, url, mode)?;
+    parse_registry(url, value)
+}
+
+/// Parse a registry document fetched from `url` (used in error messages).
+pub fn parse_registry(url: &str, value: Value) -> Result<(Registry, Vec<RegistryError>), RegistryError> {
+    let invalid = |reason: &str| RegistryError::InvalidRegistry {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    };
+    let Value::Object(mut obj) = value else {
+        return Err(invalid("not a JSON object"));
+    };
+    let servers = match obj.remove("servers") {
+        Some(Value::Array(servers)) => servers,
+        Some(_) => return Err(invalid("`servers` is not an array")),
+        None => return Err(invalid("missing `servers`")),
+    };
+    let mut registry: Registry =
+        serde_json::from_value(Value::Object(obj)).map_err(|e| invalid(&e.to_string()))?;
+
+    let mut errors = Vec::new();
+    for (index, raw) in servers.into_iter().enumerate() {
+        let id = raw.get("id").and_then(|v| v.as_str()).map(String::from);
+        match RegistryEntry::from_value(raw) {
+            Ok(entry) => registry.servers.push(entry),
+            Err(e) => errors.push(RegistryError::InvalidEntry {
+                url: url.to_string(),
+                index,
+                id,
+                reason: e.to_string(),
+            }),
+        }
+    }
+    Ok((registry, errors))
+}
+
+enum Remote {
+    NotModified,
+    Body(Vec<u8>, CacheMeta),
+}
+
+fn fetch_remote(url: &str, meta: &CacheMeta) -> Result<Remote, RegistryError> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent("dmcp/1.0")
+        .connect_timeout(Duration::from_secs(15))
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(RegistryError::HttpClient)?;
+
+    let mut req = client.get(url);
+    if let Some(etag) = &meta.etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(lm) = &meta.last_modified {
+        req = req.header(IF_MODIFIED_SINCE, lm);
+    }
+    let fetch_err = |cause| RegistryError::Fetch { url: url.to_string(), cause };
+    let resp = req.send().map_err(fetch_err)?;
+
+    let status = resp.status();
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(Remote::NotModified);
+    }
+    if !status.is_success() {
+        return Err(RegistryError::Status {
+            url: url.to_string(),
+            status: status.as_u16(),
+        });
+    }
+    let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok()).map(String::from);
+    let new_meta = CacheMeta {
+        url: url.to_string(),
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+    let body = resp.bytes().map_err(fetch_err)?;
+    Ok(Remote::Body(body.to_vec(), new_meta))
+}
+
+fn cache_paths(dir: &Path, url: &str) -> (PathBuf, PathBuf) {
+    let key: String = Sha256::digest(url.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+    (dir.join(format!("{}.json", key)), dir.join(format!("{}.meta.json", key)))
+}
+
+fn read_cached(path: &Path) -> Option<Value> {
+    let content = std::fs::read(path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// Write body then metadata, each via a temp file and rename so readers never see partial files.
+/// Under pkexec the cache still belongs to the invoking user.
+fn write_cache(body_path: &Path, meta_path: &Path, body: &[u8], meta: &CacheMeta) -> std::io::Result<()> {
+    if let Some(dir) = body_path.parent() {
+        create_dir_all_for_invoker(dir)?;
+    }
+    let meta_json = serde_json::to_vec_pretty(meta).map_err(std::io::Error::other)?;
+    for (path, data) in [(body_path, body), (meta_path, meta_json.as_slice())] {
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        std::fs::write(&tmp, data)?;
+        chown_to_invoker(&tmp);
+        std::fs::rename(&tmp, path)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    HttpClient(reqwest::Error),
+    Fetch { url: String, cause: reqwest::Error },
+    Status { url: String, status: u16 },
+    Parse(serde_json::Error),
+    NotCached(String),
+    Cache(std::io::Error),
+    InvalidRegistry { url: String, reason: String },
+    InvalidEntry { url: String, index: usize, id: Option<String>, reason: String },
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::HttpClient(e) => write!(f, "HTTP client error: {}", e),
+            RegistryError::Fetch { url, cause } => {
+                write!(f, "Failed to fetch {}: {}", url, cause)?;
+                // Show error chain for more diagnostic detail
+                let mut source: Option<&(dyn Error + '_)> = cause.source();
+                while let Some(s) = source {
+                    write!(f, "\n  Caused by: {}", s)?;
+                    source = s.source();
+                }
+                Ok(())
+            }
+            RegistryError::Status { url, status } => write!(f, "Failed to fetch {}: HTTP {}", url, status),
+            RegistryError::Parse(e) => write!(f, "Registry is not valid JSON: {}", e),
+            RegistryError::NotCached(url) => write!(f, "No cached copy of {} (run without --offline)", url),
+            RegistryError::Cache(e) => write!(f, "Failed to write registry cache: {}", e),
+            RegistryError::InvalidRegistry { url, reason } => write!(f, "Invalid registry {}: {}", url, reason),
+            RegistryError::InvalidEntry { url, index, id, reason } => {
+                write!(f, "Invalid entry servers[{}]", index)?;
+                if let Some(id) = id {
+                    write!(f, " ({})", id)?;
+                }
+                write!(f, " in {}: {}", url, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{paths, respond, serve};
+    use std::sync::{Arc, Mutex};
+
+    const ETAG_V1: &str = "\"v1\"";
+    const LAST_MODIFIED_V1: &str = "Mon, 05 Oct 2026 10:00:00 GMT";
+
+    /// `If-None-Match` and `If-Modified-Since` of each request.
+    type ConditionLog = Arc<Mutex<Vec<(Option<String>, Option<String>)>>>;
+
+    /// Registry that serves `body` with validators and answers 304 to matching
+    /// conditional requests. Records the conditional headers of each request.
+    fn registry(body: &'static str) -> (String, ConditionLog) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let base = serve(move |req, stream| {
+            let etag = req.header("if-none-match").map(String::from);
+            let since = req.header("if-modified-since").map(String::from);
+            let fresh = etag.as_deref() == Some(ETAG_V1) || since.as_deref() == Some(LAST_MODIFIED_V1);
+            log.lock().unwrap().push((etag, since));
+            if fresh {
+                respond(stream, "304 Not Modified", &[], "");
+            } else {
+                respond(stream, "200 OK", &[("ETag", ETAG_V1), ("Last-Modified", LAST_MODIFIED_V1)], body);
+            }
+        });
+        (format!("{}/registry.json", base), seen)
+    }
+
+    #[test]
+    fn second_fetch_is_conditional_and_reuses_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let (url, seen) = registry(r#"{"servers": []}"#);
+
+        let (first, status) = fetch_registry(&paths, &url, FetchMode::Default).unwrap();
+        assert_eq!(status, FetchStatus::Updated);
+        let (second, status) = fetch_registry(&paths, &url, FetchMode::Default).unwrap();
+        assert_eq!(status, FetchStatus::NotModified);
+        assert_eq!(first, second);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0], (None, None));
+        assert_eq!(seen[1], (Some(ETAG_V1.to_string()), Some(LAST_MODIFIED_V1.to_string())));
+    }
+
+    #[test]
+    fn offline_uses_cache_without_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let (url, seen) = registry(r#"{"servers": [], "name": "test"}"#);
+
+        assert!(matches!(
+            fetch_registry(&paths, &url, FetchMode::Offline),
+            Err(RegistryError::NotCached(_))
+        ));
+        fetch_registry(&paths, &url, FetchMode::Default).unwrap();
+        let (value, status) = fetch_registry(&paths, &url, FetchMode::Offline).unwrap();
+        assert_eq!(status, FetchStatus::Cached);
+        assert_eq!(value["name"], "test");
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn unreachable_registry_falls_back_to_stale_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        // Seed the cache, then point at a server that is down
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/registry.json", listener.local_addr().unwrap());
+        drop(listener);
+        let (body_path, meta_path) = cache_paths(paths.registry_cache_dir(), &url);
+        let meta = CacheMeta {
+            url: url.clone(),
+            etag: Some(ETAG_V1.to_string()),
+            last_modified: None,
+        };
+        write_cache(&body_path, &meta_path, br#"{"servers": [], "name": "stale"}"#, &meta).unwrap();
+
+        let (value, status) = fetch_registry(&paths, &url, FetchMode::Default).unwrap();
+        assert_eq!(status, FetchStatus::Cached);
+        assert_eq!(value["name"], "stale");
+        assert!(fetch_registry(&paths, &url, FetchMode::Refresh).is_err());
+    }
+
+    #[test]
+    fn enum_property_without_values_is_an_invalid_entry() {
+        let registry = serde_json::json!({"servers": [{
+            "id": "com.example.logs",
+            "name": "Logs",
+            "version": "1.0.0",
+            "transports": [{"type": "streamable_http", "url": "https://logs.example.com/mcp"}],
+            "configurableProperties": [{"key": "level", "type": "enum"}]
+        }]});
+        let (parsed, errors) = parse_registry("https://registry.example.com", registry).unwrap();
+        assert!(parsed.servers.is_empty());
+        assert!(matches!(&errors[..], [RegistryError::InvalidEntry { reason, .. }] if reason.contains("level")));
+    }
+
+    #[test]
+    fn changed_registry_replaces_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let (url, _) = registry(r#"{"servers": [], "name": "new"}"#);
+        let (body_path, meta_path) = cache_paths(paths.registry_cache_dir(), &url);
+        let meta = CacheMeta {
+            url: url.clone(),
+            etag: Some("\"v0\"".to_string()),
+            last_modified: None,
+        };
+        write_cache(&body_path, &meta_path, br#"{"servers": [], "name": "old"}"#, &meta).unwrap();
+
+        let (value, status) = fetch_registry(&paths, &url, FetchMode::Default).unwrap();
+        assert_eq!(status, FetchStatus::Updated);
+        assert_eq!(value["name"], "new");
+        let (cached, _) = fetch_registry(&paths, &url, FetchMode::Offline).unwrap();
+        assert_eq!(cached["name"], "new");
+    }
+}
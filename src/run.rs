@@ -0,0 +1,188 @@
+//! Invocation of installed stdio servers.
+//!
+//! Resolves the manifest, picks the primary stdio transport and spawns it
+//! from the install directory (see MCP-SYSTEM-SPEC.md §9).
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use crate::config::{interpolate, resolve_config, value_to_string};
+use crate::discovery::{get_manifest_path, get_server};
+use crate::models::{Manifest, Transport};
+use crate::paths::Paths;
+use crate::policy::PolicyFilter;
+
+/// Spawn the stdio process for a server. stdin/stdout/stderr are inherited,
+/// so the child talks MCP directly with whoever launched dmcp.
+pub fn spawn_server(paths: &Paths, id: &str) -> Result<Child, RunError> {
+    server_command(paths, id)?.spawn().map_err(RunError::SpawnFailed)
+}
+
+/// Run a stdio server with dmcp relaying stdin/stdout through `filter`, so
+/// tools denied by policy are hidden and their calls rejected. Returns when the server exits.
+pub fn run_filtered(paths: &Paths, id: &str, filter: PolicyFilter) -> Result<ExitStatus, RunError> {
+    let mut child = server_command(paths, id)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(RunError::SpawnFailed)?;
+    let (Some(mut child_in), Some(child_out)) = (child.stdin.take(), child.stdout.take()) else {
+        unreachable!("stdin and stdout are piped");
+    };
+    let filter = Arc::new(Mutex::new(filter));
+    let stdout = Arc::new(Mutex::new(std::io::stdout()));
+
+    let client_filter = Arc::clone(&filter);
+    let client_stdout = Arc::clone(&stdout);
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            // Non-JSON input is the server's problem; pass it through untouched
+            let forward = match serde_json::from_str::<Value>(&line) {
+                Ok(msg) => {
+                    let (forward, replies) = client_filter.lock().unwrap().client_message(msg);
+                    for reply in replies {
+                        write_line(&client_stdout, &reply.to_string());
+                    }
+                    forward.map(|m| m.to_string())
+                }
+                Err(_) => Some(line),
+            };
+            if let Some(line) = forward {
+                if writeln!(child_in, "{}", line).and_then(|()| child_in.flush()).is_err() {
+                    break;
+                }
+            }
+        }
+        // Dropping child_in closes the server's stdin
+    });
+
+    for line in BufReader::new(child_out).lines() {
+        let Ok(line) = line else { break };
+        match serde_json::from_str::<Value>(&line) {
+            Ok(mut msg) => {
+                filter.lock().unwrap().server_message(&mut msg);
+                write_line(&stdout, &msg.to_string());
+            }
+            Err(_) => write_line(&stdout, &line),
+        }
+    }
+    child.wait().map_err(RunError::WaitFailed)
+}
+
+fn write_line(out: &Mutex<std::io::Stdout>, line: &str) {
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+/// Build the command for a server's primary stdio transport without spawning it.
+/// Callers that need piped stdio configure it on the returned command.
+pub fn server_command(paths: &Paths, id: &str) -> Result<Command, RunError> {
+    let (mut manifest, _) = get_server(paths, id).ok_or(RunError::ServerNotFound)?;
+    let manifest_path = get_manifest_path(paths, id).ok_or(RunError::ServerNotFound)?;
+    crate::config::apply_user_config(paths, id, &mut manifest, true).map_err(RunError::Config)?;
+    build_command(&manifest, &manifest_path)
+}
+
+/// Build the command for a loaded manifest. `manifest_path` is used as the
+/// working directory fallback when the manifest has no `installDir`.
+pub fn build_command(manifest: &Manifest, manifest_path: &Path) -> Result<Command, RunError> {
+    let (command, args) = manifest
+        .transports
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find_map(|t| match t {
+            Transport::Stdio { command, args, .. } => Some((command, args)),
+            _ => None,
+        })
+        .ok_or(RunError::NoStdioTransport)?;
+
+    let install_dir = install_dir(manifest, manifest_path);
+    if !install_dir.is_dir() {
+        return Err(RunError::MissingInstallDir(install_dir));
+    }
+
+    let mut cmd = Command::new(resolve_program(command, &install_dir));
+    cmd.args(args.as_deref().unwrap_or_default());
+    cmd.current_dir(&install_dir);
+    cmd.envs(server_env(manifest)?);
+    Ok(cmd)
+}
+
+/// Environment overrides for a server, on top of the inherited environment.
+/// Properties with an `env` name are exported first; the manifest `env` map
+/// (with `${config.key}` interpolation) is applied last and wins.
+pub fn server_env(manifest: &Manifest) -> Result<Vec<(String, String)>, RunError> {
+    let config = resolve_config(manifest);
+    let mut env = Vec::new();
+    for prop in &manifest.configurable_properties {
+        let Some(name) = prop.env.as_deref().filter(|n| !n.is_empty()) else {
+            continue;
+        };
+        if let Some(v) = config.get(&prop.key) {
+            env.push((name.to_string(), value_to_string(v)));
+        }
+    }
+    let mut overrides: Vec<_> = manifest.env.iter().collect();
+    overrides.sort();
+    for (k, v) in overrides {
+        let value = interpolate(v, &config, &manifest.configurable_properties).map_err(RunError::Placeholder)?;
+        env.push((k.clone(), value));
+    }
+    Ok(env)
+}
+
+/// Install dir from manifest, or the directory containing manifest.json.
+fn install_dir(manifest: &Manifest, manifest_path: &Path) -> PathBuf {
+    match manifest.install_dir.as_deref().filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => manifest_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    }
+}
+
+/// Relative commands with a path component (e.g. `./bin/server`) are resolved
+/// against the install dir; bare names (e.g. `python3`) are left to PATH lookup.
+fn resolve_program(command: &str, install_dir: &Path) -> PathBuf {
+    let path = Path::new(command);
+    if path.is_relative() && path.components().count() > 1 {
+        install_dir.join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    ServerNotFound,
+    NoStdioTransport,
+    MissingInstallDir(PathBuf),
+    SpawnFailed(std::io::Error),
+    WaitFailed(std::io::Error),
+    Config(crate::config::ConfigError),
+    Placeholder(crate::config::UnknownPlaceholder),
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::ServerNotFound => write!(f, "Server not found"),
+            RunError::NoStdioTransport => write!(f, "Server has no stdio transport"),
+            RunError::MissingInstallDir(p) => write!(f, "Install directory not found: {}", p.display()),
+            RunError::SpawnFailed(e) => write!(f, "Failed to spawn server: {}", e),
+            RunError::WaitFailed(e) => write!(f, "Failed to wait for server: {}", e),
+            RunError::Config(e) => write!(f, "{}", e),
+            RunError::Placeholder(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
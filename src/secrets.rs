@@ -0,0 +1,464 @@
+//! Per-user store for `sensitive` config values, kept out of manifests.
+//!
+//! Each server's secrets live in `<secrets dir>/<id>.json` (mode 0600, in a 0700
+//! dir), so they are never written to world-readable system manifests and each
+//! user supplies their own. Values are merged into the config only when a server
+//! is launched or bridged (see `config::apply_user_config`).
+//!
+//! The store can be encrypted with a passphrase (`dmcp secrets encrypt`): each
+//! file then holds ChaCha20-Poly1305 ciphertext under an Argon2id-derived key, and
+//! the passphrase is read from `DMCP_SECRETS_PASSPHRASE` or asked on the terminal.
+
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::discovery::{get_manifest_path, list_servers};
+use crate::elevation::{chown_to_invoker, create_dir_all_for_invoker};
+use crate::models::ConfigurableProperty;
+use crate::paths::Paths;
+
+/// Environment variable holding the store passphrase.
+pub const PASSPHRASE_ENV: &str = "DMCP_SECRETS_PASSPHRASE";
+
+/// Marker file: new secrets are written encrypted while it exists.
+const ENCRYPTED_MARKER: &str = ".encrypted";
+
+/// Placeholder shown instead of a sensitive value.
+pub const MASK: &str = "********";
+
+/// A server's secrets file as it was before a change, so a failed install can
+/// put it back (see [`restore`]). Holds the raw bytes; needs no passphrase.
+pub struct Snapshot {
+    id: String,
+    content: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SecretsFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    values: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted: Option<Envelope>,
+}
+
+/// Encrypted values. `keys` stays readable so masked listings need no passphrase.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    keys: Vec<String>,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+/// Whether new secrets are written encrypted.
+pub fn is_encrypted(paths: &Paths) -> bool {
+    paths.secrets_dir().join(ENCRYPTED_MARKER).exists()
+}
+
+/// Keys with a stored secret for `id`. Never needs the passphrase.
+pub fn keys(paths: &Paths, id: &str) -> Result<Vec<String>, SecretsError> {
+    Ok(match read_file(paths, id)? {
+        Some(SecretsFile {
+            encrypted: Some(env), ..
+        }) => env.keys,
+        Some(SecretsFile { values: Some(v), .. }) => v.keys().cloned().collect(),
+        _ => Vec::new(),
+    })
+}
+
+/// Stored secrets for `id` (empty if none), decrypting if needed.
+pub fn load(paths: &Paths, id: &str) -> Result<Map<String, Value>, SecretsError> {
+    let path = secrets_path(paths, id);
+    match read_file(paths, id)? {
+        Some(SecretsFile {
+            encrypted: Some(env), ..
+        }) => decrypt(&env, &passphrase()?, &path),
+        Some(SecretsFile { values: Some(v), .. }) => Ok(v),
+        _ => Ok(Map::new()),
+    }
+}
+
+/// Store `values` for `id`, replacing existing values for the same keys.
+pub fn set(paths: &Paths, id: &str, values: Map<String, Value>) -> Result<(), SecretsError> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let mut all = load(paths, id)?;
+    all.extend(values);
+    write(paths, id, all)
+}
+
+/// Remove the secret `key` for `id`. Returns whether it was stored.
+pub fn remove(paths: &Paths, id: &str, key: &str) -> Result<bool, SecretsError> {
+    if !keys(paths, id)?.iter().any(|k| k == key) {
+        return Ok(false);
+    }
+    let mut all = load(paths, id)?;
+    all.remove(key);
+    write(paths, id, all)?;
+    Ok(true)
+}
+
+/// Delete all secrets for `id`.
+pub fn delete(paths: &Paths, id: &str) -> Result<(), SecretsError> {
+    let path = secrets_path(paths, id);
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SecretsError::Io(path, e)),
+        _ => Ok(()),
+    }
+}
+
+/// Take a [`Snapshot`] of the secrets stored for `id`.
+pub fn snapshot(paths: &Paths, id: &str) -> Result<Snapshot, SecretsError> {
+    let path = secrets_path(paths, id);
+    let content = match std::fs::read(&path) {
+        Ok(c) => Some(c),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(SecretsError::Io(path, e)),
+    };
+    Ok(Snapshot {
+        id: id.to_string(),
+        content,
+    })
+}
+
+/// Put the secrets file back the way it was when `snapshot` was taken.
+pub fn restore(paths: &Paths, snapshot: &Snapshot) -> Result<(), SecretsError> {
+    match &snapshot.content {
+        Some(content) => write_private(paths, &secrets_path(paths, &snapshot.id), content),
+        None => delete(paths, &snapshot.id),
+    }
+}
+
+/// Split the non-empty values of `sensitive` properties out of `config`.
+pub fn take_sensitive(props: &[ConfigurableProperty], config: &mut Map<String, Value>) -> Map<String, Value> {
+    let mut secrets = Map::new();
+    for prop in props.iter().filter(|p| p.sensitive) {
+        match config.get(&prop.key) {
+            None | Some(Value::Null) => {}
+            Some(Value::String(s)) if s.is_empty() => {}
+            Some(_) => {
+                secrets.insert(prop.key.clone(), config.remove(&prop.key).unwrap());
+            }
+        }
+    }
+    secrets
+}
+
+/// Outcome of [`migrate`].
+#[derive(Debug, Default)]
+pub struct Migration {
+    /// `(id, key)` pairs moved into the store.
+    pub moved: Vec<(String, String)>,
+    /// `(id, key)` pairs of system-scope servers. Their values are shared by all
+    /// users, so they are left for an administrator to remove.
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Move plain-text sensitive values from the config of user-scope servers into
+/// the store. System-scope servers are only reported: moving a shared value into
+/// one user's store would take it away from everyone else.
+pub fn migrate(paths: &Paths) -> Result<Migration, SecretsError> {
+    let mut moved = Vec::new();
+    let mut skipped = Vec::new();
+    for server in list_servers(paths, true, true, false) {
+        let Some(manifest_path) = get_manifest_path(paths, &server.id) else {
+            continue;
+        };
+        let content = std::fs::read_to_string(&manifest_path).map_err(|e| SecretsError::Io(manifest_path.clone(), e))?;
+        let mut manifest: Value =
+            serde_json::from_str(&content).map_err(|e| SecretsError::Parse(manifest_path.clone(), e))?;
+        let props: Vec<ConfigurableProperty> = manifest
+            .get("configurableProperties")
+            .and_then(|p| serde_json::from_value(p.clone()).ok())
+            .unwrap_or_default();
+        let Some(config) = manifest.get_mut("config").and_then(|c| c.as_object_mut()) else {
+            continue;
+        };
+        let secrets = take_sensitive(&props, config);
+        if secrets.is_empty() {
+            continue;
+        }
+        let keys: Vec<String> = secrets.keys().cloned().collect();
+        if server.scope == crate::discovery::Scope::System {
+            skipped.extend(keys.into_iter().map(|k| (server.id.clone(), k)));
+            continue;
+        }
+        set(paths, &server.id, secrets)?;
+        let output = serde_json::to_string_pretty(&manifest).map_err(|e| SecretsError::Parse(manifest_path.clone(), e))?;
+        std::fs::write(&manifest_path, output).map_err(|e| SecretsError::Io(manifest_path.clone(), e))?;
+        moved.extend(keys.into_iter().map(|k| (server.id.clone(), k)));
+    }
+    Ok(Migration { moved, skipped })
+}
+
+/// Encrypt every stored file with `passphrase` and encrypt new secrets from now on.
+pub fn encrypt_store(paths: &Paths, passphrase: &str) -> Result<usize, SecretsError> {
+    let ids = stored_ids(paths)?;
+    // Read everything first so a wrong passphrase for an already encrypted file changes nothing
+    let mut all = Vec::new();
+    for id in ids {
+        let path = secrets_path(paths, &id);
+        let values = match read_file(paths, &id)? {
+            Some(SecretsFile {
+                encrypted: Some(env), ..
+            }) => decrypt(&env, passphrase, &path)?,
+            Some(SecretsFile { values: Some(v), .. }) => v,
+            _ => continue,
+        };
+        all.push((id, values));
+    }
+    let _ = PASSPHRASE.set(passphrase.to_string());
+    create_dir(paths)?;
+    let marker = paths.secrets_dir().join(ENCRYPTED_MARKER);
+    std::fs::write(&marker, "").map_err(|e| SecretsError::Io(marker.clone(), e))?;
+    chown_to_invoker(&marker);
+    let count = all.len();
+    for (id, values) in all {
+        write(paths, &id, values)?;
+    }
+    Ok(count)
+}
+
+/// Decrypt every stored file and store new secrets in plain text (still 0600).
+pub fn decrypt_store(paths: &Paths) -> Result<usize, SecretsError> {
+    let mut all = Vec::new();
+    for id in stored_ids(paths)? {
+        let values = load(paths, &id)?;
+        all.push((id, values));
+    }
+    let marker = paths.secrets_dir().join(ENCRYPTED_MARKER);
+    match std::fs::remove_file(&marker) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(SecretsError::Io(marker, e)),
+        _ => {}
+    }
+    let count = all.len();
+    for (id, values) in all {
+        write(paths, &id, values)?;
+    }
+    Ok(count)
+}
+
+fn stored_ids(paths: &Paths) -> Result<Vec<String>, SecretsError> {
+    let dir = paths.secrets_dir();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(SecretsError::Io(dir.to_path_buf(), e)),
+    };
+    let mut ids: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(".json").map(String::from))
+        .collect();
+    ids.sort();
+    Ok(ids)
+}
+
+fn secrets_path(paths: &Paths, id: &str) -> PathBuf {
+    paths.secrets_dir().join(format!("{}.json", id))
+}
+
+fn read_file(paths: &Paths, id: &str) -> Result<Option<SecretsFile>, SecretsError> {
+    let path = secrets_path(paths, id);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(SecretsError::Io(path, e)),
+    };
+    serde_json::from_str(&content).map(Some).map_err(|e| SecretsError::Parse(path, e))
+}
+
+/// Replace the file for `id` with `values` (removing it when empty), encrypted if the store is.
+fn write(paths: &Paths, id: &str, values: Map<String, Value>) -> Result<(), SecretsError> {
+    let path = secrets_path(paths, id);
+    if values.is_empty() {
+        return delete(paths, id);
+    }
+    let file = if is_encrypted(paths) {
+        SecretsFile {
+            values: None,
+            encrypted: Some(encrypt(&values, &passphrase()?)?),
+        }
+    } else {
+        SecretsFile {
+            values: Some(values),
+            encrypted: None,
+        }
+    };
+    let output = serde_json::to_string_pretty(&file).map_err(|e| SecretsError::Parse(path.clone(), e))?;
+    write_private(paths, &path, output.as_bytes())
+}
+
+/// Write a 0600 temp file and rename it to `path`, so the secrets are never readable by others.
+fn write_private(paths: &Paths, path: &Path, content: &[u8]) -> Result<(), SecretsError> {
+    create_dir(paths)?;
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    let result = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut f| f.write_all(content))
+        .and_then(|_| {
+            chown_to_invoker(&tmp);
+            std::fs::rename(&tmp, path)
+        });
+    result.map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        SecretsError::Io(path.to_path_buf(), e)
+    })
+}
+
+fn create_dir(paths: &Paths) -> Result<(), SecretsError> {
+    let dir = paths.secrets_dir();
+    create_dir_all_for_invoker(dir).map_err(|e| SecretsError::Io(dir.to_path_buf(), e))?;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700)).map_err(|e| SecretsError::Io(dir.to_path_buf(), e))
+}
+
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+/// Store passphrase from `DMCP_SECRETS_PASSPHRASE`, or asked once on the terminal.
+pub fn passphrase() -> Result<String, SecretsError> {
+    if let Some(p) = PASSPHRASE.get() {
+        return Ok(p.clone());
+    }
+    let p = match std::env::var(PASSPHRASE_ENV) {
+        Ok(p) if !p.is_empty() => p,
+        _ => rpassword::prompt_password("Secrets passphrase: ").map_err(|_| SecretsError::NoPassphrase)?,
+    };
+    Ok(PASSPHRASE.get_or_init(|| p).clone())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, SecretsError> {
+    let mut key = Key::default();
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| SecretsError::Crypto(e.to_string()))?;
+    Ok(key)
+}
+
+fn encrypt(values: &Map<String, Value>, passphrase: &str) -> Result<Envelope, SecretsError> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    getrandom::getrandom(&mut salt).map_err(|e| SecretsError::Crypto(e.to_string()))?;
+    getrandom::getrandom(&mut nonce).map_err(|e| SecretsError::Crypto(e.to_string()))?;
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let plain = serde_json::to_vec(values).map_err(|e| SecretsError::Crypto(e.to_string()))?;
+    let data = cipher
+        .encrypt(Nonce::from_slice(&nonce), plain.as_slice())
+        .map_err(|e| SecretsError::Crypto(e.to_string()))?;
+    Ok(Envelope {
+        keys: values.keys().cloned().collect(),
+        salt: to_hex(&salt),
+        nonce: to_hex(&nonce),
+        data: to_hex(&data),
+    })
+}
+
+fn decrypt(env: &Envelope, passphrase: &str, path: &Path) -> Result<Map<String, Value>, SecretsError> {
+    let corrupt = || SecretsError::Corrupt(path.to_path_buf());
+    let salt = from_hex(&env.salt).ok_or_else(corrupt)?;
+    let nonce = from_hex(&env.nonce).filter(|n| n.len() == 12).ok_or_else(corrupt)?;
+    let data = from_hex(&env.data).ok_or_else(corrupt)?;
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let plain = cipher
+        .decrypt(Nonce::from_slice(&nonce), data.as_slice())
+        .map_err(|_| SecretsError::WrongPassphrase(path.to_path_buf()))?;
+    serde_json::from_slice(&plain).map_err(|_| corrupt())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum SecretsError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_json::Error),
+    NoPassphrase,
+    WrongPassphrase(PathBuf),
+    Corrupt(PathBuf),
+    Crypto(String),
+}
+
+impl std::fmt::Display for SecretsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretsError::Io(p, e) => write!(f, "Secret store {}: {}", p.display(), e),
+            SecretsError::Parse(p, e) => write!(f, "Failed to parse {}: {}", p.display(), e),
+            SecretsError::NoPassphrase => write!(
+                f,
+                "The secret store is encrypted; set {} or run from a terminal",
+                PASSPHRASE_ENV
+            ),
+            SecretsError::WrongPassphrase(p) => write!(f, "Wrong passphrase for {}", p.display()),
+            SecretsError::Corrupt(p) => write!(f, "Corrupt secrets file: {}", p.display()),
+            SecretsError::Crypto(e) => write!(f, "Encryption failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SecretsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::paths;
+    use serde_json::json;
+
+    /// Write a manifest with a plain-text sensitive `token` and index it.
+    fn install_manifest(dir: &Path, id: &str) -> PathBuf {
+        let manifest = json!({
+            "id": id,
+            "name": id,
+            "version": "1.0.0",
+            "transports": [{"type": "streamable_http", "url": "https://api.example.com/mcp"}],
+            "configurableProperties": [{"key": "token", "sensitive": true}, {"key": "region"}],
+            "config": {"token": "plain", "region": "eu"}
+        });
+        let path = dir.join(id).join("manifest.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, manifest.to_string()).unwrap();
+        let index = json!({"servers": {id: {"location": path}}});
+        std::fs::write(dir.join("index.json"), index.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn migrate_moves_user_secrets_and_leaves_system_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = paths(dir.path());
+        let user = install_manifest(paths.user_install_dir(), "com.example.mine");
+        let system = install_manifest(paths.system_install_dir(), "com.example.shared");
+        let system_before = std::fs::read_to_string(&system).unwrap();
+
+        let migration = migrate(&paths).unwrap();
+        assert_eq!(migration.moved, [("com.example.mine".to_string(), "token".to_string())]);
+        assert_eq!(migration.skipped, [("com.example.shared".to_string(), "token".to_string())]);
+
+        assert_eq!(load(&paths, "com.example.mine").unwrap()["token"], "plain");
+        let manifest: Value = serde_json::from_str(&std::fs::read_to_string(&user).unwrap()).unwrap();
+        assert_eq!(manifest["config"], json!({"region": "eu"}));
+
+        assert_eq!(std::fs::read_to_string(&system).unwrap(), system_before);
+        assert!(keys(&paths, "com.example.shared").unwrap().is_empty());
+    }
+}
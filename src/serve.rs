@@ -0,0 +1,903 @@
+//! Serve an installed stdio server over HTTP.
+//!
+//! Endpoints (one request per connection, `Connection: close`):
+//! - Streamable HTTP: `POST /mcp`, `GET /mcp` (server-initiated stream), `DELETE /mcp`
+//! - Legacy HTTP+SSE: `GET /sse` (event stream), `POST /messages?sessionId=...`
+//!
+//! Each session gets its own child process, or all sessions share one child.
+//! Request ids are rewritten per backend so shared sessions can't collide.
+//! Legacy POSTs are queued per session and reach the child in the order they arrived.
+//! Sessions without an open stream that go unused for [`SESSION_IDLE_TIMEOUT`]
+//! are dropped, which stops their child process.
+//! Tool policy (policy.json) is enforced on every session.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::paths::Paths;
+use crate::policy::{Policy, PolicyError};
+use crate::protocol::{Connection, ProtocolError, StdioConnection};
+use crate::run::{server_command, RunError};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_BODY: usize = 16 * 1024 * 1024;
+/// Sessions unused this long (and without an open stream) are removed.
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where to listen.
+#[derive(Debug, Clone)]
+pub enum Listen {
+    /// `127.0.0.1:<port>`
+    Tcp(u16),
+    /// Unix domain socket path
+    Unix(PathBuf),
+}
+
+/// Serve `id` until the process is killed.
+/// With `shared`, one child process serves every session; otherwise each session spawns its own.
+pub fn serve(paths: &Paths, id: &str, listen: &Listen, shared: bool) -> Result<(), ServeError> {
+    // Fail early if the server can't be launched at all
+    server_command(paths, id).map_err(ServeError::Run)?;
+    let policy = Arc::new(Policy::load(paths).map_err(ServeError::Policy)?);
+
+    let shared_backend = if shared {
+        Some(Backend::spawn(paths, id, true)?)
+    } else {
+        None
+    };
+    let server = Arc::new(Server {
+        paths: paths.clone(),
+        id: id.to_string(),
+        shared: shared_backend,
+        policy,
+        sessions: Mutex::new(HashMap::new()),
+    });
+
+    // Clients that never send DELETE would otherwise keep their child forever
+    let reaper = Arc::downgrade(&server);
+    std::thread::spawn(move || loop {
+        std::thread::sleep(REAP_INTERVAL);
+        let Some(server) = reaper.upgrade() else { break };
+        server.reap_idle(SESSION_IDLE_TIMEOUT);
+    });
+
+    match listen {
+        Listen::Tcp(port) => {
+            let listener = std::net::TcpListener::bind(("127.0.0.1", *port)).map_err(ServeError::Bind)?;
+            eprintln!("Serving {} on http://127.0.0.1:{} (Streamable HTTP: /mcp, SSE: /sse)", id, port);
+            for stream in listener.incoming().flatten() {
+                let server = Arc::clone(&server);
+                std::thread::spawn(move || server.handle(stream));
+            }
+        }
+        Listen::Unix(path) => {
+            // Replace a stale socket from a previous run, never a regular file
+            if let Ok(meta) = std::fs::symlink_metadata(path) {
+                use std::os::unix::fs::FileTypeExt;
+                if meta.file_type().is_socket() {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+            let listener = std::os::unix::net::UnixListener::bind(path).map_err(ServeError::Bind)?;
+            eprintln!("Serving {} on unix:{} (Streamable HTTP: /mcp, SSE: /sse)", id, path.display());
+            for stream in listener.incoming().flatten() {
+                let server = Arc::clone(&server);
+                std::thread::spawn(move || server.handle(stream));
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Server {
+    paths: Paths,
+    id: String,
+    shared: Option<Arc<Backend>>,
+    policy: Arc<Policy>,
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+struct Session {
+    backend: Arc<Backend>,
+    /// Open event stream (GET), if any. Legacy SSE sessions always have one.
+    stream: Mutex<Option<Sender<Value>>>,
+    /// When the client last made a request on this session.
+    last_seen: Mutex<Instant>,
+    /// Event streams currently being written; a session with one is never idle.
+    streams: AtomicUsize,
+    /// Legacy SSE sessions: queue feeding the child in the order messages were posted.
+    inbound: Mutex<Option<Sender<Value>>>,
+}
+
+impl Session {
+    fn new(backend: Arc<Backend>) -> Self {
+        Session {
+            backend,
+            stream: Mutex::new(None),
+            last_seen: Mutex::new(Instant::now()),
+            streams: AtomicUsize::new(0),
+            inbound: Mutex::new(None),
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    fn is_idle(&self, timeout: Duration) -> bool {
+        self.streams.load(Ordering::SeqCst) == 0 && self.last_seen.lock().unwrap().elapsed() >= timeout
+    }
+
+    /// Relay the session's messages to `stream` until the client goes away.
+    fn stream_to<S: Write>(&self, stream: &mut S, endpoint: Option<&str>) -> std::io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        *self.stream.lock().unwrap() = Some(tx);
+        self.streams.fetch_add(1, Ordering::SeqCst);
+        let result = stream_events(stream, rx, endpoint);
+        self.streams.fetch_sub(1, Ordering::SeqCst);
+        self.touch();
+        result
+    }
+
+    /// Deliver a message on the session's event stream. Returns false if none is open.
+    fn push(&self, msg: &Value) -> bool {
+        let mut stream = self.stream.lock().unwrap();
+        match stream.as_ref() {
+            Some(tx) if tx.send(msg.clone()).is_ok() => true,
+            Some(_) => {
+                *stream = None;
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Server {
+    fn handle<S: Read + Write>(&self, mut stream: S) {
+        let req = match read_request(&mut stream) {
+            Ok(r) => r,
+            Err(_) => {
+                let _ = respond(&mut stream, 400, &[], b"");
+                return;
+            }
+        };
+        if !origin_allowed(&req) {
+            let _ = respond(&mut stream, 403, &[], b"");
+            return;
+        }
+        let _ = match (req.method.as_str(), req.path.as_str()) {
+            ("POST", "/mcp") => self.post_mcp(&mut stream, &req),
+            ("GET", "/mcp") => self.get_mcp(&mut stream, &req),
+            ("DELETE", "/mcp") => self.delete_mcp(&mut stream, &req),
+            ("GET", "/sse") => self.get_sse(&mut stream),
+            ("POST", "/messages") => self.post_messages(&mut stream, &req),
+            (_, "/mcp") => respond(&mut stream, 405, &[("Allow", "GET, POST, DELETE")], b""),
+            _ => respond(&mut stream, 404, &[], b""),
+        };
+    }
+
+    fn new_session(&self) -> Result<(String, Arc<Session>), ServeError> {
+        let backend = match &self.shared {
+            Some(b) => Arc::clone(b),
+            None => Backend::spawn(&self.paths, &self.id, false)?,
+        };
+        let session = Arc::new(Session::new(Arc::clone(&backend)));
+        backend.state.lock().unwrap().sessions.push(Arc::downgrade(&session));
+        let sid = new_session_id()?;
+        self.sessions.lock().unwrap().insert(sid.clone(), Arc::clone(&session));
+        Ok((sid, session))
+    }
+
+    fn session(&self, sid: Option<&String>) -> Option<Arc<Session>> {
+        let session = self.sessions.lock().unwrap().get(sid?).cloned()?;
+        session.touch();
+        Some(session)
+    }
+
+    /// Remove sessions idle for `timeout`. A dedicated child stops once its
+    /// session's in-flight requests finish. Returns how many were removed.
+    fn reap_idle(&self, timeout: Duration) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, s| !s.is_idle(timeout));
+        before - sessions.len()
+    }
+
+    fn post_mcp<S: Write>(&self, stream: &mut S, req: &Request) -> std::io::Result<()> {
+        let Ok(body) = serde_json::from_slice::<Value>(&req.body) else {
+            return respond_json(stream, 400, &[], &rpc_error(Value::Null, -32700, "Parse error"));
+        };
+        let messages = match &body {
+            Value::Array(batch) => batch.clone(),
+            msg => vec![msg.clone()],
+        };
+        let is_init = messages
+            .iter()
+            .any(|m| m.get("method").and_then(|v| v.as_str()) == Some("initialize"));
+
+        let (sid, session) = match self.session(req.headers.get("mcp-session-id")) {
+            Some(s) => (req.headers["mcp-session-id"].clone(), s),
+            None if req.headers.contains_key("mcp-session-id") => return respond(stream, 404, &[], b""),
+            None if is_init => match self.new_session() {
+                Ok(s) => s,
+                Err(e) => return respond_json(stream, 500, &[], &rpc_error(Value::Null, -32603, &e.to_string())),
+            },
+            None => return respond(stream, 400, &[], b"Missing Mcp-Session-Id"),
+        };
+
+        let replies: Vec<Value> = messages
+            .iter()
+            .filter_map(|m| dispatch(&session.backend, &self.policy, &self.id, m))
+            .collect();
+        let session_header = [("Mcp-Session-Id", sid.as_str())];
+        match (body.is_array(), replies.len()) {
+            (_, 0) => respond(stream, 202, &session_header, b""),
+            (false, _) => respond_json(stream, 200, &session_header, &replies[0]),
+            (true, _) => respond_json(stream, 200, &session_header, &Value::Array(replies)),
+        }
+    }
+
+    fn get_mcp<S: Write>(&self, stream: &mut S, req: &Request) -> std::io::Result<()> {
+        let Some(session) = self.session(req.headers.get("mcp-session-id")) else {
+            return respond(stream, 404, &[], b"");
+        };
+        session.stream_to(stream, None)
+    }
+
+    fn delete_mcp<S: Write>(&self, stream: &mut S, req: &Request) -> std::io::Result<()> {
+        let removed = req
+            .headers
+            .get("mcp-session-id")
+            .and_then(|sid| self.sessions.lock().unwrap().remove(sid));
+        respond(stream, if removed.is_some() { 200 } else { 404 }, &[], b"")
+    }
+
+    fn get_sse<S: Write>(&self, stream: &mut S) -> std::io::Result<()> {
+        let (sid, session) = match self.new_session() {
+            Ok(s) => s,
+            Err(e) => return respond(stream, 500, &[], e.to_string().as_bytes()),
+        };
+        let (tx, rx) = mpsc::channel();
+        *session.inbound.lock().unwrap() = Some(tx);
+        let relay = Arc::clone(&session);
+        let policy = Arc::clone(&self.policy);
+        let id = self.id.clone();
+        std::thread::spawn(move || relay_in_order(&relay, &policy, &id, rx));
+
+        let endpoint = format!("/messages?sessionId={}", sid);
+        let result = session.stream_to(stream, Some(&endpoint));
+        self.sessions.lock().unwrap().remove(&sid);
+        *session.inbound.lock().unwrap() = None;
+        result
+    }
+
+    fn post_messages<S: Write>(&self, stream: &mut S, req: &Request) -> std::io::Result<()> {
+        let Some(session) = self.session(req.query.get("sessionId")) else {
+            return respond(stream, 404, &[], b"");
+        };
+        let Ok(msg) = serde_json::from_slice::<Value>(&req.body) else {
+            return respond(stream, 400, &[], b"Invalid JSON");
+        };
+        // Queue before acknowledging so a client that waits for each 202 gets its order kept.
+        // Replies go out on the event stream; don't hold the POST open for them
+        let queued = session.inbound.lock().unwrap().as_ref().is_some_and(|q| q.send(msg).is_ok());
+        if !queued {
+            return respond(stream, 404, &[], b"");
+        }
+        respond(stream, 202, &[], b"")
+    }
+}
+
+struct Waiter {
+    original_id: Value,
+    reply: Sender<Value>,
+}
+
+#[derive(Default)]
+struct BackendState {
+    next_id: u64,
+    waiting: HashMap<u64, Waiter>,
+    init_result: Option<Value>,
+    initialized: bool,
+    sessions: Vec<Weak<Session>>,
+    closed: bool,
+}
+
+/// One child process, shared by one or more sessions.
+struct Backend {
+    shared: bool,
+    conn: Mutex<Box<dyn Connection>>,
+    state: Mutex<BackendState>,
+}
+
+impl Backend {
+    fn spawn(paths: &Paths, id: &str, shared: bool) -> Result<Arc<Self>, ServeError> {
+        let cmd = server_command(paths, id).map_err(ServeError::Run)?;
+        Self::start(cmd, shared)
+    }
+
+    fn start(cmd: Command, shared: bool) -> Result<Arc<Self>, ServeError> {
+        let conn = StdioConnection::spawn(cmd).map_err(ServeError::Protocol)?;
+        let backend = Arc::new(Backend {
+            shared,
+            conn: Mutex::new(Box::new(conn)),
+            state: Mutex::new(BackendState::default()),
+        });
+        // The pump only holds a weak reference so the child dies with its last session
+        let weak = Arc::downgrade(&backend);
+        std::thread::spawn(move || loop {
+            let Some(backend) = weak.upgrade() else { break };
+            if !backend.pump_once() {
+                break;
+            }
+        });
+        Ok(backend)
+    }
+
+    /// Read one message from the child and route it. Returns false once the child is gone.
+    fn pump_once(&self) -> bool {
+        let msg = match self.conn.lock().unwrap().recv_timeout(POLL_INTERVAL) {
+            Ok(Some(m)) => m,
+            Ok(None) => return true,
+            Err(_) => {
+                let mut state = self.state.lock().unwrap();
+                state.closed = true;
+                state.waiting.clear();
+                return false;
+            }
+        };
+
+        let has_method = msg.get("method").is_some();
+        match (msg.get("id"), has_method) {
+            // Response to one of our rewritten requests
+            (Some(id), false) => {
+                let waiter = id.as_u64().and_then(|n| self.state.lock().unwrap().waiting.remove(&n));
+                if let Some(w) = waiter {
+                    let mut reply = msg.clone();
+                    reply["id"] = w.original_id;
+                    let _ = w.reply.send(reply);
+                }
+            }
+            // Server-initiated request: only a dedicated session can answer it
+            (Some(id), true) => {
+                let session = if self.shared { None } else { self.live_sessions().into_iter().next() };
+                let delivered = session.map(|s| s.push(&msg)).unwrap_or(false);
+                if !delivered {
+                    let reply = if msg.get("method").and_then(|m| m.as_str()) == Some("ping") {
+                        json!({"jsonrpc": "2.0", "id": id, "result": {}})
+                    } else {
+                        rpc_error(id.clone(), -32601, "Method not found")
+                    };
+                    let _ = self.conn.lock().unwrap().send(&reply);
+                }
+            }
+            // Notification: broadcast to every session with an open stream
+            (None, _) => {
+                for s in self.live_sessions() {
+                    s.push(&msg);
+                }
+            }
+        }
+        true
+    }
+
+    fn live_sessions(&self) -> Vec<Arc<Session>> {
+        let mut state = self.state.lock().unwrap();
+        state.sessions.retain(|w| w.strong_count() > 0);
+        state.sessions.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Forward one client message and wait for the reply to requests.
+    fn handle_client_message(&self, msg: &Value) -> Option<Value> {
+        match self.forward(msg) {
+            Forwarded::Reply(reply) => reply,
+            Forwarded::Pending(pending) => Some(self.wait(pending)),
+        }
+    }
+
+    /// Send one client message to the child without waiting for its reply.
+    fn forward(&self, msg: &Value) -> Forwarded {
+        let method = msg.get("method").and_then(|m| m.as_str());
+        let Some(id) = msg.get("id").cloned() else {
+            // Notification; a shared child only sees `initialized` once
+            if method == Some("notifications/initialized") {
+                let mut state = self.state.lock().unwrap();
+                if state.initialized {
+                    return Forwarded::Reply(None);
+                }
+                state.initialized = true;
+            }
+            let _ = self.conn.lock().unwrap().send(msg);
+            return Forwarded::Reply(None);
+        };
+        if method.is_none() {
+            // Client's answer to a server-initiated request
+            let _ = self.conn.lock().unwrap().send(msg);
+            return Forwarded::Reply(None);
+        }
+
+        if method == Some("initialize") {
+            if let Some(result) = self.state.lock().unwrap().init_result.clone() {
+                return Forwarded::Reply(Some(json!({"jsonrpc": "2.0", "id": id, "result": result})));
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let n = {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return Forwarded::Reply(Some(rpc_error(id, -32603, "Server process exited")));
+            }
+            state.next_id += 1;
+            let n = state.next_id;
+            state.waiting.insert(n, Waiter { original_id: id.clone(), reply: tx });
+            n
+        };
+        let mut forwarded = msg.clone();
+        forwarded["id"] = json!(n);
+        if let Err(e) = self.conn.lock().unwrap().send(&forwarded) {
+            self.state.lock().unwrap().waiting.remove(&n);
+            return Forwarded::Reply(Some(rpc_error(id, -32603, &e.to_string())));
+        }
+        Forwarded::Pending(Pending {
+            id,
+            n,
+            initialize: method == Some("initialize"),
+            reply: rx,
+        })
+    }
+
+    /// Wait for the reply to a forwarded request.
+    fn wait(&self, pending: Pending) -> Value {
+        let Pending { id, n, initialize, reply } = pending;
+        let reply = match reply.recv_timeout(REQUEST_TIMEOUT) {
+            Ok(r) => r,
+            Err(RecvTimeoutError::Timeout) => {
+                self.state.lock().unwrap().waiting.remove(&n);
+                return rpc_error(id, -32603, "Timed out waiting for server");
+            }
+            Err(RecvTimeoutError::Disconnected) => return rpc_error(id, -32603, "Server process exited"),
+        };
+        if self.shared && initialize {
+            if let Some(result) = reply.get("result") {
+                self.state.lock().unwrap().init_result = Some(result.clone());
+            }
+        }
+        reply
+    }
+}
+
+/// Outcome of [`Backend::forward`].
+enum Forwarded {
+    /// Answered without waiting on the child (`None` for notifications and responses).
+    Reply(Option<Value>),
+    /// Sent to the child; the reply arrives through [`Backend::wait`].
+    Pending(Pending),
+}
+
+struct Pending {
+    /// The client's id for the request.
+    id: Value,
+    /// The rewritten id the child sees.
+    n: u64,
+    initialize: bool,
+    reply: mpsc::Receiver<Value>,
+}
+
+/// Forward a client message through the tool policy: denied calls are answered
+/// here and `tools/list` results are filtered.
+fn dispatch(backend: &Backend, policy: &Policy, server_id: &str, msg: &Value) -> Option<Value> {
+    if let Some(denied) = policy.check_call(server_id, msg) {
+        return Some(denied);
+    }
+    let reply = backend.handle_client_message(msg)?;
+    Some(filter_reply(policy, server_id, msg, reply))
+}
+
+/// Drop denied tools from the reply to a `tools/list` request.
+fn filter_reply(policy: &Policy, server_id: &str, msg: &Value, mut reply: Value) -> Value {
+    if msg.get("method").and_then(|m| m.as_str()) == Some("tools/list") {
+        if let Some(tools) = reply.pointer_mut("/result/tools").and_then(|t| t.as_array_mut()) {
+            policy.filter_tools(server_id, tools);
+        }
+    }
+    reply
+}
+
+/// Legacy SSE: forward queued messages to the child one at a time, in order,
+/// and push each reply to the event stream once it arrives.
+fn relay_in_order(session: &Arc<Session>, policy: &Arc<Policy>, server_id: &str, queue: mpsc::Receiver<Value>) {
+    for msg in queue {
+        if let Some(denied) = policy.check_call(server_id, &msg) {
+            session.push(&denied);
+            continue;
+        }
+        match session.backend.forward(&msg) {
+            Forwarded::Reply(reply) => {
+                if let Some(reply) = reply {
+                    session.push(&reply);
+                }
+            }
+            // Slow requests don't hold up the ones behind them
+            Forwarded::Pending(pending) => {
+                let session = Arc::clone(session);
+                let policy = Arc::clone(policy);
+                let server_id = server_id.to_string();
+                std::thread::spawn(move || {
+                    let reply = session.backend.wait(pending);
+                    session.push(&filter_reply(&policy, &server_id, &msg, reply));
+                });
+            }
+        }
+    }
+}
+
+/// Write SSE headers, an optional legacy `endpoint` event, then relay messages until the client goes away.
+fn stream_events<S: Write>(
+    stream: &mut S,
+    rx: mpsc::Receiver<Value>,
+    endpoint: Option<&str>,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    )?;
+    if let Some(e) = endpoint {
+        write!(stream, "event: endpoint\ndata: {}\n\n", e)?;
+    }
+    stream.flush()?;
+    loop {
+        match rx.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(msg) => write!(stream, "event: message\ndata: {}\n\n", msg)?,
+            // Comment line doubles as a liveness probe for the client connection
+            Err(RecvTimeoutError::Timeout) => write!(stream, ": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        stream.flush()?;
+    }
+}
+
+fn read_request<S: Read>(stream: &mut S) -> std::io::Result<Request> {
+    let bad = || std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed request");
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(bad)?.to_string();
+    let target = parts.next().ok_or_else(bad)?;
+    let (path, query_str) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.to_string();
+    let query = query_str
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(bad());
+        }
+        let l = line.trim_end();
+        if l.is_empty() {
+            break;
+        }
+        if let Some((k, v)) = l.split_once(':') {
+            headers.insert(k.trim().to_ascii_lowercase(), v.trim().to_string());
+        }
+    }
+
+    let len: usize = headers
+        .get("content-length")
+        .map(|v| v.parse().map_err(|_| bad()))
+        .transpose()?
+        .unwrap_or(0);
+    if len > MAX_BODY {
+        return Err(bad());
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+fn respond<S: Write>(stream: &mut S, status: u16, headers: &[(&str, &str)], body: &[u8]) -> std::io::Result<()> {
+    write!(stream, "HTTP/1.1 {} {}\r\n", status, reason(status))?;
+    for (k, v) in headers {
+        write!(stream, "{}: {}\r\n", k, v)?;
+    }
+    write!(stream, "Content-Length: {}\r\nConnection: close\r\n\r\n", body.len())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn respond_json<S: Write>(stream: &mut S, status: u16, headers: &[(&str, &str)], body: &Value) -> std::io::Result<()> {
+    let mut all = headers.to_vec();
+    all.push(("Content-Type", "application/json"));
+    respond(stream, status, &all, body.to_string().as_bytes())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn rpc_error(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+/// Browsers send `Origin`; only accept local pages to prevent DNS rebinding.
+fn origin_allowed(req: &Request) -> bool {
+    let Some(origin) = req.headers.get("origin") else {
+        return true;
+    };
+    let authority = origin.split("://").nth(1).unwrap_or("").split('/').next().unwrap_or("");
+    let host = match authority.rsplit_once(':') {
+        Some((h, port)) if !port.contains(']') => h,
+        _ => authority,
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+/// 128-bit session token from the OS random source.
+fn new_session_id() -> Result<String, ServeError> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(ServeError::SessionId)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[derive(Debug)]
+pub enum ServeError {
+    Run(RunError),
+    Protocol(ProtocolError),
+    Policy(PolicyError),
+    Bind(std::io::Error),
+    SessionId(getrandom::Error),
+}
+
+impl std::fmt::Display for ServeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServeError::Run(e) => write!(f, "{}", e),
+            ServeError::Protocol(e) => write!(f, "{}", e),
+            ServeError::Policy(e) => write!(f, "{}", e),
+            ServeError::Bind(e) => write!(f, "Failed to listen: {}", e),
+            ServeError::SessionId(e) => write!(f, "Failed to generate session id: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{PolicyFile, ToolRules, DENIED_CODE};
+
+    fn server() -> Server {
+        Server {
+            paths: Paths::resolve(),
+            id: "com.example.test".to_string(),
+            shared: None,
+            policy: Arc::new(Policy::default()),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn reaps_idle_sessions_and_stops_their_child() {
+        let server = server();
+        let old = Backend::start(Command::new("cat"), false).unwrap();
+        let old_backend = Arc::downgrade(&old);
+        let idle = Session::new(old);
+        *idle.last_seen.lock().unwrap() -= Duration::from_secs(120);
+        let streaming = Session::new(Backend::start(Command::new("cat"), false).unwrap());
+        *streaming.last_seen.lock().unwrap() -= Duration::from_secs(120);
+        streaming.streams.fetch_add(1, Ordering::SeqCst);
+        let fresh = Session::new(Backend::start(Command::new("cat"), false).unwrap());
+        {
+            let mut sessions = server.sessions.lock().unwrap();
+            sessions.insert("idle".to_string(), Arc::new(idle));
+            sessions.insert("streaming".to_string(), Arc::new(streaming));
+            sessions.insert("fresh".to_string(), Arc::new(fresh));
+        }
+
+        assert_eq!(server.reap_idle(Duration::from_secs(60)), 1);
+        let mut left: Vec<String> = server.sessions.lock().unwrap().keys().cloned().collect();
+        left.sort();
+        assert_eq!(left, ["fresh", "streaming"]);
+
+        // The pump only borrows the backend briefly; once it lets go the child is killed
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while old_backend.upgrade().is_some() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        assert!(old_backend.upgrade().is_none());
+    }
+
+    #[test]
+    fn requests_keep_a_session_alive() {
+        let server = server();
+        let session = Session::new(Backend::start(Command::new("cat"), false).unwrap());
+        *session.last_seen.lock().unwrap() -= Duration::from_secs(120);
+        server.sessions.lock().unwrap().insert("s".to_string(), Arc::new(session));
+        assert!(server.session(Some(&"s".to_string())).is_some());
+        assert_eq!(server.reap_idle(Duration::from_secs(60)), 0);
+    }
+
+    #[test]
+    fn session_ids_are_random_hex() {
+        let a = new_session_id().unwrap();
+        let b = new_session_id().unwrap();
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+
+    /// Serve a shared child that copies every line it receives to `log` and answers
+    /// each request with a result echoing its method and params. Tool `secret` is denied.
+    /// Returns the base URL.
+    fn http_server(log: &std::path::Path) -> String {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(r#"tee "$0" | sed -u 's/"method":/"result":{},"echo":/'"#)
+            .arg(log);
+        let mut system = PolicyFile::default();
+        system.servers.insert(
+            "com.example.test".to_string(),
+            ToolRules {
+                allow: Vec::new(),
+                deny: vec!["secret".to_string()],
+            },
+        );
+        let server = Arc::new(Server {
+            shared: Some(Backend::start(cmd, true).unwrap()),
+            policy: Arc::new(Policy {
+                system,
+                user: PolicyFile::default(),
+            }),
+            ..server()
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = Arc::clone(&server);
+                std::thread::spawn(move || server.handle(stream));
+            }
+        });
+        url
+    }
+
+    /// Payload of the next SSE `data:` line.
+    fn next_data<R: BufRead>(events: &mut R) -> String {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            assert!(events.read_line(&mut line).unwrap() > 0, "event stream closed");
+            if let Some(data) = line.strip_prefix("data: ") {
+                return data.trim_end().to_string();
+            }
+        }
+    }
+
+    fn call(id: u64, tool: &str) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": "tools/call", "params": {"name": tool, "arguments": {"seq": id}}})
+    }
+
+    #[test]
+    fn legacy_messages_reach_the_child_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("child.log");
+        let base = http_server(&log);
+        let mut events = BufReader::new(reqwest::blocking::get(format!("{}/sse", base)).unwrap());
+        let endpoint = next_data(&mut events);
+        assert!(endpoint.starts_with("/messages?sessionId="));
+
+        let client = reqwest::blocking::Client::new();
+        for seq in 0..20 {
+            let response = client.post(format!("{}{}", base, endpoint)).json(&call(seq, "echo")).send().unwrap();
+            assert_eq!(response.status(), 202);
+        }
+        let denied = client.post(format!("{}{}", base, endpoint)).json(&call(20, "secret")).send().unwrap();
+        assert_eq!(denied.status(), 202);
+
+        let mut replies: Vec<Value> = (0..21).map(|_| serde_json::from_str(&next_data(&mut events)).unwrap()).collect();
+        replies.sort_by_key(|r| r["id"].as_u64());
+        for (seq, reply) in replies[..20].iter().enumerate() {
+            assert_eq!(reply["id"], json!(seq));
+            assert_eq!(reply["echo"], "tools/call");
+            assert_eq!(reply["params"]["arguments"]["seq"], json!(seq));
+        }
+        assert_eq!(replies[20]["error"]["code"], DENIED_CODE);
+
+        // The child saw every allowed call exactly once, in the order it was posted
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let received = loop {
+            let received: Vec<u64> = std::fs::read_to_string(&log)
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str::<Value>(l).unwrap()["params"]["arguments"]["seq"].as_u64().unwrap())
+                .collect();
+            if received.len() >= 20 || Instant::now() > deadline {
+                break received;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        assert_eq!(received, (0..20).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn streamable_post_returns_the_child_reply() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = http_server(&dir.path().join("child.log"));
+        let client = reqwest::blocking::Client::new();
+        let url = format!("{}/mcp", base);
+
+        let missing = client.post(&url).json(&call(1, "echo")).send().unwrap();
+        assert_eq!(missing.status(), 400);
+
+        let init = json!({"jsonrpc": "2.0", "id": "init", "method": "initialize", "params": {}});
+        let response = client.post(&url).json(&init).send().unwrap();
+        assert_eq!(response.status(), 200);
+        let sid = response.headers()["mcp-session-id"].to_str().unwrap().to_string();
+        let reply: Value = response.json().unwrap();
+        assert_eq!(reply["id"], "init");
+        assert_eq!(reply["echo"], "initialize");
+
+        let reply: Value = client
+            .post(&url)
+            .header("Mcp-Session-Id", &sid)
+            .json(&call(7, "echo"))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["params"]["name"], "echo");
+
+        let reply: Value = client
+            .post(&url)
+            .header("Mcp-Session-Id", &sid)
+            .json(&call(8, "secret"))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(reply["error"]["code"], DENIED_CODE);
+    }
+}
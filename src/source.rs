@@ -0,0 +1,750 @@
+//! Fetch a local (stdio) server's files from its registry `source`.
+//!
+//! Supported `source.type` values:
+//! - `git` (default): clone `url`, optionally at `ref` or `commit`
+//! - `archive`: download a `.tar.gz`/`.tgz`/`.tar`/`.zip` from `url`, verify `sha256`, extract
+//! - `local`: copy a directory, or extract an archive file, given as a path or `file://` URL
+//! - `npm`, `pypi`, `cargo`: install `package` into a private prefix in the install dir
+//!   (`node_modules/`, `venv/`, `bin/`); see [`Launcher`]
+//!
+//! For the first three, `path` selects the project root within the fetched tree.
+
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::models::{InstalledFrom, Source};
+
+/// What [`fetch_source`] installed.
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub from: InstalledFrom,
+    /// Set for package sources: the stdio command must be resolved inside the prefix.
+    pub launcher: Option<Launcher>,
+}
+
+/// Package manager prefix a server was installed into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Launcher {
+    Npm,
+    Pypi,
+    Cargo,
+}
+
+impl Launcher {
+    fn for_type(kind: &str) -> Option<Self> {
+        match kind {
+            "npm" => Some(Launcher::Npm),
+            "pypi" => Some(Launcher::Pypi),
+            "cargo" => Some(Launcher::Cargo),
+            _ => None,
+        }
+    }
+
+    /// Executables directory, relative to the install dir.
+    pub fn bin_dir(&self) -> &'static str {
+        match self {
+            Launcher::Npm => "node_modules/.bin",
+            Launcher::Pypi => "venv/bin",
+            Launcher::Cargo => "bin",
+        }
+    }
+
+    /// Point a stdio `command` + `args` at the executable installed in `dir`.
+    /// Launch-time wrappers (`npx [-y] <pkg>`, `uvx <pkg>`, `pipx run <pkg>`) are
+    /// replaced by the package's executable; other commands are only rewritten if
+    /// the package provides them. The result is relative to the install dir,
+    /// which `dmcp run` uses as the working directory.
+    pub fn resolve(&self, dir: &Path, package: &str, command: &str, args: &[String]) -> Result<(String, Vec<String>), SourceError> {
+        let bin_dir = self.bin_dir();
+        let installed = |name: &String| dir.join(bin_dir).join(name).exists();
+        let (name, rest) = match (command, args) {
+            ("npx" | "uvx", _) | ("pipx", [_, ..]) => {
+                let skip = usize::from(command == "pipx" && args[0] == "run");
+                let args = &args[skip..];
+                let pos = args.iter().position(|a| !a.starts_with('-')).unwrap_or(args.len());
+                let (names, rest) = match args.get(pos) {
+                    Some(spec) => (vec![package_name(spec), package_name(package)], &args[pos + 1..]),
+                    None => (vec![package_name(package)], &args[pos..]),
+                };
+                let name = names
+                    .iter()
+                    .find(|n| installed(n))
+                    .ok_or_else(|| SourceError::CommandNotFound(names[0].clone(), bin_dir))?;
+                (name.clone(), rest.to_vec())
+            }
+            _ if !command.contains('/') && installed(&command.to_string()) => (command.to_string(), args.to_vec()),
+            _ => return Ok((command.to_string(), args.to_vec())),
+        };
+        let program = format!("{}/{}", bin_dir, name);
+        Ok(match self {
+            // Console scripts carry the staging path in their shebang, so run them through the venv's python
+            Launcher::Pypi => ("venv/bin/python".to_string(), [vec![program], rest].concat()),
+            Launcher::Npm | Launcher::Cargo => (program, rest),
+        })
+    }
+}
+
+/// Bare package name: scope, version and extras removed (`@org/pkg@1.0` is `pkg`, `pkg[x]==1.0` is `pkg`).
+fn package_name(spec: &str) -> String {
+    let spec = spec.rsplit('/').next().unwrap_or(spec);
+    let end = spec.find(['@', '=', '<', '>', '~', '!', '[', ' ', ';']).unwrap_or(spec.len());
+    spec[..end].to_string()
+}
+
+/// Fetch `source` into `dest` (created if needed) and report what was installed.
+pub fn fetch_source(source: &Source, dest: &Path) -> Result<Fetched, SourceError> {
+    let kind = source.kind.as_deref().unwrap_or("git");
+    if let Some(launcher) = Launcher::for_type(kind) {
+        std::fs::create_dir_all(dest).map_err(SourceError::Io)?;
+        let from = install_package(launcher, source, dest)?;
+        return Ok(Fetched {
+            from,
+            launcher: Some(launcher),
+        });
+    }
+
+    let url = source.url.as_deref().ok_or(SourceError::MissingField("url"))?;
+    // Private (0700) scratch dir with a random name, removed when dropped
+    let temp = tempfile::Builder::new()
+        .prefix("dmcp-fetch-")
+        .tempdir()
+        .map_err(SourceError::Io)?;
+
+    let (root, from) = match kind {
+        "git" => fetch_git(source, url, temp.path()),
+        "archive" => fetch_archive(source, url, temp.path()),
+        "local" => fetch_local(source, url, temp.path()),
+        other => Err(SourceError::UnsupportedType(other.to_string())),
+    }?;
+    copy_project(&root, source.path.as_deref().unwrap_or(""), dest)?;
+    Ok(Fetched { from, launcher: None })
+}
+
+/// Clone into `work` and return the checkout root.
+fn fetch_git(source: &Source, url: &str, work: &Path) -> Result<(PathBuf, InstalledFrom), SourceError> {
+    let dest = work.to_str().unwrap();
+    match (source.commit.as_deref(), source.git_ref.as_deref()) {
+        // Any commit may be requested, so fetch history (without blobs) and check it out
+        (Some(commit), _) => {
+            git(&["clone", "--filter=blob:none", "--no-checkout", url, dest], "clone")?;
+            let target = format!("{}^{{commit}}", commit);
+            git(&["-C", dest, "rev-parse", "--quiet", "--verify", &target], "rev-parse")
+                .map_err(|_| SourceError::CommitNotFound(commit.to_string()))?;
+            git(&["-C", dest, "checkout", "--quiet", "--detach", &target], "checkout")?;
+        }
+        (None, Some(r)) => git(&["clone", "--depth", "1", "--filter=blob:none", "--branch", r, url, dest], "clone")?,
+        (None, None) => git(&["clone", "--depth", "1", "--filter=blob:none", url, dest], "clone")?,
+    }
+    let commit = Command::new("git")
+        .args(["-C", dest, "rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+
+    let from = InstalledFrom {
+        url: url.to_string(),
+        git_ref: source.git_ref.clone(),
+        commit,
+        ..Default::default()
+    };
+    Ok((work.to_path_buf(), from))
+}
+
+fn git(args: &[&str], what: &str) -> Result<(), SourceError> {
+    let mut cmd = Command::new("git");
+    cmd.args(["-c", "advice.detachedHead=false"]).args(args).stdout(Stdio::null());
+    run(cmd, &format!("git {}", what))
+}
+
+fn run(mut cmd: Command, what: &str) -> Result<(), SourceError> {
+    let status = cmd.status().map_err(|e| SourceError::Tool(what.to_string(), Some(e)))?;
+    if !status.success() {
+        return Err(SourceError::Tool(what.to_string(), None));
+    }
+    Ok(())
+}
+
+/// Install a package into a prefix in `dest`. `url` (a package file or URL)
+/// replaces the registry lookup for npm and PyPI, and is a path or git repo for cargo.
+///
+/// Package code must not run at install time without the user's consent, so npm
+/// lifecycle scripts are skipped and pip only installs wheels (building an sdist
+/// runs its `setup.py`). Entries that need build steps list them under `setup`.
+fn install_package(launcher: Launcher, source: &Source, dest: &Path) -> Result<InstalledFrom, SourceError> {
+    let package = source.package.as_deref().ok_or(SourceError::MissingField("package"))?;
+    let url = source.url.as_deref().map(|u| u.strip_prefix("file://").unwrap_or(u));
+    let version = source.version.as_deref();
+
+    let installed_version = match launcher {
+        Launcher::Npm => {
+            let spec = match (url, version) {
+                (Some(u), _) => u.to_string(),
+                (None, Some(v)) => format!("{}@{}", package, v),
+                (None, None) => package.to_string(),
+            };
+            let mut cmd = Command::new("npm");
+            cmd.args(["install", "--ignore-scripts", "--no-audit", "--no-fund", "--omit=dev", "--prefix"])
+                .arg(dest)
+                .arg(&spec)
+                .stdout(Stdio::from(std::io::stderr()));
+            run(cmd, "npm install")?;
+            std::fs::read_to_string(dest.join("node_modules").join(package).join("package.json"))
+                .ok()
+                .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+                .and_then(|v| v.get("version").and_then(|v| v.as_str()).map(String::from))
+        }
+        Launcher::Pypi => {
+            // `--only-binary` doesn't cover a direct file or URL, so that must be a wheel
+            if let Some(u) = url.filter(|u| !u.split(['?', '#']).next().unwrap_or(u).ends_with(".whl")) {
+                return Err(SourceError::NotAWheel(u.to_string()));
+            }
+            let venv = dest.join("venv");
+            let mut cmd = Command::new("python3");
+            cmd.args(["-m", "venv"]).arg(&venv);
+            run(cmd, "python3 -m venv")?;
+            let spec = match (url, version) {
+                (Some(u), _) => u.to_string(),
+                (None, Some(v)) => format!("{}=={}", package, v),
+                (None, None) => package.to_string(),
+            };
+            let python = venv.join("bin").join("python");
+            let mut cmd = Command::new(&python);
+            cmd.args(["-m", "pip", "install", "--disable-pip-version-check", "--no-input", "--only-binary", ":all:", &spec])
+                .stdout(Stdio::from(std::io::stderr()));
+            run(cmd, "pip install")?;
+            Command::new(&python)
+                .args(["-m", "pip", "show", "--disable-pip-version-check", package])
+                .output()
+                .ok()
+                .and_then(|o| {
+                    String::from_utf8_lossy(&o.stdout)
+                        .lines()
+                        .find_map(|l| l.strip_prefix("Version:").map(|v| v.trim().to_string()))
+                })
+        }
+        Launcher::Cargo => {
+            let mut cmd = Command::new("cargo");
+            cmd.args(["install", "--locked", "--root"]).arg(dest);
+            match url {
+                Some(u) if Path::new(u).is_dir() => {
+                    cmd.args(["--path", u]);
+                }
+                Some(u) => {
+                    cmd.args(["--git", u]);
+                    if let Some(rev) = source.commit.as_deref().or(source.git_ref.as_deref()) {
+                        cmd.args(["--rev", rev]);
+                    }
+                }
+                None => {}
+            }
+            if let (None, Some(v)) = (url, version) {
+                cmd.args(["--version", v]);
+            }
+            cmd.arg(package).stdout(Stdio::from(std::io::stderr()));
+            run(cmd, "cargo install")?;
+            Command::new("cargo")
+                .args(["install", "--list", "--root"])
+                .arg(dest)
+                .output()
+                .ok()
+                .and_then(|o| {
+                    let prefix = format!("{} v", package);
+                    String::from_utf8_lossy(&o.stdout).lines().find_map(|l| {
+                        l.strip_prefix(&prefix)
+                            .map(|v| v.split([' ', ':']).next().unwrap_or("").to_string())
+                    })
+                })
+        }
+    };
+
+    Ok(InstalledFrom {
+        kind: source.kind.clone(),
+        url: source.url.clone().unwrap_or_default(),
+        package: Some(package.to_string()),
+        version: installed_version,
+        ..Default::default()
+    })
+}
+
+/// Download, verify and extract into `work`. `sha256` is mandatory for remote archives.
+fn fetch_archive(source: &Source, url: &str, work: &Path) -> Result<(PathBuf, InstalledFrom), SourceError> {
+    let expected = source.sha256.as_deref().ok_or(SourceError::MissingField("sha256"))?;
+    let data = download(url)?;
+    let sha256 = verify(&data, Some(expected))?;
+    extract(&data, url, source.strip_components.unwrap_or(0), work)?;
+    let from = InstalledFrom {
+        url: url.to_string(),
+        sha256: Some(sha256),
+        ..Default::default()
+    };
+    Ok((work.to_path_buf(), from))
+}
+
+/// A local directory is used in place; a local file is treated as an archive
+/// (`sha256` is checked when given).
+fn fetch_local(source: &Source, url: &str, work: &Path) -> Result<(PathBuf, InstalledFrom), SourceError> {
+    let path = PathBuf::from(url.strip_prefix("file://").unwrap_or(url));
+    let meta = std::fs::metadata(&path).map_err(|e| SourceError::Local(path.clone(), e))?;
+    let mut from = InstalledFrom {
+        url: url.to_string(),
+        ..Default::default()
+    };
+    if meta.is_dir() {
+        return Ok((path, from));
+    }
+    let data = std::fs::read(&path).map_err(|e| SourceError::Local(path.clone(), e))?;
+    from.sha256 = Some(verify(&data, source.sha256.as_deref())?);
+    extract(&data, url, source.strip_components.unwrap_or(0), work)?;
+    Ok((work.to_path_buf(), from))
+}
+
+fn download(url: &str) -> Result<Vec<u8>, SourceError> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return std::fs::read(path).map_err(|e| SourceError::Local(PathBuf::from(path), e));
+    }
+    let client = reqwest::blocking::Client::builder()
+        .user_agent("dmcp/1.0")
+        .connect_timeout(Duration::from_secs(15))
+        .timeout(Duration::from_secs(300))
+        .build()
+        .map_err(SourceError::HttpClient)?;
+    let fetch_err = |cause| SourceError::Download { url: url.to_string(), cause };
+    let resp = client.get(url).send().map_err(fetch_err)?;
+    if !resp.status().is_success() {
+        return Err(SourceError::Status {
+            url: url.to_string(),
+            status: resp.status().as_u16(),
+        });
+    }
+    Ok(resp.bytes().map_err(fetch_err)?.to_vec())
+}
+
+/// Hex SHA-256 of `data`, checked against `expected` (case-insensitive) if given.
+fn verify(data: &[u8], expected: Option<&str>) -> Result<String, SourceError> {
+    let actual: String = Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect();
+    match expected {
+        Some(e) if !e.trim().eq_ignore_ascii_case(&actual) => Err(SourceError::ChecksumMismatch {
+            expected: e.trim().to_string(),
+            actual,
+        }),
+        _ => Ok(actual),
+    }
+}
+
+enum ArchiveFormat {
+    TarGz,
+    Tar,
+    Zip,
+}
+
+/// Detect the format from the URL suffix, falling back to magic bytes.
+fn archive_format(data: &[u8], url: &str) -> Option<ArchiveFormat> {
+    let name = url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        return Some(ArchiveFormat::TarGz);
+    }
+    if name.ends_with(".tar") {
+        return Some(ArchiveFormat::Tar);
+    }
+    if name.ends_with(".zip") {
+        return Some(ArchiveFormat::Zip);
+    }
+    if data.starts_with(&[0x1f, 0x8b]) {
+        Some(ArchiveFormat::TarGz)
+    } else if data.starts_with(b"PK\x03\x04") {
+        Some(ArchiveFormat::Zip)
+    } else if data.get(257..262) == Some(b"ustar") {
+        Some(ArchiveFormat::Tar)
+    } else {
+        None
+    }
+}
+
+fn extract(data: &[u8], url: &str, strip: usize, dest: &Path) -> Result<(), SourceError> {
+    match archive_format(data, url).ok_or_else(|| SourceError::UnknownFormat(url.to_string()))? {
+        ArchiveFormat::TarGz => extract_tar(flate2::read::GzDecoder::new(data), strip, dest),
+        ArchiveFormat::Tar => extract_tar(data, strip, dest),
+        ArchiveFormat::Zip => extract_zip(data, strip, dest),
+    }
+}
+
+/// Extract files and directories. Links are rejected, since a link followed by an
+/// entry written through it could land outside `dest`; devices and FIFOs are skipped.
+fn extract_tar<R: Read>(reader: R, strip: usize, dest: &Path) -> Result<(), SourceError> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(SourceError::Extract)? {
+        let mut entry = entry.map_err(SourceError::Extract)?;
+        let name = entry.path().map_err(SourceError::Extract)?.into_owned();
+        let kind = entry.header().entry_type();
+        if kind.is_symlink() || kind.is_hard_link() {
+            return Err(SourceError::LinkEntry(name.display().to_string()));
+        }
+        if !kind.is_file() && !kind.is_dir() {
+            continue;
+        }
+        let Some(rel) = strip_path(&name, strip)? else { continue };
+        let target = dest.join(rel);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(SourceError::Extract)?;
+        }
+        entry.unpack(&target).map_err(SourceError::Extract)?;
+    }
+    Ok(())
+}
+
+fn extract_zip(data: &[u8], strip: usize, dest: &Path) -> Result<(), SourceError> {
+    let zip_err = |e: zip::result::ZipError| SourceError::Extract(std::io::Error::other(e));
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(zip_err)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(zip_err)?;
+        let name = file
+            .enclosed_name()
+            .ok_or_else(|| SourceError::UnsafePath(file.name().to_string()))?;
+        let Some(rel) = strip_path(&name, strip)? else { continue };
+        let target = dest.join(rel);
+        if file.is_dir() {
+            std::fs::create_dir_all(&target).map_err(SourceError::Extract)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(SourceError::Extract)?;
+        }
+        let mut out = std::fs::File::create(&target).map_err(SourceError::Extract)?;
+        std::io::copy(&mut file, &mut out).map_err(SourceError::Extract)?;
+        #[cfg(unix)]
+        if let Some(mode) = file.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            // Permission bits only: no setuid, setgid or sticky bits from the archive
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode & 0o777))
+                .map_err(SourceError::Extract)?;
+        }
+    }
+    Ok(())
+}
+
+/// Drop the first `strip` components. `None` for entries that disappear entirely;
+/// absolute paths and `..` are rejected so nothing lands outside the destination.
+fn strip_path(path: &Path, strip: usize) -> Result<Option<PathBuf>, SourceError> {
+    let mut parts = Vec::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(p) => parts.push(p),
+            Component::CurDir => {}
+            _ => return Err(SourceError::UnsafePath(path.display().to_string())),
+        }
+    }
+    if parts.len() <= strip {
+        return Ok(None);
+    }
+    Ok(Some(parts[strip..].iter().collect()))
+}
+
+/// Copy the project root (`path` within `root`, or all of it) into `dest`.
+fn copy_project(root: &Path, path: &str, dest: &Path) -> Result<(), SourceError> {
+    let src = if path.is_empty() { root.to_path_buf() } else { root.join(path) };
+    if !src.is_dir() {
+        return Err(SourceError::PathNotFound(path.to_string()));
+    }
+    copy_dir_all(&src, dest).map_err(SourceError::Io)
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let ty = entry.file_type()?;
+        let dst_path = dst.join(entry.file_name());
+        if ty.is_dir() {
+            copy_dir_all(&entry.path(), &dst_path)?;
+        } else {
+            std::fs::copy(entry.path(), dst_path)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    UnsupportedType(String),
+    MissingField(&'static str),
+    Tool(String, Option<std::io::Error>),
+    CommandNotFound(String, &'static str),
+    CommitNotFound(String),
+    HttpClient(reqwest::Error),
+    Download { url: String, cause: reqwest::Error },
+    Status { url: String, status: u16 },
+    Local(PathBuf, std::io::Error),
+    ChecksumMismatch { expected: String, actual: String },
+    UnknownFormat(String),
+    UnsafePath(String),
+    LinkEntry(String),
+    NotAWheel(String),
+    Extract(std::io::Error),
+    PathNotFound(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::UnsupportedType(t) => write!(f, "Unsupported source type `{}`", t),
+            SourceError::MissingField(field) => write!(f, "Invalid `source`: missing `{}`", field),
+            SourceError::Tool(what, Some(e)) => write!(f, "{} failed: {}", what, e),
+            SourceError::Tool(what, None) => write!(f, "{} failed", what),
+            SourceError::CommandNotFound(name, dir) => write!(f, "Command `{}` not found in {} after install", name, dir),
+            SourceError::CommitNotFound(c) => write!(f, "Commit not found in repository: {}", c),
+            SourceError::HttpClient(e) => write!(f, "HTTP client error: {}", e),
+            SourceError::Download { url, cause } => write!(f, "Failed to download {}: {}", url, cause),
+            SourceError::Status { url, status } => write!(f, "Failed to download {}: HTTP {}", url, status),
+            SourceError::Local(p, e) => write!(f, "Failed to read {}: {}", p.display(), e),
+            SourceError::ChecksumMismatch { expected, actual } => {
+                write!(f, "Checksum mismatch: expected sha256 {}, got {}", expected, actual)
+            }
+            SourceError::UnknownFormat(url) => write!(f, "Unrecognized archive format: {}", url),
+            SourceError::UnsafePath(p) => write!(f, "Archive entry escapes the destination: {}", p),
+            SourceError::LinkEntry(p) => write!(f, "Archive contains a link, which is not supported: {}", p),
+            SourceError::NotAWheel(u) => write!(
+                f,
+                "pypi `url` must be a wheel (.whl), not {}; build steps belong in `setup`",
+                u
+            ),
+            SourceError::Extract(e) => write!(f, "Failed to extract archive: {}", e),
+            SourceError::PathNotFound(p) => write!(f, "Source path not found: {}", p),
+            SourceError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Package tests need the real package manager; skip them where it's missing.
+    fn have(tool: &str) -> bool {
+        let found = Command::new(tool).arg("--version").stdout(Stdio::null()).status().is_ok();
+        if !found {
+            eprintln!("skipping: {} not found", tool);
+        }
+        found
+    }
+
+    fn package_source(kind: &str, package: &str, url: &Path) -> Source {
+        Source {
+            kind: Some(kind.to_string()),
+            package: Some(package.to_string()),
+            url: Some(url.display().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn touch_bin(dir: &Path, bin_dir: &str, name: &str) {
+        std::fs::create_dir_all(dir.join(bin_dir)).unwrap();
+        std::fs::write(dir.join(bin_dir).join(name), "").unwrap();
+    }
+
+    #[test]
+    fn launcher_replaces_wrappers_with_installed_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        touch_bin(dir.path(), "node_modules/.bin", "calc-mcp");
+        touch_bin(dir.path(), "venv/bin", "calc-mcp");
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let npm = Launcher::Npm.resolve(dir.path(), "@org/calc-mcp", "npx", &args(&["-y", "@org/calc-mcp@1.0", "--stdio"]));
+        assert_eq!(npm.unwrap(), ("node_modules/.bin/calc-mcp".to_string(), args(&["--stdio"])));
+
+        let pypi = Launcher::Pypi.resolve(dir.path(), "calc-mcp", "uvx", &args(&["calc-mcp"]));
+        assert_eq!(pypi.unwrap(), ("venv/bin/python".to_string(), args(&["venv/bin/calc-mcp"])));
+
+        let other = Launcher::Npm.resolve(dir.path(), "@org/calc-mcp", "node", &args(&["server.js"]));
+        assert_eq!(other.unwrap(), ("node".to_string(), args(&["server.js"])));
+
+        let missing = Launcher::Cargo.resolve(dir.path(), "calc-mcp", "npx", &args(&["calc-mcp"]));
+        assert!(matches!(missing, Err(SourceError::CommandNotFound(..))));
+    }
+
+    #[test]
+    fn npm_tarball_installs_without_running_scripts() {
+        if !have("npm") {
+            return;
+        }
+        let work = tempfile::tempdir().unwrap();
+        let marker = work.path().join("postinstall-ran");
+        let package_json = serde_json::json!({
+            "name": "calc-mcp",
+            "version": "1.2.3",
+            "bin": {"calc-mcp": "index.js"},
+            "scripts": {"postinstall": format!("touch {}", marker.display())}
+        })
+        .to_string();
+        let index = "#!/usr/bin/env node\n";
+        let tarball = work.path().join("calc-mcp-1.2.3.tgz");
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            std::fs::File::create(&tarball).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for (name, content, mode) in [("package/package.json", package_json.as_str(), 0o644), ("package/index.js", index, 0o755)] {
+            builder.append_data(&mut file_header(content.len(), mode), name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let dest = work.path().join("install");
+        let fetched = fetch_source(&package_source("npm", "calc-mcp", &tarball), &dest).unwrap();
+        assert_eq!(fetched.launcher, Some(Launcher::Npm));
+        assert_eq!(fetched.from.version.as_deref(), Some("1.2.3"));
+        assert!(dest.join("node_modules/.bin/calc-mcp").exists());
+        assert!(!marker.exists(), "postinstall script ran");
+    }
+
+    #[test]
+    fn pypi_wheel_installs_into_venv() {
+        if !have("python3") {
+            return;
+        }
+        let work = tempfile::tempdir().unwrap();
+        let wheel = work.path().join("calc_mcp-0.4.0-py3-none-any.whl");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&wheel).unwrap());
+        let info = "calc_mcp-0.4.0.dist-info";
+        let files = [
+            ("calc_mcp/__init__.py".to_string(), "def main():\n    print('calc')\n"),
+            (format!("{}/METADATA", info), "Metadata-Version: 2.1\nName: calc-mcp\nVersion: 0.4.0\n"),
+            (
+                format!("{}/WHEEL", info),
+                "Wheel-Version: 1.0\nGenerator: dmcp-test\nRoot-Is-Purelib: true\nTag: py3-none-any\n",
+            ),
+            (format!("{}/entry_points.txt", info), "[console_scripts]\ncalc-mcp = calc_mcp:main\n"),
+        ];
+        let mut record = String::new();
+        for (name, content) in &files {
+            zip.start_file(name.as_str(), zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+            record.push_str(&format!("{},,\n", name));
+        }
+        record.push_str(&format!("{}/RECORD,,\n", info));
+        zip.start_file(format!("{}/RECORD", info), zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(record.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        let dest = work.path().join("install");
+        let fetched = fetch_source(&package_source("pypi", "calc-mcp", &wheel), &dest).unwrap();
+        assert_eq!(fetched.from.version.as_deref(), Some("0.4.0"));
+        assert!(dest.join("venv/bin/calc-mcp").exists());
+    }
+
+    #[test]
+    fn pypi_sdist_is_refused_before_running_anything() {
+        let work = tempfile::tempdir().unwrap();
+        let sdist = work.path().join("calc-mcp-0.4.0.tar.gz");
+        std::fs::write(&sdist, "").unwrap();
+        let dest = work.path().join("install");
+        let err = fetch_source(&package_source("pypi", "calc-mcp", &sdist), &dest).unwrap_err();
+        assert!(matches!(err, SourceError::NotAWheel(_)));
+        assert!(!dest.join("venv").exists());
+    }
+
+    fn tar_with(build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        builder.into_inner().unwrap()
+    }
+
+    fn file_header(size: usize, mode: u32) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size as u64);
+        header.set_mode(mode);
+        header.set_entry_type(tar::EntryType::Regular);
+        header
+    }
+
+    fn link_header(kind: tar::EntryType, target: &str) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_entry_type(kind);
+        header.set_link_name(target).unwrap();
+        header
+    }
+
+    #[test]
+    fn tar_extracts_files_with_strip() {
+        let data = tar_with(|b| {
+            b.append_data(&mut file_header(5, 0o755), "pkg-1.0/bin/server", &b"hello"[..]).unwrap();
+        });
+        let dest = tempfile::tempdir().unwrap();
+        extract_tar(data.as_slice(), 1, dest.path()).unwrap();
+        let path = dest.path().join("bin/server");
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+    }
+
+    #[test]
+    fn tar_symlink_cannot_redirect_later_entries() {
+        let outside = tempfile::tempdir().unwrap();
+        let data = tar_with(|b| {
+            let target = outside.path().to_str().unwrap();
+            b.append_data(&mut link_header(tar::EntryType::Symlink, target), "escape", std::io::empty())
+                .unwrap();
+            b.append_data(&mut file_header(4, 0o644), "escape/owned", &b"evil"[..]).unwrap();
+        });
+        let dest = tempfile::tempdir().unwrap();
+        let err = extract_tar(data.as_slice(), 0, dest.path()).unwrap_err();
+        assert!(matches!(err, SourceError::LinkEntry(ref p) if p == "escape"));
+        assert!(!outside.path().join("owned").exists());
+        assert!(std::fs::symlink_metadata(dest.path().join("escape")).is_err());
+    }
+
+    #[test]
+    fn tar_hard_link_is_rejected() {
+        let data = tar_with(|b| {
+            b.append_data(&mut link_header(tar::EntryType::Link, "/etc/passwd"), "passwd", std::io::empty())
+                .unwrap();
+        });
+        let dest = tempfile::tempdir().unwrap();
+        let err = extract_tar(data.as_slice(), 0, dest.path()).unwrap_err();
+        assert!(matches!(err, SourceError::LinkEntry(_)));
+        assert!(!dest.path().join("passwd").exists());
+    }
+
+    #[test]
+    fn tar_parent_dir_entry_is_rejected() {
+        let mut data = tar_with(|b| {
+            b.append_data(&mut file_header(4, 0o644), "placeholder", &b"evil"[..]).unwrap();
+        });
+        // Builder refuses `..`, so patch the name in the header (and its checksum)
+        data[..11].copy_from_slice(b"../escaped\0");
+        let mut header = tar::Header::new_old();
+        header.as_mut_bytes().copy_from_slice(&data[..512]);
+        header.set_cksum();
+        data[..512].copy_from_slice(header.as_bytes());
+
+        let dest = tempfile::tempdir().unwrap();
+        let err = extract_tar(data.as_slice(), 0, dest.path()).unwrap_err();
+        assert!(matches!(err, SourceError::UnsafePath(_)));
+        assert!(!dest.path().parent().unwrap().join("escaped").exists());
+    }
+
+    #[test]
+    fn zip_drops_setuid_bits() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default().unix_permissions(0o755);
+        zip.start_file("server", options).unwrap();
+        zip.write_all(b"#!/bin/sh\n").unwrap();
+        let mut data = zip.finish().unwrap().into_inner();
+        // The writer masks modes to 0o777; set setuid in the central directory by hand
+        let central = data.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        let attrs = &mut data[central + 38..central + 42];
+        attrs.copy_from_slice(&((0o104755u32) << 16).to_le_bytes());
+
+        let dest = tempfile::tempdir().unwrap();
+        extract_zip(&data, 0, dest.path()).unwrap();
+        let mode = std::fs::metadata(dest.path().join("server")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+}
@@ -0,0 +1,280 @@
+//! HTTP+SSE transport (MCP 2024-11-05).
+//!
+//! The client opens a GET event stream, waits for the `endpoint` event naming
+//! the POST URL, then posts JSON-RPC messages there. Responses arrive as
+//! `message` events on the stream.
+
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use reqwest::blocking::Client as HttpClient;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT};
+use reqwest::Url;
+use serde_json::Value;
+
+use crate::protocol::{Connection, ProtocolError};
+
+const ENDPOINT_TIMEOUT: Duration = Duration::from_secs(30);
+const POST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One dispatched server-sent event.
+#[derive(Debug, Clone, Default)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+    pub id: Option<String>,
+}
+
+/// Incremental `text/event-stream` parser.
+pub struct EventReader<R> {
+    reader: R,
+}
+
+impl<R: BufRead> EventReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Next complete event, or `None` at end of stream.
+    pub fn next_event(&mut self) -> std::io::Result<Option<SseEvent>> {
+        let mut event = SseEvent::default();
+        let mut data: Vec<String> = Vec::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let l = line.trim_end_matches(['\r', '\n']);
+            if l.is_empty() {
+                if data.is_empty() {
+                    event = SseEvent::default();
+                    continue;
+                }
+                event.data = data.join("\n");
+                if event.event.is_empty() {
+                    event.event = "message".to_string();
+                }
+                return Ok(Some(event));
+            }
+            if l.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match l.find(':') {
+                Some(i) => (&l[..i], l[i + 1..].strip_prefix(' ').unwrap_or(&l[i + 1..])),
+                None => (l, ""),
+            };
+            match field {
+                "event" => event.event = value.to_string(),
+                "data" => data.push(value.to_string()),
+                "id" => event.id = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Build a header map from `(name, value)` pairs.
+pub fn header_map(headers: &[(String, String)]) -> Result<HeaderMap, ProtocolError> {
+    let mut map = HeaderMap::new();
+    for (k, v) in headers {
+        let name = HeaderName::from_bytes(k.as_bytes()).map_err(|_| ProtocolError::InvalidHeader(k.clone()))?;
+        let value = HeaderValue::from_str(v).map_err(|_| ProtocolError::InvalidHeader(k.clone()))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+enum StreamItem {
+    Endpoint(String),
+    Message(Value),
+}
+
+/// MCP connection over HTTP+SSE.
+pub struct SseConnection {
+    http: HttpClient,
+    endpoint: Url,
+    headers: HeaderMap,
+    rx: Receiver<StreamItem>,
+}
+
+impl SseConnection {
+    /// Open the event stream at `url` and wait for the `endpoint` event.
+    pub fn open(url: &str, headers: &[(String, String)]) -> Result<Self, ProtocolError> {
+        let base = Url::parse(url).map_err(|_| ProtocolError::InvalidUrl(url.to_string()))?;
+        let headers = header_map(headers)?;
+        // No overall timeout: the event stream stays open for the whole session
+        let http = HttpClient::builder()
+            .user_agent("dmcp/1.0")
+            .connect_timeout(Duration::from_secs(15))
+            .timeout(None)
+            .build()
+            .map_err(ProtocolError::Http)?;
+
+        let resp = http
+            .get(base.clone())
+            .headers(headers.clone())
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .map_err(ProtocolError::Http)?;
+        if !resp.status().is_success() {
+            return Err(ProtocolError::HttpStatus(resp.status().as_u16()));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut events = EventReader::new(BufReader::new(resp));
+            while let Ok(Some(ev)) = events.next_event() {
+                let item = match ev.event.as_str() {
+                    "endpoint" => StreamItem::Endpoint(ev.data.trim().to_string()),
+                    "message" => match serde_json::from_str(&ev.data) {
+                        Ok(v) => StreamItem::Message(v),
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+                if tx.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let endpoint = match rx.recv_timeout(ENDPOINT_TIMEOUT) {
+            Ok(StreamItem::Endpoint(e)) => {
+                let endpoint = base.join(&e).map_err(|_| ProtocolError::InvalidUrl(e.clone()))?;
+                // POSTs carry our headers (often credentials): never follow the server elsewhere
+                if endpoint.origin() != base.origin() {
+                    return Err(ProtocolError::InvalidResponse(format!(
+                        "endpoint {} is not on the same origin as {}",
+                        endpoint, base
+                    )));
+                }
+                endpoint
+            }
+            Ok(StreamItem::Message(_)) => {
+                return Err(ProtocolError::InvalidResponse("message before endpoint event".to_string()))
+            }
+            Err(RecvTimeoutError::Timeout) => return Err(ProtocolError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => return Err(ProtocolError::Closed),
+        };
+
+        Ok(Self { http, endpoint, headers, rx })
+    }
+}
+
+impl Connection for SseConnection {
+    fn send(&mut self, message: &Value) -> Result<(), ProtocolError> {
+        let resp = self
+            .http
+            .post(self.endpoint.clone())
+            .headers(self.headers.clone())
+            .timeout(POST_TIMEOUT)
+            .json(message)
+            .send()
+            .map_err(ProtocolError::Http)?;
+        if !resp.status().is_success() {
+            return Err(ProtocolError::HttpStatus(resp.status().as_u16()));
+        }
+        Ok(())
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Value>, ProtocolError> {
+        loop {
+            match self.rx.recv_timeout(timeout) {
+                Ok(StreamItem::Message(v)) => return Ok(Some(v)),
+                // A repeated endpoint event carries nothing new for us
+                Ok(StreamItem::Endpoint(_)) => continue,
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => return Err(ProtocolError::Closed),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{read_request, Request};
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// SSE stand-in: announces `endpoint`, then answers every POST with a
+    /// `message` event echoing the request's method. Every request it receives
+    /// (the GET, then each POST) is reported on the returned channel.
+    fn serve(endpoint: &'static str) -> (String, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sse", listener.local_addr().unwrap());
+        let (seen, seen_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = seen.send(read_request(&mut stream).unwrap());
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n: hello\n\nevent: endpoint\ndata: {}\n\n",
+                endpoint
+            )
+            .unwrap();
+            for post in listener.incoming() {
+                let mut post = post.unwrap();
+                let raw = read_request(&mut post).unwrap();
+                let request: Value = serde_json::from_str(&raw.body).unwrap();
+                let _ = seen.send(raw);
+                post.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n").unwrap();
+                let reply = serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "result": {"echo": request["method"]}});
+                if write!(stream, "event: message\ndata: {}\n\n", reply).is_err() {
+                    break;
+                }
+            }
+        });
+        (url, seen_rx)
+    }
+
+    #[test]
+    fn parses_multiline_data_and_skips_comments() {
+        let input = ": comment\nevent: endpoint\ndata: /a\n\ndata: {\"x\":\ndata: 1}\nid: 7\n\n";
+        let mut events = EventReader::new(input.as_bytes());
+        let first = events.next_event().unwrap().unwrap();
+        assert_eq!((first.event.as_str(), first.data.as_str()), ("endpoint", "/a"));
+        let second = events.next_event().unwrap().unwrap();
+        assert_eq!(second.event, "message");
+        assert_eq!(second.data, "{\"x\":\n1}");
+        assert_eq!(second.id.as_deref(), Some("7"));
+        assert!(events.next_event().unwrap().is_none());
+    }
+
+    #[test]
+    fn posts_to_endpoint_and_reads_replies_from_stream() {
+        let (url, seen) = serve("/messages?session=1");
+        let headers = [
+            ("Authorization".to_string(), "Bearer t".to_string()),
+            ("X-Workspace".to_string(), "notes".to_string()),
+        ];
+        let mut conn = SseConnection::open(&url, &headers).unwrap();
+        assert_eq!(conn.endpoint.path(), "/messages");
+        conn.send(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"})).unwrap();
+        let reply = conn.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["result"]["echo"], "tools/list");
+
+        // The configured headers go on the event stream and on every POST
+        let requests: Vec<Request> = seen.try_iter().collect();
+        let lines: Vec<String> = requests.iter().map(|r| format!("{} {}", r.method, r.path)).collect();
+        assert_eq!(lines, ["GET /sse", "POST /messages?session=1"]);
+        for request in &requests {
+            assert_eq!(request.header("authorization"), Some("Bearer t"));
+            assert_eq!(request.header("x-workspace"), Some("notes"));
+        }
+    }
+
+    #[test]
+    fn rejects_endpoint_on_another_origin() {
+        let (url, _) = serve("http://attacker.invalid/steal");
+        match SseConnection::open(&url, &[]) {
+            Err(ProtocolError::InvalidResponse(msg)) => assert!(msg.contains("attacker.invalid")),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("cross-origin endpoint was accepted"),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Streamable HTTP transport (MCP 2025-03-26).
+//!
+//! Every message is POSTed to a single endpoint. The server answers with plain
+//! JSON, an SSE stream of messages, or 202 for notifications and responses.
+//! The session is tracked through the `Mcp-Session-Id` header and closed with
+//! DELETE when the connection is dropped. If the server answers 404 because the
+//! session expired, the `initialize` handshake is replayed once for a new session.
+
+use std::io::BufReader;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use reqwest::blocking::{Client as HttpClient, Response};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+use reqwest::{StatusCode, Url};
+use serde_json::Value;
+
+use crate::protocol::{Connection, ProtocolError};
+use crate::sse::{header_map, EventReader};
+
+/// Header carrying the session id assigned by the server.
+pub const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+/// MCP connection over Streamable HTTP.
+pub struct StreamableHttpConnection {
+    http: HttpClient,
+    url: Url,
+    headers: HeaderMap,
+    session: Option<HeaderValue>,
+    /// `initialize` request and `notifications/initialized`, replayed for a new session.
+    handshake: Vec<Value>,
+    listening: bool,
+    tx: Sender<Value>,
+    rx: Receiver<Value>,
+}
+
+impl StreamableHttpConnection {
+    /// Prepare a connection to `url`. Nothing is sent until the first message.
+    pub fn open(url: &str, headers: &[(String, String)]) -> Result<Self, ProtocolError> {
+        let url = Url::parse(url).map_err(|_| ProtocolError::InvalidUrl(url.to_string()))?;
+        let headers = header_map(headers)?;
+        // No overall timeout: SSE responses stay open as long as the request runs
+        let http = HttpClient::builder()
+            .user_agent("dmcp/1.0")
+            .connect_timeout(Duration::from_secs(15))
+            .timeout(None)
+            .build()
+            .map_err(ProtocolError::Http)?;
+        let (tx, rx) = mpsc::channel();
+        Ok(Self {
+            http,
+            url,
+            headers,
+            session: None,
+            handshake: Vec::new(),
+            listening: false,
+            tx,
+            rx,
+        })
+    }
+
+    fn request_headers(&self) -> HeaderMap {
+        let mut h = self.headers.clone();
+        if let Some(s) = &self.session {
+            h.insert(SESSION_HEADER, s.clone());
+        }
+        h
+    }
+
+    /// Open the optional GET stream for server-initiated messages. Servers
+    /// that don't offer one answer 405, which is fine.
+    fn listen(&mut self) {
+        self.listening = true;
+        let req = self
+            .http
+            .get(self.url.clone())
+            .headers(self.request_headers())
+            .header(ACCEPT, "text/event-stream");
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            if let Ok(resp) = req.send() {
+                if resp.status().is_success() {
+                    forward_events(resp, &tx);
+                }
+            }
+        });
+    }
+
+    /// POST `message`, failing on any status but success. Records the session id.
+    fn post(&mut self, message: &Value) -> Result<Response, ProtocolError> {
+        let resp = self
+            .http
+            .post(self.url.clone())
+            .headers(self.request_headers())
+            .header(ACCEPT, "application/json, text/event-stream")
+            .json(message)
+            .send()
+            .map_err(ProtocolError::Http)?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(ProtocolError::HttpStatus(status.as_u16()));
+        }
+        if let Some(s) = resp.headers().get(SESSION_HEADER) {
+            self.session = Some(s.clone());
+        }
+        Ok(resp)
+    }
+
+    /// Start a new session by replaying the handshake. The `initialize` reply
+    /// is read and dropped; the client already has one.
+    fn reinitialize(&mut self) -> Result<(), ProtocolError> {
+        self.session = None;
+        let handshake = self.handshake.clone();
+        let Some((init, rest)) = handshake.split_first() else {
+            return Err(ProtocolError::HttpStatus(StatusCode::NOT_FOUND.as_u16()));
+        };
+        let resp = self.post(init)?;
+        if is_event_stream(&resp) {
+            let id = init.get("id");
+            let mut events = EventReader::new(BufReader::new(resp));
+            while let Some(ev) = events.next_event().map_err(ProtocolError::Io)? {
+                let reply: Option<Value> = serde_json::from_str(&ev.data).ok();
+                if reply.as_ref().and_then(|r| r.get("id")) == id {
+                    break;
+                }
+            }
+        }
+        for msg in rest {
+            self.post(msg)?;
+        }
+        // The old GET stream belonged to the expired session
+        if self.listening {
+            self.listen();
+        }
+        Ok(())
+    }
+}
+
+impl Connection for StreamableHttpConnection {
+    fn send(&mut self, message: &Value) -> Result<(), ProtocolError> {
+        match message.get("method").and_then(|m| m.as_str()) {
+            Some("initialize") => self.handshake = vec![message.clone()],
+            Some("notifications/initialized") if self.handshake.len() == 1 => self.handshake.push(message.clone()),
+            _ => {}
+        }
+        let resp = match self.post(message) {
+            // Expired session: the spec says to start over with a fresh initialize
+            Err(ProtocolError::HttpStatus(404)) if self.session.is_some() && self.handshake.first() != Some(message) => {
+                self.reinitialize()?;
+                self.post(message)?
+            }
+            other => other?,
+        };
+        let status = resp.status();
+        if message.get("method").and_then(|m| m.as_str()) == Some("notifications/initialized") && !self.listening {
+            self.listen();
+        }
+        if status == StatusCode::ACCEPTED {
+            return Ok(());
+        }
+
+        if is_event_stream(&resp) {
+            // Read the stream in the background so long-running calls don't block sending
+            let tx = self.tx.clone();
+            std::thread::spawn(move || forward_events(resp, &tx));
+        } else if content_type(&resp).starts_with("application/json") {
+            let body: Value = resp.json().map_err(ProtocolError::Http)?;
+            match body {
+                Value::Array(batch) => {
+                    for msg in batch {
+                        let _ = self.tx.send(msg);
+                    }
+                }
+                msg => {
+                    let _ = self.tx.send(msg);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Value>, ProtocolError> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(msg) => Ok(Some(msg)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            // Unreachable while we hold `tx`, kept for completeness
+            Err(RecvTimeoutError::Disconnected) => Err(ProtocolError::Closed),
+        }
+    }
+}
+
+impl Drop for StreamableHttpConnection {
+    fn drop(&mut self) {
+        if self.session.is_some() {
+            let _ = self
+                .http
+                .delete(self.url.clone())
+                .headers(self.request_headers())
+                .timeout(Duration::from_secs(5))
+                .send();
+        }
+    }
+}
+
+fn content_type(resp: &Response) -> String {
+    resp.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+fn is_event_stream(resp: &Response) -> bool {
+    content_type(resp).starts_with("text/event-stream")
+}
+
+fn forward_events(resp: Response, tx: &Sender<Value>) {
+    let mut events = EventReader::new(BufReader::new(resp));
+    while let Ok(Some(ev)) = events.next_event() {
+        if ev.event != "message" {
+            continue;
+        }
+        if let Ok(msg) = serde_json::from_str::<Value>(&ev.data) {
+            if tx.send(msg).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{respond, serve};
+    use std::sync::{Arc, Mutex};
+
+    /// Stand-in whose first session expires: requests on `s1` other than the
+    /// handshake get 404, and the next `initialize` opens `s2`. Records
+    /// `(method, session)` for each POST.
+    type PostLog = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+    fn expiring_server() -> (String, PostLog) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let seen = log.clone();
+        let url = serve(move |req, stream| {
+            assert_eq!(req.path, "/mcp");
+            if req.method != "POST" {
+                return respond(stream, "405 Method Not Allowed", &[], "");
+            }
+            let msg: Value = serde_json::from_str(&req.body).unwrap();
+            let method = msg["method"].as_str().unwrap_or_default().to_string();
+            let session = req.header(SESSION_HEADER).map(String::from);
+            let inits = {
+                let mut log = seen.lock().unwrap();
+                log.push((method.clone(), session.clone()));
+                log.iter().filter(|(m, _)| m == "initialize").count()
+            };
+            let new_session = format!("s{}", inits);
+            match (method.as_str(), session.as_deref()) {
+                ("initialize", _) => {
+                    let reply = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}});
+                    let headers = [(SESSION_HEADER, new_session.as_str()), ("Content-Type", "application/json")];
+                    respond(stream, "200 OK", &headers, &reply.to_string())
+                }
+                ("notifications/initialized", _) => respond(stream, "202 Accepted", &[], ""),
+                (_, Some("s1")) => respond(stream, "404 Not Found", &[], ""),
+                _ => {
+                    let reply = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {"method": method}});
+                    respond(stream, "200 OK", &[("Content-Type", "application/json")], &reply.to_string())
+                }
+            }
+        });
+        (format!("{}/mcp", url), log)
+    }
+
+    #[test]
+    fn reinitializes_once_when_session_expires() {
+        let (url, log) = expiring_server();
+        let mut conn = StreamableHttpConnection::open(&url, &[]).unwrap();
+        conn.send(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"})).unwrap();
+        assert_eq!(conn.recv_timeout(Duration::from_secs(5)).unwrap().unwrap()["id"], 1);
+        conn.send(&serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"})).unwrap();
+
+        conn.send(&serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"})).unwrap();
+        let reply = conn.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(reply["id"], 2);
+        assert_eq!(reply["result"]["method"], "tools/list");
+        // The replayed initialize reply is not delivered to the caller
+        assert!(conn.recv_timeout(Duration::from_millis(100)).unwrap().is_none());
+
+        let log = log.lock().unwrap().clone();
+        let expected: Vec<(String, Option<String>)> = [
+            ("initialize", None),
+            ("notifications/initialized", Some("s1")),
+            ("tools/list", Some("s1")),
+            ("initialize", None),
+            ("notifications/initialized", Some("s2")),
+            ("tools/list", Some("s2")),
+        ]
+        .iter()
+        .map(|(m, s)| (m.to_string(), s.map(String::from)))
+        .collect();
+        assert_eq!(log, expected);
+    }
+
+    #[test]
+    fn not_found_without_session_is_an_error() {
+        let url = serve(|_, stream| respond(stream, "404 Not Found", &[], ""));
+        let mut conn = StreamableHttpConnection::open(&url, &[]).unwrap();
+        let err = conn.send(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"})).unwrap_err();
+        assert!(matches!(err, ProtocolError::HttpStatus(404)));
+    }
+}
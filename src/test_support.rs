@@ -0,0 +1,151 @@
+//! Local HTTP and MCP stand-ins and scratch paths for tests.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::paths::Paths;
+use crate::protocol::{Connection, ProtocolError};
+
+type Handler = Box<dyn FnMut(&str, &Value) -> Result<Value, (i64, String)> + Send>;
+
+/// In-memory MCP server. `handler` answers each request from its method and params;
+/// every message the client sends is recorded in `sent`, and anything pushed to
+/// `inbox` is delivered to the client as-is.
+pub struct StandIn {
+    handler: Handler,
+    pub sent: Arc<Mutex<Vec<Value>>>,
+    pub inbox: Arc<Mutex<VecDeque<Value>>>,
+}
+
+impl StandIn {
+    pub fn new(handler: impl FnMut(&str, &Value) -> Result<Value, (i64, String)> + Send + 'static) -> Self {
+        Self {
+            handler: Box::new(handler),
+            sent: Arc::new(Mutex::new(Vec::new())),
+            inbox: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+}
+
+impl Connection for StandIn {
+    fn send(&mut self, message: &Value) -> Result<(), ProtocolError> {
+        self.sent.lock().unwrap().push(message.clone());
+        let (Some(id), Some(method)) = (message.get("id"), message.get("method").and_then(|m| m.as_str())) else {
+            return Ok(());
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let reply = match (self.handler)(method, &params) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}}),
+        };
+        self.inbox.lock().unwrap().push_back(reply);
+        Ok(())
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Value>, ProtocolError> {
+        if let Some(msg) = self.inbox.lock().unwrap().pop_front() {
+            return Ok(Some(msg));
+        }
+        std::thread::sleep(timeout.min(Duration::from_millis(5)));
+        Ok(None)
+    }
+}
+
+/// One parsed HTTP request.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    /// Value of header `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Read one request from `stream`; `None` if the client closed it.
+pub fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let l = line.trim_end();
+        if l.is_empty() {
+            break;
+        }
+        if let Some((k, v)) = l.split_once(':') {
+            headers.push((k.trim().to_string(), v.trim().to_string()));
+        }
+    }
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: String::new(),
+    };
+    let length: usize = request.header("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    request.body = String::from_utf8_lossy(&body).into_owned();
+    Some(request)
+}
+
+/// Write a complete response and close the connection.
+pub fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, &str)], body: &str) {
+    let mut out = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+    for (k, v) in headers {
+        out.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    out.push_str("\r\n");
+    out.push_str(body);
+    let _ = stream.write_all(out.as_bytes());
+}
+
+/// Serve every connection on a background thread with `handler`, one request
+/// per connection. Returns the base URL (`http://127.0.0.1:<port>`).
+pub fn serve(handler: impl Fn(Request, &mut TcpStream) + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            if let Some(request) = read_request(&mut stream) {
+                handler(request, &mut stream);
+            }
+        }
+    });
+    url
+}
+
+/// Paths with every user and system location under `root`.
+pub fn paths(root: &Path) -> Paths {
+    Paths {
+        user_sources: root.join("user/sources.list"),
+        user_install_dir: root.join("user/servers"),
+        system_sources: root.join("system/sources.list"),
+        system_install_dir: root.join("system/servers"),
+        user_policy: root.join("user/policy.json"),
+        system_policy: root.join("system/policy.json"),
+        registry_cache: root.join("cache"),
+        secrets: root.join("user/secrets"),
+        user_config: root.join("user/config"),
+    }
+}
@@ -0,0 +1,187 @@
+//! Detect and apply server updates from registry sources.
+//!
+//! Installed manifest versions are compared with registry entries as semver.
+//! Updating reinstalls the server from its registry entry and carries the
+//! user's `config` over to the new manifest.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+use semver::Version;
+use serde_json::Value;
+
+use crate::browse::{find_registry_server, list_registry_servers, BrowseError};
+use crate::discovery::{get_manifest_path, get_server, list_servers, Scope};
+use crate::install::{install, InstallError};
+use crate::models::RegistryEntry;
+use crate::paths::Paths;
+use crate::secrets;
+
+/// An installed server with a newer version in a registry.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Outdated {
+    pub id: String,
+    pub installed: String,
+    pub available: String,
+    pub scope: Scope,
+    /// Registry URL listing the newer version.
+    pub source: String,
+}
+
+/// Result of updating one server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    /// `skipped_secrets` are sensitive keys left out of a system-wide install.
+    Updated {
+        from: String,
+        to: String,
+        skipped_secrets: Vec<String>,
+    },
+    UpToDate(String),
+}
+
+/// Compare two version strings as semver. A leading `v` and missing minor or
+/// patch components are accepted (`v1.2` is `1.2.0`). `None` if either doesn't parse.
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    Some(parse_version(a)?.cmp(&parse_version(b)?))
+}
+
+fn parse_version(s: &str) -> Option<Version> {
+    let s = s.trim();
+    let s = s.strip_prefix('v').unwrap_or(s);
+    if let Ok(v) = Version::parse(s) {
+        return Some(v);
+    }
+    // Pad `1` and `1.2` to three components, keeping any pre-release/build suffix
+    let split = s.find(['-', '+']).unwrap_or(s.len());
+    let (core, rest) = s.split_at(split);
+    let padded = match core.split('.').count() {
+        1 => format!("{}.0.0{}", core, rest),
+        2 => format!("{}.0{}", core, rest),
+        _ => return None,
+    };
+    Version::parse(&padded).ok()
+}
+
+/// Whether `available` should replace `installed`. Versions that don't parse
+/// can't be ordered, so they never count as newer (`dmcp install` still replaces them).
+pub fn is_newer(available: &str, installed: &str) -> bool {
+    compare_versions(available, installed) == Some(Ordering::Greater)
+}
+
+/// List installed servers that have a newer version in a registry source.
+/// Registry errors are returned next to the result.
+pub fn list_outdated(paths: &Paths, offline: bool) -> (Vec<Outdated>, Vec<BrowseError>) {
+    let installed = list_servers(paths, true, true, false);
+    if installed.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let (available, errors) = list_registry_servers(paths, true, true, offline);
+    let outdated = installed
+        .into_iter()
+        .filter_map(|s| {
+            let r = available.iter().find(|r| r.entry.id == s.id)?;
+            is_newer(&r.entry.version, &s.version).then(|| Outdated {
+                id: s.id,
+                installed: s.version,
+                available: r.entry.version.clone(),
+                scope: s.scope,
+                source: r.source.clone(),
+            })
+        })
+        .collect();
+    (outdated, errors)
+}
+
+/// Update an installed server to the version in its registry, in the scope it
+/// is installed in. Install is transactional, so a failure keeps the previous version.
+/// `collect_config` completes the entry's `config` the way `dmcp install` does (new
+/// required properties, defaults); `run_setup` is asked whether the new entry's
+/// `setup` commands may run (see [`install`]).
+pub fn update(
+    paths: &Paths,
+    id: &str,
+    offline: bool,
+    collect_config: impl FnOnce(&mut RegistryEntry),
+    run_setup: impl FnOnce(&RegistryEntry) -> bool,
+) -> Result<UpdateOutcome, UpdateError> {
+    let (manifest, scope) = get_server(paths, id).ok_or_else(|| UpdateError::NotInstalled(id.to_string()))?;
+    let installed = manifest.version.unwrap_or_default();
+    let mut server = find_registry_server(paths, id, offline).map_err(UpdateError::Registry)?;
+    let available = server.entry.version.clone();
+    if !is_newer(&available, &installed) {
+        return Ok(UpdateOutcome::UpToDate(installed));
+    }
+
+    // Overlay the previous config on the registry's; the user's values win
+    let manifest_path = get_manifest_path(paths, id).ok_or_else(|| UpdateError::NotInstalled(id.to_string()))?;
+    let mut config = match server.entry.extra.remove("config") {
+        Some(Value::Object(c)) => c,
+        _ => serde_json::Map::new(),
+    };
+    config.extend(read_config(&manifest_path));
+    // Keep stored secrets rather than replacing them with registry placeholders
+    for key in secrets::keys(paths, id).unwrap_or_default() {
+        config.remove(&key);
+    }
+    server.entry.extra.insert("config".to_string(), Value::Object(config));
+    collect_config(&mut server.entry);
+
+    let run_setup = run_setup(&server.entry);
+    let skipped_secrets = install(paths, id, scope, Some(server.entry), run_setup).map_err(UpdateError::Install)?;
+    Ok(UpdateOutcome::Updated {
+        from: installed,
+        to: available,
+        skipped_secrets,
+    })
+}
+
+fn read_config(manifest_path: &Path) -> serde_json::Map<String, Value> {
+    std::fs::read_to_string(manifest_path)
+        .ok()
+        .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+        .and_then(|m| m.get("config").and_then(|c| c.as_object()).cloned())
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub enum UpdateError {
+    NotInstalled(String),
+    Registry(BrowseError),
+    Install(InstallError),
+}
+
+impl std::fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateError::NotInstalled(id) => write!(f, "Server not installed: {}", id),
+            UpdateError::Registry(e) => write!(f, "{}", e),
+            UpdateError::Install(e) => write!(f, "{} (previous version kept)", e),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_as_semver() {
+        assert!(is_newer("1.10.0", "1.9.0"));
+        assert!(is_newer("v2", "1.9.9"));
+        assert!(is_newer("1.2.0", "1.2.0-beta.1"));
+        assert!(!is_newer("1.2", "1.2.0"));
+        assert!(!is_newer("1.0.0", "1.0.1"));
+    }
+
+    #[test]
+    fn unparseable_versions_are_not_newer() {
+        assert_eq!(compare_versions("latest", "1.0.0"), None);
+        assert!(!is_newer("latest", "1.0.0"));
+        assert!(!is_newer("1.0.0", "2024-01-05"));
+        assert!(!is_newer("", "1.0.0"));
+        assert!(!is_newer("nightly", "stable"));
+    }
+}
@@ -0,0 +1,427 @@
+//! WebSocket transport.
+//!
+//! One JSON-RPC message per text frame, negotiated with the `mcp` subprotocol.
+//! The connection pings the server when idle and reconnects on close, replaying
+//! the `initialize` handshake so the session can continue. Unanswered read-only
+//! requests (listings, reads) are resent; anything else, notably `tools/call`,
+//! fails with a connection error instead, since the server may already have run it.
+
+use std::collections::VecDeque;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::http::{HeaderName, HeaderValue};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::protocol::{Connection, ProtocolError};
+
+/// Subprotocol requested in `Sec-WebSocket-Protocol`.
+pub const SUBPROTOCOL: &str = "mcp";
+
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(20);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const RECONNECT_ATTEMPTS: u32 = 3;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Requests without side effects, safe to send again after a reconnect.
+const REPLAY_SAFE: &[&str] = &[
+    "ping",
+    "tools/list",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "prompts/list",
+    "prompts/get",
+    "completion/complete",
+];
+
+/// JSON-RPC error code for requests lost to a dropped connection.
+const CONNECTION_LOST: i64 = -32000;
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// MCP connection over a WebSocket.
+pub struct WebSocketConnection {
+    url: String,
+    headers: Vec<(String, String)>,
+    socket: Socket,
+    last_activity: Instant,
+    /// `initialize` request and `notifications/initialized`, replayed after reconnect.
+    handshake: Vec<Value>,
+    /// Requests sent but not yet answered: resent or failed after reconnect.
+    in_flight: Vec<Value>,
+    pending: VecDeque<Value>,
+    /// How long a reconnect waits for the replayed `initialize` to be answered.
+    handshake_timeout: Duration,
+}
+
+impl WebSocketConnection {
+    /// Connect to `url` with optional extra HTTP headers on the upgrade request.
+    pub fn open(url: &str, headers: &[(String, String)]) -> Result<Self, ProtocolError> {
+        let socket = open_socket(url, headers)?;
+        Ok(Self {
+            url: url.to_string(),
+            headers: headers.to_vec(),
+            socket,
+            last_activity: Instant::now(),
+            handshake: Vec::new(),
+            in_flight: Vec::new(),
+            pending: VecDeque::new(),
+            handshake_timeout: HANDSHAKE_TIMEOUT,
+        })
+    }
+
+    fn write(&mut self, message: &Value) -> Result<(), ProtocolError> {
+        let text = serde_json::to_string(message).map_err(ProtocolError::Serialize)?;
+        self.socket.send(Message::text(text)).map_err(ws_error)?;
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Reopen the socket, backing off between attempts, and replay the handshake.
+    fn reconnect(&mut self) -> Result<(), ProtocolError> {
+        let mut last_err = ProtocolError::Closed;
+        for attempt in 0..RECONNECT_ATTEMPTS {
+            if attempt > 0 {
+                std::thread::sleep(Duration::from_secs(1 << attempt));
+            }
+            match open_socket(&self.url, &self.headers) {
+                Ok(socket) => {
+                    self.socket = socket;
+                    self.last_activity = Instant::now();
+                    self.replay_handshake()?;
+                    self.replay_in_flight()?;
+                    return Ok(());
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Resend unanswered read-only requests; answer the rest with an error reply.
+    fn replay_in_flight(&mut self) -> Result<(), ProtocolError> {
+        let (safe, lost): (Vec<Value>, Vec<Value>) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|m| m.get("method").and_then(|m| m.as_str()).is_some_and(|m| REPLAY_SAFE.contains(&m)));
+        for msg in lost {
+            let method = msg.get("method").and_then(|m| m.as_str()).unwrap_or_default();
+            self.pending.push_back(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": msg.get("id").cloned().unwrap_or(Value::Null),
+                "error": {
+                    "code": CONNECTION_LOST,
+                    "message": format!("Connection lost before the server answered {}; not retried", method),
+                },
+            }));
+        }
+        self.in_flight = safe;
+        for msg in self.in_flight.clone() {
+            self.write(&msg)?;
+        }
+        Ok(())
+    }
+
+    fn replay_handshake(&mut self) -> Result<(), ProtocolError> {
+        let handshake = self.handshake.clone();
+        for msg in &handshake {
+            self.write(msg)?;
+            let Some(id) = msg.get("id").cloned() else {
+                continue;
+            };
+            // Swallow the initialize response; the client already has one
+            let deadline = Instant::now() + self.handshake_timeout;
+            loop {
+                if Instant::now() >= deadline {
+                    return Err(ProtocolError::Timeout);
+                }
+                match self.read_once()? {
+                    Some(reply) if reply.get("id") == Some(&id) && reply.get("method").is_none() => break,
+                    Some(other) => self.pending.push_back(other),
+                    None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// One read attempt bounded by the socket read timeout. `None` on timeout or control frames.
+    fn read_once(&mut self) -> Result<Option<Value>, ProtocolError> {
+        let msg: Option<Value> = match self.socket.read() {
+            Ok(Message::Text(text)) => serde_json::from_str(text.as_str()).ok(),
+            Ok(Message::Binary(data)) => serde_json::from_slice(&data).ok(),
+            Ok(Message::Close(_)) => return Err(ProtocolError::Closed),
+            Ok(_) => {
+                // Ping/Pong: tungstenite queues the pong reply itself
+                self.last_activity = Instant::now();
+                return Ok(None);
+            }
+            Err(e) => return self.read_error(e),
+        };
+        self.last_activity = Instant::now();
+        if let Some(m) = &msg {
+            if m.get("method").is_none() {
+                if let Some(id) = m.get("id") {
+                    self.in_flight.retain(|r| r.get("id") != Some(id));
+                }
+            }
+        }
+        Ok(msg)
+    }
+
+    fn read_error(&self, e: tungstenite::Error) -> Result<Option<Value>, ProtocolError> {
+        match e {
+            tungstenite::Error::Io(e)
+                if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) =>
+            {
+                Ok(None)
+            }
+            tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => Err(ProtocolError::Closed),
+            e => Err(ws_error(e)),
+        }
+    }
+
+    fn keepalive(&mut self) -> Result<(), ProtocolError> {
+        if self.last_activity.elapsed() >= KEEPALIVE_INTERVAL {
+            self.socket.send(Message::Ping(Default::default())).map_err(ws_error)?;
+            self.last_activity = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+impl Connection for WebSocketConnection {
+    fn send(&mut self, message: &Value) -> Result<(), ProtocolError> {
+        let method = message.get("method").and_then(|m| m.as_str());
+        if method == Some("initialize") {
+            self.handshake.clear();
+        }
+        // Whether reconnect() will resend this message itself
+        let replayed = match method {
+            Some("initialize") => false,
+            Some("notifications/initialized") => {
+                self.handshake.push(message.clone());
+                true
+            }
+            Some(_) if message.get("id").is_some() => {
+                self.in_flight.push(message.clone());
+                true
+            }
+            _ => false,
+        };
+        if self.write(message).is_err() {
+            self.reconnect()?;
+            if !replayed {
+                self.write(message)?;
+            }
+        }
+        if method == Some("initialize") {
+            self.handshake = vec![message.clone()];
+        }
+        Ok(())
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Value>, ProtocolError> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(Some(msg));
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            set_read_timeout(&self.socket, remaining.min(POLL_INTERVAL));
+            let result = self.keepalive().and_then(|()| self.read_once());
+            match result {
+                Ok(Some(msg)) => return Ok(Some(msg)),
+                Ok(None) => {}
+                Err(_) => {
+                    self.reconnect()?;
+                    if let Some(msg) = self.pending.pop_front() {
+                        return Ok(Some(msg));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for WebSocketConnection {
+    fn drop(&mut self) {
+        let _ = self.socket.close(None);
+        let _ = self.socket.flush();
+    }
+}
+
+fn open_socket(url: &str, headers: &[(String, String)]) -> Result<Socket, ProtocolError> {
+    let mut request = url
+        .into_client_request()
+        .map_err(|_| ProtocolError::InvalidUrl(url.to_string()))?;
+    let h = request.headers_mut();
+    h.insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL));
+    for (k, v) in headers {
+        let name = HeaderName::from_bytes(k.as_bytes()).map_err(|_| ProtocolError::InvalidHeader(k.clone()))?;
+        let value = HeaderValue::from_str(v).map_err(|_| ProtocolError::InvalidHeader(k.clone()))?;
+        h.insert(name, value);
+    }
+
+    let (socket, response) = tungstenite::connect(request).map_err(ws_error)?;
+    // Reads must never block for good, or a silent server would hang the handshake replay
+    set_read_timeout(&socket, POLL_INTERVAL);
+    // Servers that ignore subprotocols are accepted; a different one is not
+    if let Some(proto) = response.headers().get("Sec-WebSocket-Protocol") {
+        if proto.as_bytes() != SUBPROTOCOL.as_bytes() {
+            return Err(ProtocolError::InvalidResponse(format!(
+                "server selected subprotocol {}",
+                String::from_utf8_lossy(proto.as_bytes())
+            )));
+        }
+    }
+    Ok(socket)
+}
+
+fn set_read_timeout(socket: &Socket, timeout: Duration) {
+    let _ = match socket.get_ref() {
+        MaybeTlsStream::Plain(s) => s.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::Rustls(s) => s.get_ref().set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    };
+}
+
+fn ws_error(e: tungstenite::Error) -> ProtocolError {
+    match e {
+        tungstenite::Error::Io(io) => ProtocolError::Io(io),
+        tungstenite::Error::Http(resp) => ProtocolError::HttpStatus(resp.status().as_u16()),
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => ProtocolError::Closed,
+        other => ProtocolError::WebSocket(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+    fn request(id: u64, method: &str) -> Value {
+        serde_json::json!({"jsonrpc": "2.0", "id": id, "method": method})
+    }
+
+    // The signature is fixed by tungstenite's `Callback`
+    #[allow(clippy::result_large_err)]
+    fn select_subprotocol(_: &Request, mut resp: Response) -> Result<Response, ErrorResponse> {
+        resp.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL));
+        Ok(resp)
+    }
+
+    /// WebSocket stand-in. Every request is reported on the channel with the
+    /// connection number. The first connection leaves `tools/list` unanswered and
+    /// drops on `tools/call`; later ones answer everything.
+    fn serve() -> (String, mpsc::Receiver<(usize, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/mcp", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for (n, stream) in listener.incoming().enumerate() {
+                let mut ws = tungstenite::accept_hdr(stream.unwrap(), select_subprotocol).unwrap();
+                while let Ok(Message::Text(text)) = ws.read() {
+                    let msg: Value = serde_json::from_str(text.as_str()).unwrap();
+                    let _ = tx.send((n, msg.clone()));
+                    let method = msg["method"].as_str().unwrap_or_default().to_string();
+                    if n == 0 && method == "tools/call" {
+                        let _ = ws.close(None);
+                        let _ = ws.flush();
+                        break;
+                    }
+                    if msg.get("id").is_none() || (n == 0 && method == "tools/list") {
+                        continue;
+                    }
+                    let reply = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {"method": method}});
+                    ws.send(Message::text(reply.to_string())).unwrap();
+                }
+            }
+        });
+        (url, rx)
+    }
+
+    fn recv(conn: &mut WebSocketConnection) -> Value {
+        conn.recv_timeout(Duration::from_secs(10)).unwrap().expect("no reply")
+    }
+
+    #[test]
+    fn round_trip() {
+        let (url, _) = serve();
+        let mut conn = WebSocketConnection::open(&url, &[]).unwrap();
+        conn.send(&request(1, "initialize")).unwrap();
+        let reply = recv(&mut conn);
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["result"]["method"], "initialize");
+    }
+
+    #[test]
+    fn reconnect_to_a_silent_server_times_out() {
+        // The first connection answers `initialize` and drops on anything else;
+        // the second accepts and then never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/mcp", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let mut sockets = Vec::new();
+            for (n, stream) in listener.incoming().enumerate() {
+                let mut ws = tungstenite::accept_hdr(stream.unwrap(), select_subprotocol).unwrap();
+                if n == 0 {
+                    let Ok(Message::Text(text)) = ws.read() else { continue };
+                    let msg: Value = serde_json::from_str(text.as_str()).unwrap();
+                    let reply = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}});
+                    ws.send(Message::text(reply.to_string())).unwrap();
+                    let _ = ws.read();
+                    let _ = ws.close(None);
+                    let _ = ws.flush();
+                }
+                sockets.push(ws);
+            }
+        });
+
+        let (done, result) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut conn = WebSocketConnection::open(&url, &[]).unwrap();
+            conn.handshake_timeout = Duration::from_secs(1);
+            conn.send(&request(1, "initialize")).unwrap();
+            assert_eq!(recv(&mut conn)["id"], 1);
+            conn.send(&request(2, "tools/list")).unwrap();
+            let _ = done.send(conn.recv_timeout(Duration::from_secs(5)));
+        });
+        match result.recv_timeout(Duration::from_secs(10)) {
+            Ok(Err(ProtocolError::Timeout)) => {}
+            Ok(other) => panic!("expected a handshake timeout, got {:?}", other),
+            Err(_) => panic!("reconnect blocked on a silent server"),
+        }
+    }
+
+    #[test]
+    fn reconnect_replays_reads_but_fails_tool_calls() {
+        let (url, seen) = serve();
+        let mut conn = WebSocketConnection::open(&url, &[]).unwrap();
+        conn.send(&request(1, "initialize")).unwrap();
+        assert_eq!(recv(&mut conn)["id"], 1);
+        conn.send(&serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"})).unwrap();
+        conn.send(&request(2, "tools/list")).unwrap();
+        conn.send(&request(3, "tools/call")).unwrap();
+
+        let mut replies = [recv(&mut conn), recv(&mut conn)];
+        replies.sort_by_key(|r| r["id"].as_u64());
+        assert_eq!(replies[0]["result"]["method"], "tools/list");
+        assert_eq!(replies[1]["error"]["code"], CONNECTION_LOST);
+
+        let second: Vec<String> = seen
+            .try_iter()
+            .filter(|(n, _)| *n == 1)
+            .map(|(_, m)| m["method"].as_str().unwrap_or_default().to_string())
+            .collect();
+        assert_eq!(second, ["initialize", "notifications/initialized", "tools/list"]);
+    }
+}